@@ -12,6 +12,63 @@ pub enum Commands {
     /// Create a superuser (admin) account
     CreateSuperuser(CreateSuperuserArgs),
 
+    /// Set the role of a user: viewer, operator, approver or admin
+    SetRole(SetRoleArgs),
+
+    /// Create a user with the given role (viewer by default)
+    CreateUser(CreateUserArgs),
+
+    /// List all users with their role and status
+    ListUsers,
+
+    /// Deactivate a user so they can't log in anymore
+    DeactivateUser(UsernameArgs),
+
+    /// Activate a deactivated user
+    ActivateUser(UsernameArgs),
+
+    /// Delete a user
+    DeleteUser(UsernameArgs),
+
+    /// Set a new password for a user
+    ResetPassword(ResetPasswordArgs),
+
+    /// Change the email address of a user
+    ChangeEmail(ChangeEmailArgs),
+
+    /// Enroll a user in TOTP two-factor authentication and print the secret and recovery codes
+    EnrollTotp(UsernameArgs),
+
+    /// Remove the TOTP second factor of a user, e.g. after losing their authenticator
+    ResetTotp(UsernameArgs),
+
+    /// Create an API key for a service account; the key is only printed once
+    CreateApiKey(CreateApiKeyArgs),
+
+    /// List API keys
+    ListApiKeys,
+
+    /// Revoke an API key
+    RevokeApiKey(RevokeApiKeyArgs),
+
+    /// Generate a new JWT signing key file
+    GenerateJwtKeys(GenerateJwtKeysArgs),
+
+    /// Rotate the JWT signing key, keeping the previous public keys for verification
+    RotateJwtKeys(RotateJwtKeysArgs),
+
+    /// Queue a manual token transfer to a buyer; the running service sends it
+    TransferTokens(TransferTokensArgs),
+
+    /// Add a signed correction to the received balance of a buyer
+    AdjustBalance(AdjustBalanceArgs),
+
+    /// Recompute the balances of buyers and the totals of groups from the ledger
+    RebuildBalances,
+
+    /// Check the consistency of buyers, schedules, transactions and the ledger
+    CheckInvariants,
+
     /// Create a new Solana wallet (for testing only)
     ///
     /// This command generates a new Solana wallet and saves the keypair to a file.
@@ -53,6 +110,177 @@ pub struct CreateSuperuserArgs {
     pub password: String,
 }
 
+#[derive(ClapArgs, Debug)]
+pub struct SetRoleArgs {
+    /// Username of the user
+    #[arg(short, long, help = "Username of the user")]
+    pub username: String,
+
+    /// New role of the user
+    #[arg(short, long, help = "Role: viewer, operator, approver or admin")]
+    pub role: String,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct CreateUserArgs {
+    /// Username for the user
+    #[arg(short, long, help = "Username for the user")]
+    pub username: String,
+
+    /// Email address for the user
+    #[arg(short, long, help = "Email address for the user")]
+    pub email: String,
+
+    /// Password for the user
+    #[arg(short, long, help = "Password for the user")]
+    pub password: String,
+
+    /// Role of the user
+    #[arg(
+        short,
+        long,
+        default_value = "viewer",
+        help = "Role: viewer, operator, approver or admin"
+    )]
+    pub role: String,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct UsernameArgs {
+    /// Username of the user
+    #[arg(short, long, help = "Username of the user")]
+    pub username: String,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct ResetPasswordArgs {
+    /// Username of the user
+    #[arg(short, long, help = "Username of the user")]
+    pub username: String,
+
+    /// New password of the user
+    #[arg(short, long, help = "New password of the user")]
+    pub password: String,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct ChangeEmailArgs {
+    /// Username of the user
+    #[arg(short, long, help = "Username of the user")]
+    pub username: String,
+
+    /// New email address of the user
+    #[arg(short, long, help = "New email address of the user")]
+    pub email: String,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct CreateApiKeyArgs {
+    /// Name of the service using the key
+    #[arg(short, long, help = "Name of the service using the key")]
+    pub name: String,
+
+    /// Role the key acts with
+    #[arg(
+        short,
+        long,
+        default_value = "viewer",
+        help = "Role: viewer or operator"
+    )]
+    pub role: String,
+
+    /// Username of the owner the key acts for
+    #[arg(short, long, help = "Username of the owner the key acts for")]
+    pub owner: String,
+
+    /// Days until the key expires
+    #[arg(short, long, help = "Days until the key expires, never if not set")]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct RevokeApiKeyArgs {
+    /// ID of the API key
+    #[arg(short, long, help = "ID of the API key")]
+    pub id: i64,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct GenerateJwtKeysArgs {
+    /// Path of the key file to create
+    #[arg(short, long, help = "Path of the key file to create")]
+    pub out: String,
+
+    /// Overwrite an existing key file
+    #[arg(short, long, help = "Overwrite an existing key file")]
+    pub force: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct RotateJwtKeysArgs {
+    /// Path of the key file to rotate
+    #[arg(short, long, help = "Path of the key file to rotate")]
+    pub file: String,
+
+    /// Number of previous keys that still verify tokens
+    #[arg(
+        short,
+        long,
+        default_value_t = 2,
+        help = "Number of previous keys that still verify tokens"
+    )]
+    pub keep: usize,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct TransferTokensArgs {
+    /// Wallet of the buyer
+    #[arg(short, long, help = "Wallet of the buyer")]
+    pub wallet: String,
+
+    /// SPL lamports to send
+    #[arg(short, long, help = "SPL lamports to send")]
+    pub amount: u64,
+
+    /// Why the transfer is made
+    #[arg(short, long, help = "Why the transfer is made")]
+    pub reason: String,
+
+    /// User who requests the transfer if the approval policy holds it
+    #[arg(
+        long,
+        help = "User who requests the transfer if the approval policy holds it"
+    )]
+    pub requested_by: Option<String>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct AdjustBalanceArgs {
+    /// Wallet of the buyer
+    #[arg(short, long, help = "Wallet of the buyer")]
+    pub wallet: String,
+
+    /// SPL lamports added to the received balance, negative to lower it
+    #[arg(
+        short,
+        long,
+        allow_negative_numbers = true,
+        help = "SPL lamports added to the received balance, negative to lower it"
+    )]
+    pub amount: i64,
+
+    /// Why the balance is corrected
+    #[arg(short, long, help = "Why the balance is corrected")]
+    pub reason: String,
+
+    /// User who requests the adjustment if the approval policy holds it
+    #[arg(
+        long,
+        help = "User who requests the adjustment if the approval policy holds it"
+    )]
+    pub requested_by: Option<String>,
+}
+
 #[derive(ClapArgs, Debug)]
 pub struct CreateMintArgs {
     /// Base58-encoded wallet keypair (for testing only)
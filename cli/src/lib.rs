@@ -1,8 +1,16 @@
 mod args;
 
-pub use args::{Args, Commands, CreateSuperuserArgs};
+pub use args::{
+    AdjustBalanceArgs, Args, ChangeEmailArgs, Commands, CreateApiKeyArgs, CreateSuperuserArgs,
+    CreateUserArgs, GenerateJwtKeysArgs, ResetPasswordArgs, RevokeApiKeyArgs, RotateJwtKeysArgs,
+    SetRoleArgs, TransferTokensArgs, UsernameArgs,
+};
 use clap::Parser;
-use common::{Buyer, Database, SplToken, User};
+use common::{
+    Adjustment, ApiKey, Approval, ApprovalAction, ApprovalPolicy, AuditEntry, Buyer, Database,
+    JwtKeys, Role, SplToken, Totp, User, generate_recovery_codes, hash_recovery_code,
+};
+use serde_json::json;
 
 /// Runs the CLI command parser and executes the selected command.
 /// Returns true if a CLI command was handled, false otherwise.
@@ -10,32 +18,341 @@ pub async fn run_cli() -> bool {
     let args = Args::parse();
     match &args.command {
         Some(Commands::CreateSuperuser(superuser_args)) => {
-            if let Err(e) = create_superuser(
+            let result = create_superuser(
                 &superuser_args.username,
                 &superuser_args.email,
                 &superuser_args.password,
             )
             .await
-            {
-                eprintln!("Failed to create superuser: {e}");
+            .map_err(|e| format!("Failed to create superuser: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "create-superuser",
+                json!({ "username": superuser_args.username, "email": superuser_args.email }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::SetRole(set_role_args)) => {
+            let result = set_role(&set_role_args.username, &set_role_args.role)
+                .await
+                .map_err(|e| format!("Failed to set role: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "set-role",
+                json!({ "username": set_role_args.username, "role": set_role_args.role }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::CreateUser(create_user_args)) => {
+            let result = create_user(
+                &create_user_args.username,
+                &create_user_args.email,
+                &create_user_args.password,
+                &create_user_args.role,
+            )
+            .await
+            .map_err(|e| format!("Failed to create user: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "create-user",
+                json!({
+                    "username": create_user_args.username,
+                    "email": create_user_args.email,
+                    "role": create_user_args.role,
+                }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::ListUsers) => {
+            if let Err(e) = list_users().await {
+                eprintln!("Failed to list users: {e}");
+            }
+            true
+        }
+        Some(Commands::DeactivateUser(username_args)) => {
+            let result = set_active(&username_args.username, false)
+                .await
+                .map_err(|e| format!("Failed to deactivate user: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "deactivate-user",
+                json!({ "username": username_args.username }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::ActivateUser(username_args)) => {
+            let result = set_active(&username_args.username, true)
+                .await
+                .map_err(|e| format!("Failed to activate user: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "activate-user",
+                json!({ "username": username_args.username }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::DeleteUser(username_args)) => {
+            let result = delete_user(&username_args.username)
+                .await
+                .map_err(|e| format!("Failed to delete user: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "delete-user",
+                json!({ "username": username_args.username }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::ResetPassword(reset_password_args)) => {
+            let result =
+                reset_password(&reset_password_args.username, &reset_password_args.password)
+                    .await
+                    .map_err(|e| format!("Failed to reset password: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "reset-password",
+                json!({ "username": reset_password_args.username }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::ChangeEmail(change_email_args)) => {
+            let result = change_email(&change_email_args.username, &change_email_args.email)
+                .await
+                .map_err(|e| format!("Failed to change email: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "change-email",
+                json!({ "username": change_email_args.username, "email": change_email_args.email }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::EnrollTotp(username_args)) => {
+            let result = enroll_totp(&username_args.username)
+                .await
+                .map_err(|e| format!("Failed to enroll TOTP: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "enroll-totp",
+                json!({ "username": username_args.username }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::ResetTotp(username_args)) => {
+            let result = reset_totp(&username_args.username)
+                .await
+                .map_err(|e| format!("Failed to reset TOTP: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "reset-totp",
+                json!({ "username": username_args.username }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::CreateApiKey(create_api_key_args)) => {
+            let result = create_api_key(
+                &create_api_key_args.name,
+                &create_api_key_args.role,
+                &create_api_key_args.owner,
+                create_api_key_args.expires_in_days,
+            )
+            .await
+            .map_err(|e| format!("Failed to create API key: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "create-api-key",
+                json!({
+                    "name": create_api_key_args.name,
+                    "role": create_api_key_args.role,
+                    "owner": create_api_key_args.owner,
+                    "expires_in_days": create_api_key_args.expires_in_days,
+                }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::ListApiKeys) => {
+            if let Err(e) = list_api_keys().await {
+                eprintln!("Failed to list API keys: {e}");
+            }
+            true
+        }
+        Some(Commands::RevokeApiKey(revoke_api_key_args)) => {
+            let result = revoke_api_key(revoke_api_key_args.id)
+                .await
+                .map_err(|e| format!("Failed to revoke API key: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "revoke-api-key",
+                json!({ "id": revoke_api_key_args.id }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::GenerateJwtKeys(generate_keys_args)) => {
+            let result = generate_jwt_keys(&generate_keys_args.out, generate_keys_args.force)
+                .map_err(|e| format!("Failed to generate JWT keys: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "generate-jwt-keys",
+                json!({ "out": generate_keys_args.out, "force": generate_keys_args.force }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::RotateJwtKeys(rotate_keys_args)) => {
+            let result = rotate_jwt_keys(&rotate_keys_args.file, rotate_keys_args.keep)
+                .map_err(|e| format!("Failed to rotate JWT keys: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
             }
+            audit_command(
+                "rotate-jwt-keys",
+                json!({ "file": rotate_keys_args.file, "keep": rotate_keys_args.keep }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::TransferTokens(transfer_args)) => {
+            let result = transfer_tokens(
+                &transfer_args.wallet,
+                transfer_args.amount,
+                &transfer_args.reason,
+                transfer_args.requested_by.as_deref(),
+            )
+            .await
+            .map_err(|e| format!("Failed to queue transfer: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "transfer-tokens",
+                json!({
+                    "wallet": transfer_args.wallet,
+                    "amount_lamports": transfer_args.amount,
+                    "reason": transfer_args.reason,
+                    "requested_by": transfer_args.requested_by,
+                }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::AdjustBalance(adjust_args)) => {
+            let result = adjust_balance(
+                &adjust_args.wallet,
+                adjust_args.amount,
+                &adjust_args.reason,
+                adjust_args.requested_by.as_deref(),
+            )
+            .await
+            .map_err(|e| format!("Failed to adjust balance: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command(
+                "adjust-balance",
+                json!({
+                    "wallet": adjust_args.wallet,
+                    "amount_lamports": adjust_args.amount,
+                    "reason": adjust_args.reason,
+                    "requested_by": adjust_args.requested_by,
+                }),
+                &result,
+            )
+            .await;
+            true
+        }
+        Some(Commands::RebuildBalances) => {
+            let result = rebuild_balances()
+                .await
+                .map_err(|e| format!("Failed to rebuild balances: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command("rebuild-balances", json!({}), &result).await;
+            true
+        }
+        Some(Commands::CheckInvariants) => {
+            let result = check_invariants()
+                .await
+                .map_err(|e| format!("Failed to check invariants: {e}"));
+            if let Err(e) = &result {
+                eprintln!("{e}");
+            }
+            audit_command("check-invariants", json!({}), &result).await;
             true
         }
         Some(Commands::CreateWallet) => {
-            match get_client_url() {
+            let result = match get_client_url() {
                 Ok(client_url) => match generate_main_wallet(&client_url).await {
-                    Ok((wallet_pubkey, wallet_str)) => println!(
-                        "Wallet successfully generated!\n Pubkey:{} Base58 Keypair: {}",
-                        wallet_pubkey, wallet_str
-                    ),
-                    Err(e) => eprintln!("Failed to generate wallet: {e}"),
+                    Ok((wallet_pubkey, wallet_str)) => {
+                        println!(
+                            "Wallet successfully generated!\n Pubkey:{} Base58 Keypair: {}",
+                            wallet_pubkey, wallet_str
+                        );
+                        Ok(())
+                    }
+                    Err(e) => Err(format!("Failed to generate wallet: {e}")),
                 },
-                Err(e) => eprintln!("{e}"),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = &result {
+                eprintln!("{e}");
             }
+            audit_command("create-wallet", json!({}), &result).await;
             true
         }
         Some(Commands::CreateMint(create_mint_args)) => {
-            match get_client_url() {
+            let result = match get_client_url() {
                 Ok(client_url) => {
                     match generate_mint(
                         &client_url,
@@ -44,19 +361,32 @@ pub async fn run_cli() -> bool {
                     )
                     .await
                     {
-                        Ok(mint_str) => println!(
-                            "Mint token successfully generated! Base58 Pubkey: {}",
-                            mint_str
-                        ),
-                        Err(e) => eprintln!("Failed to generate mint token: {e}"),
+                        Ok(mint_str) => {
+                            println!(
+                                "Mint token successfully generated! Base58 Pubkey: {}",
+                                mint_str
+                            );
+                            Ok(())
+                        }
+                        Err(e) => Err(format!("Failed to generate mint token: {e}")),
                     }
                 }
-                Err(e) => eprintln!("{e}"),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = &result {
+                eprintln!("{e}");
             }
+            // The wallet keypair is secret and never written to the audit log
+            audit_command(
+                "create-mint",
+                json!({ "decimals": create_mint_args.decimals }),
+                &result,
+            )
+            .await;
             true
         }
         Some(Commands::MintTokens(mint_tokens_args)) => {
-            match get_client_url() {
+            let result = match get_client_url() {
                 Ok(client_url) => match mint_tokens(
                     &client_url,
                     &mint_tokens_args.wallet,
@@ -65,27 +395,55 @@ pub async fn run_cli() -> bool {
                 )
                 .await
                 {
-                    Ok(_) => println!("Successfully minted {} tokens!", mint_tokens_args.amount),
-                    Err(e) => eprintln!("Failed to mint tokens: {e}"),
+                    Ok(_) => {
+                        println!("Successfully minted {} tokens!", mint_tokens_args.amount);
+                        Ok(())
+                    }
+                    Err(e) => Err(format!("Failed to mint tokens: {e}")),
                 },
-                Err(e) => eprintln!("{e}"),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = &result {
+                eprintln!("{e}");
             }
+            audit_command(
+                "mint-tokens",
+                json!({ "mint": mint_tokens_args.mint, "amount": mint_tokens_args.amount }),
+                &result,
+            )
+            .await;
             true
         }
         Some(Commands::GenerateBuyers(generate_buyers_args)) => {
-            match Buyer::generate_test_buyers_csv_async(
+            let result = match Buyer::generate_test_buyers_csv_async(
                 &generate_buyers_args.out,
                 generate_buyers_args.count,
                 generate_buyers_args.group_count,
             )
             .await
             {
-                Ok(_) => println!(
-                    "Successfully generated buyers to: {}",
-                    generate_buyers_args.out
-                ),
-                Err(e) => eprintln!("Failed to generate buyers: {e}"),
+                Ok(_) => {
+                    println!(
+                        "Successfully generated buyers to: {}",
+                        generate_buyers_args.out
+                    );
+                    Ok(())
+                }
+                Err(e) => Err(format!("Failed to generate buyers: {e}")),
+            };
+            if let Err(e) = &result {
+                eprintln!("{e}");
             }
+            audit_command(
+                "generate-buyers",
+                json!({
+                    "count": generate_buyers_args.count,
+                    "group_count": generate_buyers_args.group_count,
+                    "out": generate_buyers_args.out,
+                }),
+                &result,
+            )
+            .await;
             true
         }
         None => {
@@ -95,16 +453,36 @@ pub async fn run_cli() -> bool {
     }
 }
 
+/// Writes the command to the audit log as the OS user that ran it.
+/// The command has already run, so an unreachable database only prints a warning.
+async fn audit_command(action: &str, parameters: serde_json::Value, result: &Result<(), String>) {
+    let actor = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok();
+    let mut entry = AuditEntry::new("cli", actor, action, parameters);
+    if let Err(e) = result {
+        entry = entry.failed(e.clone());
+    }
+
+    let saved = match std::env::var("DATABASE_URL") {
+        Ok(database_url) => match Database::new(&database_url).await {
+            Ok(db) => db.save_audit_entry(&entry).await.map(|_| ()),
+            Err(e) => Err(e),
+        },
+        Err(_) => Err(anyhow::anyhow!("DATABASE_URL not set")),
+    };
+    if let Err(e) = saved {
+        eprintln!("Warning: failed to write `{action}` to the audit log: {e}");
+    }
+}
+
 /// Creates a superuser: validates input, hashes password, checks for duplicates, and saves to DB.
 async fn create_superuser(username: &str, email: &str, password: &str) -> anyhow::Result<()> {
     // Validate and hash
     let user = User::new(username, email, password, true)
         .map_err(|e| anyhow::anyhow!("Validation error: {e}"))?;
 
-    // Connect to DB (adjust as needed for your project)
-    let database_url =
-        std::env::var("DATABASE_URL").map_err(|_| anyhow::anyhow!("DATABASE_URL not set"))?;
-    let db = Database::new(&database_url).await?;
+    let db = connect_database().await?;
 
     // Check if user already exists
     if db.get_user(username).await?.is_some() {
@@ -123,6 +501,451 @@ async fn create_superuser(username: &str, email: &str, password: &str) -> anyhow
     Ok(())
 }
 
+/// Changes the role of an existing user. Their sessions end, so they log in with the new role.
+async fn set_role(username: &str, role: &str) -> anyhow::Result<()> {
+    let role = parse_role(role)?;
+
+    let db = connect_database().await?;
+    let user = existing_user(&db, username).await?;
+    db.update_user_role(username, role).await?;
+    db.revoke_user_sessions(user.id, None).await?;
+
+    println!("Role of '{}' set to '{}'.", username, role.as_str());
+    Ok(())
+}
+
+/// Creates a user with the given role.
+async fn create_user(
+    username: &str,
+    email: &str,
+    password: &str,
+    role: &str,
+) -> anyhow::Result<()> {
+    let role = parse_role(role)?;
+    let user = User::with_role(username, email, password, role)
+        .map_err(|e| anyhow::anyhow!("Validation error: {e}"))?;
+
+    let db = connect_database().await?;
+    if db.get_user(username).await?.is_some() {
+        return Err(anyhow::anyhow!(
+            "A user with username '{}' already exists.",
+            username
+        ));
+    }
+    db.save_user(&user)
+        .await
+        .map_err(|e| anyhow::anyhow!("Database error: {e}"))?;
+
+    println!("User '{}' created with role '{}'.", username, role.as_str());
+    Ok(())
+}
+
+/// Prints all users as a table.
+async fn list_users() -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let users = db.get_all_users().await?;
+
+    println!(
+        "{:<24} {:<32} {:<10} {:<8} CREATED",
+        "USERNAME", "EMAIL", "ROLE", "ACTIVE"
+    );
+    for user in users {
+        println!(
+            "{:<24} {:<32} {:<10} {:<8} {}",
+            user.username,
+            user.email,
+            user.role().as_str(),
+            if user.active { "yes" } else { "no" },
+            user.created_at
+                .map(|t| t.to_string())
+                .unwrap_or_else(|| "-".to_string())
+        );
+    }
+    Ok(())
+}
+
+/// Activates or deactivates a user. Deactivating also ends their sessions.
+async fn set_active(username: &str, active: bool) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let user = existing_user(&db, username).await?;
+    db.set_user_active(username, active).await?;
+    if !active {
+        db.revoke_user_sessions(user.id, None).await?;
+    }
+
+    let state = if active { "activated" } else { "deactivated" };
+    println!("User '{}' {}.", username, state);
+    Ok(())
+}
+
+async fn delete_user(username: &str) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    existing_user(&db, username).await?;
+    db.delete_user(username).await?;
+
+    println!("User '{}' deleted.", username);
+    Ok(())
+}
+
+/// Validates and hashes the new password of a user.
+async fn reset_password(username: &str, password: &str) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let mut user = existing_user(&db, username).await?;
+    user.set_password(password)
+        .map_err(|e| anyhow::anyhow!("Validation error: {e}"))?;
+    db.update_user_password(username, &user.password_hash)
+        .await?;
+    // Logins with the old password end
+    db.revoke_user_sessions(user.id, None).await?;
+
+    println!("Password of '{}' reset.", username);
+    Ok(())
+}
+
+async fn change_email(username: &str, email: &str) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let mut user = existing_user(&db, username).await?;
+    user.set_email(email)
+        .map_err(|e| anyhow::anyhow!("Validation error: {e}"))?;
+    db.update_user_email(username, &user.email).await?;
+
+    println!("Email of '{}' set to '{}'.", username, email);
+    Ok(())
+}
+
+/// Enables TOTP for a user with a new secret and prints it with the recovery codes,
+/// to be handed to the user.
+async fn enroll_totp(username: &str) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let user = existing_user(&db, username).await?;
+    if db.get_user_totp(user.id).await?.enabled {
+        return Err(anyhow::anyhow!(
+            "'{}' already has a second factor. Remove it first with reset-totp.",
+            username
+        ));
+    }
+
+    let totp = Totp::generate();
+    let recovery_codes = generate_recovery_codes();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|c| hash_recovery_code(c))
+        .collect();
+    db.set_user_totp(user.id, Some(totp.secret()), false)
+        .await?;
+    db.replace_recovery_codes(user.id, &hashes).await?;
+
+    println!("TOTP enabled for '{}'.", username);
+    println!("Secret: {}", totp.secret());
+    println!("URI:    {}", totp.uri(username));
+    println!("Recovery codes, each can be used once instead of a code:");
+    for code in recovery_codes {
+        println!("  {}", code);
+    }
+    Ok(())
+}
+
+/// Removes the second factor and recovery codes of a user. Superusers enroll again at
+/// their next login.
+async fn reset_totp(username: &str) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let user = existing_user(&db, username).await?;
+    db.set_user_totp(user.id, None, false).await?;
+
+    println!("TOTP of '{}' reset.", username);
+    Ok(())
+}
+
+fn parse_role(role: &str) -> anyhow::Result<Role> {
+    Role::parse(role).ok_or_else(|| {
+        anyhow::anyhow!("Unknown role '{role}'. Use viewer, operator, approver or admin.")
+    })
+}
+
+async fn existing_user(db: &Database, username: &str) -> anyhow::Result<User> {
+    db.get_user(username)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("A user with username '{}' doesn't exist.", username))
+}
+
+/// Connects to the database from `DATABASE_URL`.
+async fn connect_database() -> anyhow::Result<Database> {
+    let database_url =
+        std::env::var("DATABASE_URL").map_err(|_| anyhow::anyhow!("DATABASE_URL not set"))?;
+    Database::new(&database_url).await
+}
+
+/// Creates an API key of `owner` and prints it; only its hash is stored.
+async fn create_api_key(
+    name: &str,
+    role: &str,
+    owner: &str,
+    expires_in_days: Option<i64>,
+) -> anyhow::Result<()> {
+    let role = parse_role(role)?;
+    let expires_at = match expires_in_days {
+        Some(days) if days <= 0 => {
+            return Err(anyhow::anyhow!("--expires-in-days must be positive."));
+        }
+        Some(days) => Some(chrono::Utc::now().naive_utc() + chrono::Duration::days(days)),
+        None => None,
+    };
+    let db = connect_database().await?;
+    let owner = existing_user(&db, owner).await?;
+    let (api_key, key) = ApiKey::generate(name, role, expires_at, &owner)
+        .map_err(|e| anyhow::anyhow!("Validation error: {e}"))?;
+
+    let id = db.save_api_key(&api_key).await?;
+
+    println!(
+        "API key {} '{}' created with role '{}'. Store it now, it can't be shown again:\n{}",
+        id,
+        api_key.name,
+        role.as_str(),
+        key
+    );
+    Ok(())
+}
+
+/// Prints all API keys as a table.
+async fn list_api_keys() -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let api_keys = db.get_all_api_keys().await?;
+
+    println!(
+        "{:<6} {:<24} {:<14} {:<10} {:<8} {:<20} LAST USED",
+        "ID", "NAME", "PREFIX", "ROLE", "VALID", "EXPIRES"
+    );
+    for api_key in api_keys {
+        let format_time = |t: Option<chrono::NaiveDateTime>| {
+            t.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| "-".to_string())
+        };
+        println!(
+            "{:<6} {:<24} {:<14} {:<10} {:<8} {:<20} {}",
+            api_key.id,
+            api_key.name,
+            api_key.prefix,
+            api_key.role().as_str(),
+            if api_key.is_valid() { "yes" } else { "no" },
+            format_time(api_key.expires_at),
+            format_time(api_key.last_used_at)
+        );
+    }
+    Ok(())
+}
+
+async fn revoke_api_key(id: i64) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    if db.get_api_key(id).await?.is_none() {
+        return Err(anyhow::anyhow!("An API key with id {} doesn't exist.", id));
+    }
+    if !db.revoke_api_key(id).await? {
+        return Err(anyhow::anyhow!("API key {} is already revoked.", id));
+    }
+
+    println!("API key {} revoked.", id);
+    Ok(())
+}
+
+/// Records a manual transfer as pending. The CLI has no wallet, the schedule runner of the
+/// running service sends it on its next tick.
+async fn transfer_tokens(
+    wallet: &str,
+    amount: u64,
+    reason: &str,
+    requested_by: Option<&str>,
+) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let buyer = existing_buyer(&db, wallet).await?;
+    let adjustment = Adjustment::transfer(
+        wallet,
+        buyer.group_id,
+        amount,
+        reason,
+        std::env::var("USER").ok(),
+    )?;
+    let parameters = json!({ "wallet": wallet, "amount_lamports": amount, "reason": reason });
+    if request_approval(
+        &db,
+        ApprovalAction::ManualTransfer,
+        amount,
+        parameters,
+        requested_by,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+    let id = db.save_adjustment(&adjustment).await?;
+
+    println!(
+        "Transfer {} of {} lamports to '{}' queued. The service sends it on the next runner tick.",
+        id, amount, wallet
+    );
+    Ok(())
+}
+
+/// Records a signed correction and adds it to the buyer's received balance.
+async fn adjust_balance(
+    wallet: &str,
+    amount: i64,
+    reason: &str,
+    requested_by: Option<&str>,
+) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let buyer = existing_buyer(&db, wallet).await?;
+    let adjustment = Adjustment::correction(
+        wallet,
+        buyer.group_id,
+        amount,
+        reason,
+        std::env::var("USER").ok(),
+    )?;
+    if amount < 0 && amount.unsigned_abs() > buyer.received_spl_lamports {
+        return Err(anyhow::anyhow!(
+            "Adjustment of {} would make the received balance {} negative.",
+            amount,
+            buyer.received_spl_lamports
+        ));
+    }
+    let parameters = json!({ "wallet": wallet, "amount_lamports": amount, "reason": reason });
+    if request_approval(
+        &db,
+        ApprovalAction::AdjustBalance,
+        amount.unsigned_abs(),
+        parameters,
+        requested_by,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let id = db.save_adjustment(&adjustment).await?;
+    if let Err(e) = db.apply_adjustment(id, None).await {
+        db.fail_adjustment(id, None, &e.to_string()).await?;
+        return Err(e);
+    }
+
+    println!(
+        "Balance of '{}' adjusted by {} lamports (adjustment {}).",
+        wallet, amount, id
+    );
+    Ok(())
+}
+
+/// Files `action` for approval as `requested_by` if the approval policy holds it, like the
+/// API does. Returns true if the operation waits for approval and must not run now.
+async fn request_approval(
+    db: &Database,
+    action: ApprovalAction,
+    amount_lamports: u64,
+    parameters: serde_json::Value,
+    requested_by: Option<&str>,
+) -> anyhow::Result<bool> {
+    if !ApprovalPolicy::from_env()?.requires_approval(action, amount_lamports) {
+        return Ok(false);
+    }
+    let Some(requested_by) = requested_by else {
+        return Err(anyhow::anyhow!(
+            "`{}` of {} lamports needs approval. Pass --requested-by with the user who requests it.",
+            action,
+            amount_lamports
+        ));
+    };
+    // The approved request runs as this user, so they need the permission for it
+    let user = existing_user(db, requested_by).await?;
+    if !user.active || !user.can(action.permission()) {
+        return Err(anyhow::anyhow!(
+            "User '{}' is not allowed to request `{}`.",
+            user.username,
+            action
+        ));
+    }
+
+    let approval = Approval::new(action, parameters, amount_lamports, &user);
+    let id = db.save_approval(&approval).await?;
+    println!(
+        "`{}` of {} lamports by '{}' waits for approval {}.",
+        action, amount_lamports, user.username, id
+    );
+    Ok(true)
+}
+
+/// Overwrites the cached balance columns with the ledger balances.
+async fn rebuild_balances() -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let (buyers, groups) = db.rebuild_balances().await?;
+
+    println!(
+        "Balances rebuilt from the ledger: {} buyers and {} groups corrected.",
+        buyers, groups
+    );
+    Ok(())
+}
+
+/// Prints the invariant violations by wallet and by group.
+async fn check_invariants() -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    let report = db.check_invariants().await?;
+
+    for (wallet, violations) in &report.wallets {
+        println!("Buyer {}:", wallet);
+        for violation in violations {
+            println!("  [{}] {}", violation.invariant, violation.message);
+        }
+    }
+    for (group_id, violations) in &report.groups {
+        println!("Group {}:", group_id);
+        for violation in violations {
+            println!("  [{}] {}", violation.invariant, violation.message);
+        }
+    }
+    println!(
+        "Checked {} buyers and {} groups: {} violations.",
+        report.buyers_checked, report.groups_checked, report.violations
+    );
+    Ok(())
+}
+
+async fn existing_buyer(db: &Database, wallet: &str) -> anyhow::Result<Buyer> {
+    db.get_buyer_by_wallet(wallet)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("A buyer with wallet '{}' doesn't exist.", wallet))
+}
+
+/// Writes a new JWT key file.
+fn generate_jwt_keys(out: &str, force: bool) -> anyhow::Result<()> {
+    if !force && std::path::Path::new(out).exists() {
+        return Err(anyhow::anyhow!(
+            "'{}' already exists. Use rotate-jwt-keys or --force.",
+            out
+        ));
+    }
+    JwtKeys::generate().save(out)?;
+
+    println!(
+        "JWT keys written to '{}'. Set JWT_KEYS_FILE={} to use them.",
+        out, out
+    );
+    Ok(())
+}
+
+/// Replaces the signing key in a key file. Running servers pick it up after a restart.
+fn rotate_jwt_keys(file: &str, keep: usize) -> anyhow::Result<()> {
+    let mut keys = JwtKeys::load(file)?;
+    keys.rotate(keep)?;
+    keys.save(file)?;
+
+    println!(
+        "JWT signing key in '{}' rotated, {} previous key(s) kept. Restart the servers to use it.",
+        file,
+        keys.verifying_keys.len()
+    );
+    Ok(())
+}
+
 /// Helper to fetch CLIENT_URL from environment.
 fn get_client_url() -> Result<String, String> {
     std::env::var("CLIENT_URL")
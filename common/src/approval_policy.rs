@@ -0,0 +1,102 @@
+use crate::ApprovalAction;
+use anyhow::Context;
+
+/// Which operations need a second user's approval, read from the environment.
+#[derive(Debug, Clone)]
+pub struct ApprovalPolicy {
+    /// Operations moving or revoking more SPL lamports than this need approval.
+    pub threshold_lamports: Option<u64>,
+    /// Operations that always need approval.
+    pub actions: Vec<ApprovalAction>,
+}
+
+impl ApprovalPolicy {
+    /// `APPROVAL_THRESHOLD_LAMPORTS` and `APPROVAL_ACTIONS`, a comma separated list like
+    /// `update_group,delete_group`. Nothing needs approval if neither is set.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let threshold_lamports = match std::env::var("APPROVAL_THRESHOLD_LAMPORTS") {
+            Ok(value) => Some(
+                value
+                    .trim()
+                    .parse::<u64>()
+                    .context("APPROVAL_THRESHOLD_LAMPORTS must be a number")?,
+            ),
+            Err(_) => None,
+        };
+
+        let actions = match std::env::var("APPROVAL_ACTIONS") {
+            Ok(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|a| !a.is_empty())
+                .map(|a| {
+                    ApprovalAction::parse(a).with_context(|| {
+                        format!(
+                            "Unknown action `{}` in APPROVAL_ACTIONS. Use {}",
+                            a,
+                            ApprovalAction::ALL.map(|a| a.as_str()).join(", ")
+                        )
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            Err(_) => Vec::new(),
+        };
+
+        Ok(ApprovalPolicy {
+            threshold_lamports,
+            actions,
+        })
+    }
+
+    /// Whether `action` can need approval at all, to skip working out its amount.
+    pub fn applies_to(&self, action: ApprovalAction) -> bool {
+        self.threshold_lamports.is_some() || self.actions.contains(&action)
+    }
+
+    pub fn requires_approval(&self, action: ApprovalAction, amount_lamports: u64) -> bool {
+        self.actions.contains(&action)
+            || self
+                .threshold_lamports
+                .is_some_and(|threshold| amount_lamports > threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_needs_approval_without_a_policy() {
+        let policy = ApprovalPolicy {
+            threshold_lamports: None,
+            actions: Vec::new(),
+        };
+        for action in ApprovalAction::ALL {
+            assert!(!policy.applies_to(action));
+            assert!(!policy.requires_approval(action, u64::MAX));
+        }
+    }
+
+    #[test]
+    fn listed_actions_always_need_approval() {
+        let policy = ApprovalPolicy {
+            threshold_lamports: None,
+            actions: vec![ApprovalAction::DeleteGroup],
+        };
+        assert!(policy.applies_to(ApprovalAction::DeleteGroup));
+        assert!(policy.requires_approval(ApprovalAction::DeleteGroup, 0));
+        assert!(!policy.applies_to(ApprovalAction::UpdateGroup));
+        assert!(!policy.requires_approval(ApprovalAction::UpdateGroup, u64::MAX));
+    }
+
+    #[test]
+    fn amounts_above_the_threshold_need_approval() {
+        let policy = ApprovalPolicy {
+            threshold_lamports: Some(1000),
+            actions: Vec::new(),
+        };
+        assert!(policy.applies_to(ApprovalAction::ManualTransfer));
+        assert!(!policy.requires_approval(ApprovalAction::ManualTransfer, 1000));
+        assert!(policy.requires_approval(ApprovalAction::ManualTransfer, 1001));
+    }
+}
@@ -2,11 +2,23 @@ use std::str::FromStr;
 
 use anyhow::Context;
 use solana_sdk::pubkey::Pubkey;
-use sqlx::{MySqlPool, mysql::MySqlConnectOptions};
+use sqlx::{
+    MySql, MySqlConnection, MySqlPool, QueryBuilder, Row,
+    mysql::{MySqlConnectOptions, MySqlRow},
+};
+use tokio_stream::{Stream, StreamExt};
 
 use crate::{
     User,
-    schema::{Buyer, Group, Schedule, Transaction},
+    schema::{
+        Account, Adjustment, ApiKey, Approval, AuditEntry, AuditFilter, Buyer, BuyerFilter,
+        BuyerStats, BuyerTotals, BuyerUpload, Cursor, DistributionStats, DuplicatePercent,
+        FailureStats, Group, GroupTotals, GroupUpdate, GroupVersion, InvariantReport, LedgerEntry,
+        LedgerFilter, Movement, PERCENT_TOLERANCE, Page, PageQuery, Role, Schedule,
+        ScheduleBacklog, ScheduleFilter, ScheduleSends, Session, SortOrder, SortValue, StatsWindow,
+        TokenStats, Transaction, TransactionFilter, UnlockBucket, UserTotp, Webhook,
+        WebhookDelivery,
+    },
 };
 
 pub struct Database {
@@ -19,7 +31,26 @@ impl Database {
         let pool = MySqlPool::connect_with(options).await?;
         Ok(Self { pool })
     }
+
+    pub fn from_pool(pool: MySqlPool) -> Self {
+        Self { pool }
+    }
+
+    /// Checks that a connection can be taken from the pool and the database answers.
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query!("SELECT 1 AS one")
+            .fetch_one(&self.pool)
+            .await
+            .context("Database is not reachable")?;
+        Ok(())
+    }
+    /// Inserts a group unless its id exists, funding it from the treasury.
     pub async fn save_group(&self, group: &Group) -> anyhow::Result<bool> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
         let result = sqlx::query!(
             r#"
                 INSERT IGNORE INTO `groups` (
@@ -36,12 +67,25 @@ impl Database {
             group.unlock_interval_seconds,
             group.unlock_percent_per_interval
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await
         .context(format!("Failed to save group {} to database", group.id))?;
 
         // true = rows inserted; false = ignored
-        Ok(result.rows_affected() > 0)
+        let inserted = result.rows_affected() > 0;
+        if inserted {
+            let funding = LedgerEntry::new(
+                Movement::Allocation,
+                &Account::Group(group.id),
+                &Account::Treasury,
+                group.spl_total_lamports,
+                Some(group.id),
+                None,
+            );
+            post_entries(&mut tx, &[funding]).await?;
+        }
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(inserted)
     }
 
     pub async fn get_all_groups(&self) -> anyhow::Result<Vec<Group>> {
@@ -71,8 +115,245 @@ impl Database {
         Ok(row)
     }
 
+    pub async fn create_group(&self, group: &Group) -> anyhow::Result<i64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO `groups` (
+                    spl_share_percent, spl_total_lamports, spl_price_lamports,
+                    initial_unlock_percent, unlock_interval_seconds,
+                    unlock_percent_per_interval
+                ) VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+            group.spl_share_percent,
+            group.spl_total_lamports,
+            group.spl_price_lamports,
+            group.initial_unlock_percent,
+            group.unlock_interval_seconds,
+            group.unlock_percent_per_interval
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to create group")?;
+        let group_id = result.last_insert_id() as i64;
+
+        let funding = LedgerEntry::new(
+            Movement::Allocation,
+            &Account::Group(group_id),
+            &Account::Treasury,
+            group.spl_total_lamports,
+            Some(group_id),
+            None,
+        );
+        post_entries(&mut tx, &[funding]).await?;
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(group_id)
+    }
+
+    /// Updates a group, saves it as its next version and, with `plan`, replaces the `pending`
+    /// schedules of its buyers, all in one transaction. The group and its buyers stay locked
+    /// from the check of their `allocation` on, so the buyers that fit into the new total are
+    /// the ones that get re-planned. A changed `spl_total_lamports` moves the difference
+    /// between the treasury and the group.
+    pub async fn update_group<A, F>(
+        &self,
+        group: &Group,
+        changed_by: Option<&str>,
+        allocation: A,
+        plan: Option<F>,
+    ) -> anyhow::Result<GroupUpdate>
+    where
+        A: Fn(&Buyer) -> u64,
+        F: Fn(&Buyer, &[Schedule]) -> (u64, Vec<Schedule>),
+    {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        let locked = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM `groups` WHERE id = ? FOR UPDATE
+            "#,
+            group.id
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .context(format!("Failed to lock group {}", group.id))?;
+        if locked.is_none() {
+            anyhow::bail!(
+                "Failed to update group. No group found with id {}",
+                group.id
+            );
+        }
+        let buyers =
+            sqlx::query("SELECT * FROM `buyers` WHERE group_id = ? ORDER BY wallet FOR UPDATE")
+                .bind(group.id)
+                .fetch_all(&mut *tx)
+                .await
+                .context(format!("Failed to lock buyers of group {}", group.id))?
+                .iter()
+                .map(buyer_from_row)
+                .collect::<anyhow::Result<Vec<Buyer>>>()?;
+        let total_allocation: u64 = buyers.iter().map(&allocation).sum();
+        if total_allocation > group.spl_total_lamports {
+            return Ok(GroupUpdate::OverAllocated {
+                allocation: total_allocation,
+            });
+        }
+
+        let funded = group_funding(&mut tx, group.id).await?;
+        let difference = group.spl_total_lamports as i64 - funded;
+        let entry = if difference >= 0 {
+            LedgerEntry::new(
+                Movement::Allocation,
+                &Account::Group(group.id),
+                &Account::Treasury,
+                difference as u64,
+                Some(group.id),
+                None,
+            )
+        } else {
+            LedgerEntry::new(
+                Movement::Revocation,
+                &Account::Treasury,
+                &Account::Group(group.id),
+                difference.unsigned_abs(),
+                Some(group.id),
+                None,
+            )
+        };
+
+        sqlx::query!(
+            r#"
+                UPDATE `groups`
+                SET spl_share_percent = ?,
+                    spl_total_lamports = ?,
+                    spl_price_lamports = ?,
+                    initial_unlock_percent = ?,
+                    unlock_interval_seconds = ?,
+                    unlock_percent_per_interval = ?
+                WHERE id = ?
+            "#,
+            group.spl_share_percent,
+            group.spl_total_lamports,
+            group.spl_price_lamports,
+            group.initial_unlock_percent,
+            group.unlock_interval_seconds,
+            group.unlock_percent_per_interval,
+            group.id
+        )
+        .execute(&mut *tx)
+        .await
+        .context(format!("Failed to update group {}", group.id))?;
+        post_entries(&mut tx, &[entry]).await?;
+        let version = insert_group_version(&mut tx, group, "update", changed_by).await?;
+
+        let mut removed = Vec::new();
+        let mut created = Vec::new();
+        if let Some(plan) = plan {
+            for buyer in &buyers {
+                let (buyer_removed, buyer_created) =
+                    replace_pending_schedules(&mut tx, buyer, group.id, &plan).await?;
+                removed.extend(buyer_removed);
+                created.extend(buyer_created);
+            }
+        }
+        tx.commit().await.context("Failed to commit transaction")?;
+
+        let group = self
+            .get_group(group.id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Group {} not found after update", group.id))?;
+        Ok(GroupUpdate::Updated {
+            group,
+            version,
+            removed,
+            created,
+        })
+    }
+
+    /// Deletes a group and returns its unallocated tokens to the treasury.
+    pub async fn delete_group(&self, group_id: i64) -> anyhow::Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM `groups` WHERE id = ?
+            "#,
+            group_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context(format!("Failed to delete group with id {}", group_id))?;
+
+        if result.rows_affected() == 0 {
+            anyhow::bail!(
+                "Failed to delete group. No group found with id {}",
+                group_id
+            );
+        }
+
+        let balance = account_balance(&mut tx, &Account::Group(group_id)).await?;
+        let revocation = LedgerEntry::new(
+            Movement::Revocation,
+            &Account::Treasury,
+            &Account::Group(group_id),
+            balance.max(0) as u64,
+            Some(group_id),
+            None,
+        );
+        post_entries(&mut tx, &[revocation]).await?;
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    /// Stores a snapshot of `group` as its next version and returns the version number.
+    pub async fn save_group_version(
+        &self,
+        group: &Group,
+        change_type: &str,
+        changed_by: Option<&str>,
+    ) -> anyhow::Result<i64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        let version = insert_group_version(&mut tx, group, change_type, changed_by).await?;
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(version)
+    }
+
+    pub async fn get_group_versions(&self, group_id: i64) -> anyhow::Result<Vec<GroupVersion>> {
+        let rows = sqlx::query_as!(
+            GroupVersion,
+            r#"
+            SELECT * FROM `group_versions` WHERE group_id = ? ORDER BY version
+            "#,
+            group_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context(format!("Failed to get versions of group {}", group_id))?;
+        Ok(rows)
+    }
+
+    /// Inserts a buyer unless the wallet exists, allocating their balances from the group.
     pub async fn save_buyer(&self, buyer: &Buyer) -> anyhow::Result<bool> {
         let wallet_str = buyer.wallet.to_string();
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
         let result=sqlx::query!(
             r#"
             INSERT IGNORE INTO `buyers` (
@@ -87,12 +368,36 @@ impl Database {
             buyer.pending_spl_lamports,
             buyer.error
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await
         .context("Failed to save buyer to database")?;
 
         // true = rows inserted; false = ignored
-        Ok(result.rows_affected() > 0)
+        let inserted = result.rows_affected() > 0;
+        if inserted {
+            let pending = Account::BuyerPending(wallet_str.clone());
+            let entries = [
+                LedgerEntry::new(
+                    Movement::Allocation,
+                    &pending,
+                    &Account::Group(buyer.group_id),
+                    buyer.pending_spl_lamports + buyer.received_spl_lamports,
+                    Some(buyer.group_id),
+                    Some(wallet_str.as_str()),
+                ),
+                LedgerEntry::new(
+                    Movement::Unlock,
+                    &Account::Buyer(wallet_str.clone()),
+                    &pending,
+                    buyer.received_spl_lamports,
+                    Some(buyer.group_id),
+                    Some(wallet_str.as_str()),
+                ),
+            ];
+            post_entries(&mut tx, &entries).await?;
+        }
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(inserted)
     }
 
     pub async fn get_buyers_by_group(&self, group_id: i64) -> anyhow::Result<Vec<Buyer>> {
@@ -132,6 +437,9 @@ impl Database {
         }
         Ok(buyers)
     }
+    /// Brings the balances of a buyer to the given values by posting the differences to the
+    /// ledger: more received is an unlock, less an adjustment; more pending is an allocation,
+    /// less a refund. Applying the same values twice changes nothing.
     pub async fn update_buyer(
         &self,
         wallet: &str,
@@ -139,32 +447,242 @@ impl Database {
         received_percent: f64,
         pending_spl_lamports: u64,
     ) -> anyhow::Result<Buyer> {
-        let result = sqlx::query!(
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        let group_id = lock_buyer(&mut tx, wallet).await?;
+        let received = Account::Buyer(wallet.to_string());
+        let pending = Account::BuyerPending(wallet.to_string());
+        let current_received = account_balance(&mut tx, &received).await?;
+        let current_pending = account_balance(&mut tx, &pending).await?;
+
+        let mut entries = Vec::new();
+        let unlocked = received_spl_lamports as i64 - current_received;
+        if unlocked >= 0 {
+            entries.push(LedgerEntry::new(
+                Movement::Unlock,
+                &received,
+                &pending,
+                unlocked as u64,
+                Some(group_id),
+                Some(wallet),
+            ));
+        } else {
+            entries.push(LedgerEntry::new(
+                Movement::Adjustment,
+                &Account::Treasury,
+                &received,
+                unlocked.unsigned_abs(),
+                Some(group_id),
+                Some(wallet),
+            ));
+        }
+        let allocated = pending_spl_lamports as i64 - (current_pending - unlocked.max(0));
+        entries.push(pending_change(wallet, group_id, allocated));
+        post_entries(&mut tx, &entries).await?;
+
+        sqlx::query!(
             r#"
-            UPDATE `buyers`
-            SET received_spl_lamports = ?, received_percent = ?, pending_spl_lamports = ?
-            WHERE wallet = ?
+            UPDATE `buyers` SET received_percent = ? WHERE wallet = ?
             "#,
-            received_spl_lamports,
             received_percent,
-            pending_spl_lamports,
             wallet
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await
         .context("Failed to update buyer in database")?;
+        refresh_buyer_totals(&mut tx, wallet).await?;
+        tx.commit().await.context("Failed to commit transaction")?;
 
-        if result.rows_affected() == 0 {
-            anyhow::bail!(
-                "Failed to update buyer. No buyer found with wallet `{}`",
-                wallet
-            );
-        }
         let updated_opt = self.get_buyer_by_wallet(wallet).await?;
-
         updated_opt.ok_or_else(|| anyhow::anyhow!("Buyer `{}` not found after update", wallet))
     }
 
+    /// Records a sent schedule as an unlock of the buyer, at most once per schedule.
+    pub async fn record_unlock(&self, schedule: &Schedule) -> anyhow::Result<Buyer> {
+        let wallet = schedule.buyer_wallet.as_str();
+        let reference = format!("schedule:{}", schedule.id);
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        lock_buyer(&mut tx, wallet).await?;
+
+        let recorded = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) FROM `ledger_entries` WHERE movement = 'unlock' AND reference = ?
+            "#,
+            reference
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .context(format!(
+            "Failed to check unlock of schedule {}",
+            schedule.id
+        ))?;
+        if recorded == 0 {
+            let unlock = LedgerEntry::new(
+                Movement::Unlock,
+                &Account::Buyer(wallet.to_string()),
+                &Account::BuyerPending(wallet.to_string()),
+                schedule.amount_lamports,
+                Some(schedule.group_id),
+                Some(wallet),
+            )
+            .with_reference(reference);
+            post_entries(&mut tx, &[unlock]).await?;
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE `buyers` SET received_percent = GREATEST(received_percent, ?) WHERE wallet = ?
+            "#,
+            schedule.percent,
+            wallet
+        )
+        .execute(&mut *tx)
+        .await
+        .context(format!("Failed to update buyer `{}`", wallet))?;
+        refresh_buyer_totals(&mut tx, wallet).await?;
+        tx.commit().await.context("Failed to commit transaction")?;
+
+        self.get_buyer_by_wallet(wallet)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Buyer `{}` not found after update", wallet))
+    }
+
+    /// Changes the purchase of a buyer and replaces their `pending` schedules in one
+    /// transaction, like `replan_schedules`. The pending allocation moves along when the
+    /// group changes. Returns `None` without changing anything if the buyer should move to
+    /// another group but something may already have been sent to them.
+    pub async fn update_buyer_details<F>(
+        &self,
+        wallet: &str,
+        paid_lamports: u64,
+        group_id: i64,
+        plan: F,
+    ) -> anyhow::Result<Option<(Vec<Schedule>, Vec<Schedule>)>>
+    where
+        F: FnOnce(&Buyer, &[Schedule]) -> (u64, Vec<Schedule>),
+    {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        let mut buyer = lock_buyer_row(&mut tx, wallet).await?;
+        let current_group_id = buyer.group_id;
+        if current_group_id != group_id {
+            if buyer.received_spl_lamports > 0 || has_transfers(&mut tx, wallet).await? {
+                return Ok(None);
+            }
+            let pending = Account::BuyerPending(wallet.to_string());
+            let current_pending = account_balance(&mut tx, &pending).await?;
+            if current_pending > 0 {
+                let entries = [
+                    LedgerEntry::new(
+                        Movement::Revocation,
+                        &Account::Group(current_group_id),
+                        &pending,
+                        current_pending as u64,
+                        Some(current_group_id),
+                        Some(wallet),
+                    ),
+                    LedgerEntry::new(
+                        Movement::Allocation,
+                        &pending,
+                        &Account::Group(group_id),
+                        current_pending as u64,
+                        Some(group_id),
+                        Some(wallet),
+                    ),
+                ];
+                post_entries(&mut tx, &entries).await?;
+            }
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE `buyers`
+            SET paid_lamports = ?, group_id = ?
+            WHERE wallet = ?
+            "#,
+            paid_lamports,
+            group_id,
+            wallet
+        )
+        .execute(&mut *tx)
+        .await
+        .context(format!("Failed to update details of buyer `{}`", wallet))?;
+        buyer.paid_lamports = paid_lamports;
+        buyer.group_id = group_id;
+
+        let schedules = replace_pending_schedules(&mut tx, &buyer, current_group_id, plan).await?;
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(Some(schedules))
+    }
+
+    /// Deletes a buyer with their `pending` schedules and revokes their pending allocation
+    /// back to the group. Returns false without deleting anything if something may already
+    /// have been sent to the buyer, so no transfer record is lost.
+    pub async fn delete_buyer(&self, wallet: &str) -> anyhow::Result<bool> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        let buyer = lock_buyer_row(&mut tx, wallet).await?;
+        if buyer.received_spl_lamports > 0 || has_transfers(&mut tx, wallet).await? {
+            return Ok(false);
+        }
+        let pending = Account::BuyerPending(wallet.to_string());
+        let current_pending = account_balance(&mut tx, &pending).await?;
+        let revocation = LedgerEntry::new(
+            Movement::Revocation,
+            &Account::Group(buyer.group_id),
+            &pending,
+            current_pending.max(0) as u64,
+            Some(buyer.group_id),
+            Some(wallet),
+        );
+        post_entries(&mut tx, &[revocation]).await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM `schedule` WHERE buyer_wallet = ? AND status = 'pending'
+            "#,
+            wallet
+        )
+        .execute(&mut *tx)
+        .await
+        .context(format!("Failed to delete schedules of buyer `{}`", wallet))?;
+        sqlx::query!(
+            r#"
+            DELETE FROM `buyers` WHERE wallet = ?
+            "#,
+            wallet
+        )
+        .execute(&mut *tx)
+        .await
+        .context(format!("Failed to delete buyer `{}`", wallet))?;
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(true)
+    }
+
+    /// Whether a schedule of the buyer left `pending`, or the buyer has a transaction, a
+    /// manual transfer or an adjustment.
+    pub async fn buyer_has_transfers(&self, wallet: &str) -> anyhow::Result<bool> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .context("Failed to get a database connection")?;
+        has_transfers(&mut conn, wallet).await
+    }
+
     pub async fn get_buyer_by_wallet(&self, wallet: &str) -> anyhow::Result<Option<Buyer>> {
         let row = sqlx::query!(
             r#"
@@ -236,19 +754,97 @@ impl Database {
         Ok(buyers)
     }
 
+    /// Returns one page of buyers matching the filter.
+    pub async fn list_buyers(
+        &self,
+        filter: &BuyerFilter,
+        page: &PageQuery,
+        cursor: Option<&Cursor>,
+    ) -> anyhow::Result<Page<Buyer>> {
+        let mut query = QueryBuilder::<MySql>::new("SELECT * FROM `buyers` WHERE 1 = 1");
+        if let Some(wallet) = &filter.wallet {
+            query.push(" AND wallet = ").push_bind(wallet.clone());
+        }
+        if let Some(group_id) = filter.group_id {
+            query.push(" AND group_id = ").push_bind(group_id);
+        }
+        if let Some(min) = filter.min_paid_lamports {
+            query.push(" AND paid_lamports >= ").push_bind(min);
+        }
+        if let Some(max) = filter.max_paid_lamports {
+            query.push(" AND paid_lamports <= ").push_bind(max);
+        }
+        if let Some(cursor) = cursor {
+            let key = SortValue::Text(cursor.key.clone());
+            push_cursor(
+                &mut query,
+                filter.sort.column(),
+                "wallet",
+                page.order,
+                cursor,
+                key,
+            );
+        }
+        push_order(&mut query, filter.sort.column(), "wallet", page);
+
+        let rows = query
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list buyers")?;
+        let buyers = rows
+            .iter()
+            .map(buyer_from_row)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Page::from_rows(buyers, page.limit, |b| {
+            filter.sort.cursor(b)
+        }))
+    }
+
+    /// Streams all buyers row by row, used by exports instead of loading the whole table.
+    pub fn stream_buyers(&self) -> impl Stream<Item = anyhow::Result<Buyer>> + Send + '_ {
+        sqlx::query!(
+            r#"
+            SELECT * FROM `buyers` ORDER BY created_at, wallet
+            "#
+        )
+        .fetch(&self.pool)
+        .map(|row| {
+            let row = row.context("Failed to stream buyers")?;
+            let wallet_pk = Pubkey::from_str(&row.wallet).with_context(|| {
+                format!("Failed to stream buyers. Invalid Pubkey `{}`", row.wallet)
+            })?;
+            Ok(Buyer {
+                wallet: wallet_pk,
+                paid_lamports: row.paid_lamports,
+                group_id: row.group_id,
+                received_spl_lamports: row.received_spl_lamports,
+                received_percent: row.received_percent,
+                pending_spl_lamports: row.pending_spl_lamports,
+                error: row.error,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            })
+        })
+    }
+
     pub async fn save_transaction(&self, transaction: Transaction) -> anyhow::Result<i64> {
         let result = sqlx::query!(
             r#"
             INSERT INTO `transactions` (
-                buyer_wallet, group_id, amount_lamports, percent, status, error_message, sent_at
-            ) VALUES (?, ?, ?, ?, ?, ?, ?)
+                schedule_id, buyer_wallet, group_id, amount_lamports, percent, status,
+                error_message, signature, sent_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
+            transaction.schedule_id,
             transaction.buyer_wallet,
             transaction.group_id,
             transaction.amount_lamports,
             transaction.percent,
             transaction.status,
             transaction.error_message,
+            transaction.signature,
             transaction.sent_at
         )
         .execute(&self.pool)
@@ -279,6 +875,30 @@ impl Database {
         Ok(transactions)
     }
 
+    pub async fn get_transactions_by_wallet(
+        &self,
+        buyer_wallet: &str,
+    ) -> anyhow::Result<Vec<Transaction>> {
+        let transactions = sqlx::query_as!(
+            Transaction,
+            r#"
+            SELECT
+                *
+            FROM `transactions`
+            WHERE buyer_wallet = ?
+            "#,
+            buyer_wallet
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context(format!(
+            "Failed to get transactions of buyer `{}`",
+            buyer_wallet
+        ))?;
+
+        Ok(transactions)
+    }
+
     pub async fn get_all_transactions(&self) -> anyhow::Result<Vec<Transaction>> {
         let transactions = sqlx::query_as!(
             Transaction,
@@ -295,27 +915,83 @@ impl Database {
         Ok(transactions)
     }
 
-    pub async fn save_schedule(&self, schedule: &Schedule) -> anyhow::Result<i64> {
-        let result = sqlx::query!(
+    /// Returns one page of transactions matching the filter.
+    pub async fn list_transactions(
+        &self,
+        filter: &TransactionFilter,
+        page: &PageQuery,
+        cursor: Option<&Cursor>,
+    ) -> anyhow::Result<Page<Transaction>> {
+        let mut query = QueryBuilder::<MySql>::new("SELECT * FROM `transactions` WHERE 1 = 1");
+        if let Some(wallet) = &filter.wallet {
+            query.push(" AND buyer_wallet = ").push_bind(wallet.clone());
+        }
+        if let Some(group_id) = filter.group_id {
+            query.push(" AND group_id = ").push_bind(group_id);
+        }
+        if let Some(status) = &filter.status {
+            query.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(from) = filter.from {
+            query.push(" AND sent_at >= ").push_bind(from);
+        }
+        if let Some(to) = filter.to {
+            query.push(" AND sent_at <= ").push_bind(to);
+        }
+        if let Some(min) = filter.min_amount {
+            query.push(" AND amount_lamports >= ").push_bind(min);
+        }
+        if let Some(max) = filter.max_amount {
+            query.push(" AND amount_lamports <= ").push_bind(max);
+        }
+        if let Some(cursor) = cursor {
+            let key = id_key(cursor)?;
+            push_cursor(
+                &mut query,
+                filter.sort.column(),
+                "id",
+                page.order,
+                cursor,
+                key,
+            );
+        }
+        push_order(&mut query, filter.sort.column(), "id", page);
+
+        let transactions = query
+            .build_query_as::<Transaction>()
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list transactions")?;
+
+        Ok(Page::from_rows(transactions, page.limit, |t| {
+            filter.sort.cursor(t)
+        }))
+    }
+
+    /// Streams all transactions row by row.
+    pub fn stream_transactions(
+        &self,
+    ) -> impl Stream<Item = anyhow::Result<Transaction>> + Send + '_ {
+        sqlx::query_as!(
+            Transaction,
             r#"
-            INSERT INTO `schedule` (
-                group_id, buyer_wallet, scheduled_at, amount_lamports, percent, status
-            ) VALUES (?, ?, ?, ?, ?, ?)
-            "#,
-            schedule.group_id,
-            schedule.buyer_wallet,
-            schedule.scheduled_at,
-            schedule.amount_lamports,
-            schedule.percent,
-            schedule.status
+            SELECT
+                *
+            FROM `transactions`
+            ORDER BY id
+            "#
         )
-        .execute(&self.pool)
-        .await
-        .context(format!(
-            "Failed to insert schedule for group_id={} buyer={}",
-            schedule.group_id, schedule.buyer_wallet
-        ))?;
-        Ok(result.last_insert_id() as i64)
+        .fetch(&self.pool)
+        .map(|row| row.context("Failed to stream transactions"))
+    }
+
+    pub async fn save_schedule(&self, schedule: &Schedule) -> anyhow::Result<i64> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .context("Failed to get a database connection")?;
+        insert_schedule(&mut conn, schedule).await
     }
     pub async fn get_schedule_by_id(&self, schedule_id: i64) -> anyhow::Result<Option<Schedule>> {
         let row = sqlx::query_as!(
@@ -365,18 +1041,86 @@ impl Database {
 
         Ok(rows)
     }
-    pub async fn get_schedules_due(
+
+    /// Returns one page of schedules matching the filter.
+    pub async fn list_schedules(
         &self,
-        now: chrono::NaiveDateTime,
-    ) -> anyhow::Result<Vec<Schedule>> {
-        let rows = sqlx::query_as!(
+        filter: &ScheduleFilter,
+        page: &PageQuery,
+        cursor: Option<&Cursor>,
+    ) -> anyhow::Result<Page<Schedule>> {
+        let mut query = QueryBuilder::<MySql>::new("SELECT * FROM `schedule` WHERE 1 = 1");
+        if let Some(wallet) = &filter.wallet {
+            query.push(" AND buyer_wallet = ").push_bind(wallet.clone());
+        }
+        if let Some(group_id) = filter.group_id {
+            query.push(" AND group_id = ").push_bind(group_id);
+        }
+        if let Some(status) = &filter.status {
+            query.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(from) = filter.from {
+            query.push(" AND scheduled_at >= ").push_bind(from);
+        }
+        if let Some(to) = filter.to {
+            query.push(" AND scheduled_at <= ").push_bind(to);
+        }
+        if let Some(min) = filter.min_amount {
+            query.push(" AND amount_lamports >= ").push_bind(min);
+        }
+        if let Some(max) = filter.max_amount {
+            query.push(" AND amount_lamports <= ").push_bind(max);
+        }
+        if let Some(cursor) = cursor {
+            let key = id_key(cursor)?;
+            push_cursor(
+                &mut query,
+                filter.sort.column(),
+                "id",
+                page.order,
+                cursor,
+                key,
+            );
+        }
+        push_order(&mut query, filter.sort.column(), "id", page);
+
+        let schedules = query
+            .build_query_as::<Schedule>()
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list schedules")?;
+
+        Ok(Page::from_rows(schedules, page.limit, |s| {
+            filter.sort.cursor(s)
+        }))
+    }
+
+    /// Streams all schedules row by row.
+    pub fn stream_schedules(&self) -> impl Stream<Item = anyhow::Result<Schedule>> + Send + '_ {
+        sqlx::query_as!(
             Schedule,
             r#"
             SELECT
                 *
             FROM `schedule`
-            WHERE  scheduled_at <= ? AND status = 'pending'
-            "#,
+            ORDER BY id
+            "#
+        )
+        .fetch(&self.pool)
+        .map(|row| row.context("Failed to stream schedules"))
+    }
+    pub async fn get_schedules_due(
+        &self,
+        now: chrono::NaiveDateTime,
+    ) -> anyhow::Result<Vec<Schedule>> {
+        let rows = sqlx::query_as!(
+            Schedule,
+            r#"
+            SELECT
+                *
+            FROM `schedule`
+            WHERE  scheduled_at <= ? AND status = 'pending'
+            "#,
             now //maybe in future also check status
         )
         .fetch_all(&self.pool)
@@ -439,6 +1183,55 @@ impl Database {
             .await?
             .ok_or_else(|| anyhow::anyhow!("Updated schedule not found (id: {})", schedule_id))
     }
+    /// Marks a schedule with `status` as being sent. Returns false if its status changed or
+    /// it was replaced in the meantime, so it is never sent twice.
+    pub async fn claim_schedule(&self, schedule_id: i64, status: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE `schedule` SET status = 'processing' WHERE id = ? AND status = ?
+            "#,
+            schedule_id,
+            status
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to claim schedule {}", schedule_id))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Replaces the `pending` schedules of a buyer in `group_id` in one transaction. `plan`
+    /// gets the buyer and the schedules that stay (sent, being sent or failed) and returns
+    /// the new pending allocation and the schedules to add. The runner claims a schedule
+    /// before sending it, so a schedule on its way to the chain is never replaced.
+    /// Returns the removed and the created schedules.
+    pub async fn replan_schedules<F>(
+        &self,
+        wallet: &str,
+        group_id: i64,
+        plan: F,
+    ) -> anyhow::Result<(Vec<Schedule>, Vec<Schedule>)>
+    where
+        F: FnOnce(&Buyer, &[Schedule]) -> (u64, Vec<Schedule>),
+    {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        let buyer = lock_buyer_row(&mut tx, wallet).await?;
+        if buyer.group_id != group_id {
+            anyhow::bail!(
+                "Buyer `{}` is in group {}, not in group {}",
+                wallet,
+                buyer.group_id,
+                group_id
+            );
+        }
+        let schedules = replace_pending_schedules(&mut tx, &buyer, group_id, plan).await?;
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(schedules)
+    }
+
     pub async fn delete_schedule(&self, schedule_id: i64) -> anyhow::Result<()> {
         let result = sqlx::query!(
             r#"
@@ -462,12 +1255,13 @@ impl Database {
     pub async fn save_user(&self, user: &User) -> anyhow::Result<i64> {
         let result = sqlx::query!(
             r#"
-            INSERT INTO users (username, email, password_hash, is_superuser)
-            VALUES (?, ?, ?, ?)
+            INSERT INTO users (username, email, password_hash, role, is_superuser)
+            VALUES (?, ?, ?, ?, ?)
             "#,
             user.username,
             user.email,
             user.password_hash,
+            user.role,
             user.is_superuser,
         )
         .execute(&self.pool)
@@ -494,7 +1288,10 @@ impl Database {
                 username, 
                 email, 
                 password_hash, 
+                role,
                 is_superuser as `is_superuser: bool`, 
+                active as `active: bool`,
+                NULL as `session_id: String`,
                 created_at, 
                 updated_at
             FROM users
@@ -507,4 +1304,2485 @@ impl Database {
         .context(format!("Failed to get user with username '{}'", username))?;
         Ok(user)
     }
+
+    pub async fn get_all_users(&self) -> anyhow::Result<Vec<User>> {
+        let users = sqlx::query_as!(
+            User,
+            r#"
+            SELECT
+                id,
+                username,
+                email,
+                password_hash,
+                role,
+                is_superuser as `is_superuser: bool`,
+                active as `active: bool`,
+                NULL as `session_id: String`,
+                created_at,
+                updated_at
+            FROM users
+            ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get users")?;
+        Ok(users)
+    }
+
+    pub async fn update_user_email(&self, username: &str, email: &str) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users SET email = ? WHERE username = ?
+            "#,
+            email,
+            username
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update email of user `{}`", username))?;
+        Ok(())
+    }
+
+    pub async fn update_user_password(
+        &self,
+        username: &str,
+        password_hash: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users SET password_hash = ? WHERE username = ?
+            "#,
+            password_hash,
+            username
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update password of user `{}`", username))?;
+        Ok(())
+    }
+
+    pub async fn set_user_active(&self, username: &str, active: bool) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users SET active = ? WHERE username = ?
+            "#,
+            active,
+            username
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!(
+            "Failed to update active flag of user `{}`",
+            username
+        ))?;
+        Ok(())
+    }
+
+    pub async fn delete_user(&self, username: &str) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM users WHERE username = ?
+            "#,
+            username
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to delete user `{}`", username))?;
+        Ok(())
+    }
+
+    /// End of the user's login lockout, if any.
+    pub async fn get_user_locked_until(
+        &self,
+        user_id: i64,
+    ) -> anyhow::Result<Option<chrono::NaiveDateTime>> {
+        let row = sqlx::query!(
+            r#"
+            SELECT locked_until FROM users WHERE id = ?
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get lockout of user id={}", user_id))?;
+        Ok(row.and_then(|r| r.locked_until))
+    }
+
+    /// Counts a wrong password. The `max_failures`-th one in a row locks the user until
+    /// `lock_until` and starts the count again. Returns true if the user got locked.
+    pub async fn record_failed_login(
+        &self,
+        user_id: i64,
+        max_failures: u32,
+        lock_until: chrono::NaiveDateTime,
+    ) -> anyhow::Result<bool> {
+        // DATETIME has no fractions of a second, so the stored value can be compared below
+        let lock_until = chrono::SubsecRound::trunc_subsecs(lock_until, 0);
+        // MySQL assigns from left to right, so `locked_until` still sees the old count
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET locked_until = IF(failed_logins + 1 >= ?, ?, locked_until),
+                failed_logins = IF(failed_logins + 1 >= ?, 0, failed_logins + 1)
+            WHERE id = ?
+            "#,
+            max_failures,
+            lock_until,
+            max_failures,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!(
+            "Failed to record failed login of user id={}",
+            user_id
+        ))?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        let locked_until = self.get_user_locked_until(user_id).await?;
+        Ok(locked_until == Some(lock_until))
+    }
+
+    pub async fn reset_failed_logins(&self, user_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users SET failed_logins = 0, locked_until = NULL WHERE id = ?
+            "#,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!(
+            "Failed to reset failed logins of user id={}",
+            user_id
+        ))?;
+        Ok(())
+    }
+
+    pub async fn get_user_by_id(&self, user_id: i64) -> anyhow::Result<Option<User>> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            SELECT
+                id,
+                username,
+                email,
+                password_hash,
+                role,
+                is_superuser as `is_superuser: bool`,
+                active as `active: bool`,
+                NULL as `session_id: String`,
+                created_at,
+                updated_at
+            FROM users
+            WHERE id = ?
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get user with id={}", user_id))?;
+        Ok(user)
+    }
+
+    pub async fn get_user_totp(&self, user_id: i64) -> anyhow::Result<UserTotp> {
+        let totp = sqlx::query_as!(
+            UserTotp,
+            r#"
+            SELECT
+                totp_secret as secret,
+                totp_enabled as `enabled: bool`,
+                (
+                    SELECT COUNT(*) FROM recovery_codes
+                    WHERE user_id = users.id AND used_at IS NULL
+                ) as `recovery_codes_left!: i64`
+            FROM users
+            WHERE id = ?
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get TOTP of user id={}", user_id))?;
+        Ok(totp.unwrap_or_default())
+    }
+
+    /// Stores a TOTP secret, enabled or still being enrolled, or removes the second factor
+    /// with its recovery codes when `secret` is `None`.
+    pub async fn set_user_totp(
+        &self,
+        user_id: i64,
+        secret: Option<&str>,
+        enabled: bool,
+    ) -> anyhow::Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_secret = ?, totp_enabled = ?, totp_last_step = NULL
+            WHERE id = ?
+            "#,
+            secret,
+            enabled && secret.is_some(),
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context(format!("Failed to update TOTP of user id={}", user_id))?;
+        if secret.is_none() {
+            sqlx::query!(
+                r#"
+                DELETE FROM recovery_codes WHERE user_id = ?
+                "#,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await
+            .context(format!(
+                "Failed to delete recovery codes of user id={}",
+                user_id
+            ))?;
+        }
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    /// Marks the time step of a TOTP code as used. Returns false if this or a later step
+    /// was already used, i.e. the code is replayed.
+    pub async fn use_totp_step(&self, user_id: i64, step: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_step = ?
+            WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)
+            "#,
+            step,
+            user_id,
+            step
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update TOTP step of user id={}", user_id))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Replaces all recovery codes of a user. Completes the TOTP enrollment if it is still
+    /// pending, as recovery codes are handed out when it is confirmed.
+    pub async fn replace_recovery_codes(
+        &self,
+        user_id: i64,
+        code_hashes: &[String],
+    ) -> anyhow::Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_enabled = TRUE
+            WHERE id = ? AND totp_secret IS NOT NULL
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context(format!("Failed to enable TOTP of user id={}", user_id))?;
+        sqlx::query!(
+            r#"
+            DELETE FROM recovery_codes WHERE user_id = ?
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context(format!(
+            "Failed to delete recovery codes of user id={}",
+            user_id
+        ))?;
+        for code_hash in code_hashes {
+            sqlx::query!(
+                r#"
+                INSERT INTO recovery_codes (user_id, code_hash) VALUES (?, ?)
+                "#,
+                user_id,
+                code_hash
+            )
+            .execute(&mut *tx)
+            .await
+            .context(format!(
+                "Failed to save recovery code of user id={}",
+                user_id
+            ))?;
+        }
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    /// Uses up a recovery code. Returns false if it doesn't exist or was used before.
+    pub async fn use_recovery_code(&self, user_id: i64, code_hash: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE recovery_codes
+            SET used_at = ?
+            WHERE user_id = ? AND code_hash = ? AND used_at IS NULL
+            "#,
+            chrono::Utc::now().naive_utc(),
+            user_id,
+            code_hash
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!(
+            "Failed to use recovery code of user id={}",
+            user_id
+        ))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Changes the role of a user; `is_superuser` follows the admin role.
+    pub async fn update_user_role(&self, username: &str, role: Role) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET role = ?, is_superuser = ?
+            WHERE username = ?
+            "#,
+            role.as_str(),
+            role == Role::Admin,
+            username
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update role of user `{}`", username))?;
+        Ok(())
+    }
+
+    pub async fn save_session(&self, session: &Session) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO `sessions` (id, user_id, ip_address, user_agent, expires_at, pending_totp)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+            session.id,
+            session.user_id,
+            session.ip_address,
+            session.user_agent,
+            session.expires_at,
+            session.pending_totp
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!(
+            "Failed to save session for user id={}",
+            session.user_id
+        ))?;
+        Ok(())
+    }
+
+    pub async fn get_session(&self, session_id: &str) -> anyhow::Result<Option<Session>> {
+        let session = sqlx::query_as!(
+            Session,
+            r#"
+            SELECT
+                id, user_id, ip_address, user_agent, created_at, last_seen_at, expires_at,
+                revoked_at, pending_totp as `pending_totp: bool`
+            FROM `sessions`
+            WHERE id = ?
+            "#,
+            session_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get session {}", session_id))?;
+        Ok(session)
+    }
+
+    /// Sessions of a user that are neither revoked nor expired, newest first.
+    pub async fn get_active_sessions(&self, user_id: i64) -> anyhow::Result<Vec<Session>> {
+        let sessions = sqlx::query_as!(
+            Session,
+            r#"
+            SELECT
+                id, user_id, ip_address, user_agent, created_at, last_seen_at, expires_at,
+                revoked_at, pending_totp as `pending_totp: bool`
+            FROM `sessions`
+            WHERE user_id = ? AND revoked_at IS NULL AND expires_at > ? AND pending_totp = FALSE
+            ORDER BY created_at DESC
+            "#,
+            user_id,
+            chrono::Utc::now().naive_utc()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context(format!("Failed to get sessions of user id={}", user_id))?;
+        Ok(sessions)
+    }
+
+    /// Issues tokens for a session that was waiting for the TOTP code.
+    pub async fn confirm_session(
+        &self,
+        session_id: &str,
+        expires_at: chrono::NaiveDateTime,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE `sessions`
+            SET pending_totp = FALSE, expires_at = ?, last_seen_at = ?
+            WHERE id = ?
+            "#,
+            expires_at,
+            chrono::Utc::now().naive_utc(),
+            session_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to confirm session {}", session_id))?;
+        Ok(())
+    }
+
+    /// Records that the session refreshed its access token.
+    pub async fn touch_session(&self, session_id: &str) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE `sessions` SET last_seen_at = ? WHERE id = ?
+            "#,
+            chrono::Utc::now().naive_utc(),
+            session_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update session {}", session_id))?;
+        Ok(())
+    }
+
+    /// Revokes one session. Returns false if it doesn't exist or was already revoked.
+    pub async fn revoke_session(&self, session_id: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE `sessions` SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL
+            "#,
+            chrono::Utc::now().naive_utc(),
+            session_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to revoke session {}", session_id))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Revokes all sessions of a user except `keep`, returns how many were revoked.
+    pub async fn revoke_user_sessions(
+        &self,
+        user_id: i64,
+        keep: Option<&str>,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE `sessions`
+            SET revoked_at = ?
+            WHERE user_id = ? AND revoked_at IS NULL AND (? IS NULL OR id <> ?)
+            "#,
+            chrono::Utc::now().naive_utc(),
+            user_id,
+            keep,
+            keep
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to revoke sessions of user id={}", user_id))?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn save_api_key(&self, api_key: &ApiKey) -> anyhow::Result<i64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO `api_keys` (name, prefix, key_hash, role, user_id, created_by, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            api_key.name,
+            api_key.prefix,
+            api_key.key_hash,
+            api_key.role,
+            api_key.user_id,
+            api_key.created_by,
+            api_key.expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to save API key `{}`", api_key.name))?;
+
+        Ok(result.last_insert_id() as i64)
+    }
+
+    pub async fn get_api_key(&self, api_key_id: i64) -> anyhow::Result<Option<ApiKey>> {
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, name, prefix, key_hash, role, user_id, created_by, expires_at, last_used_at, revoked_at, created_at
+            FROM `api_keys`
+            WHERE id = ?
+            "#,
+            api_key_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get API key {}", api_key_id))?;
+        Ok(api_key)
+    }
+
+    pub async fn get_api_key_by_hash(&self, key_hash: &str) -> anyhow::Result<Option<ApiKey>> {
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, name, prefix, key_hash, role, user_id, created_by, expires_at, last_used_at, revoked_at, created_at
+            FROM `api_keys`
+            WHERE key_hash = ?
+            "#,
+            key_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get API key by hash")?;
+        Ok(api_key)
+    }
+
+    pub async fn get_all_api_keys(&self) -> anyhow::Result<Vec<ApiKey>> {
+        let api_keys = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, name, prefix, key_hash, role, user_id, created_by, expires_at, last_used_at, revoked_at, created_at
+            FROM `api_keys`
+            ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get API keys")?;
+        Ok(api_keys)
+    }
+
+    pub async fn touch_api_key(&self, api_key_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE `api_keys` SET last_used_at = ? WHERE id = ?
+            "#,
+            chrono::Utc::now().naive_utc(),
+            api_key_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update API key {}", api_key_id))?;
+        Ok(())
+    }
+
+    /// Returns false if the key doesn't exist or was already revoked.
+    pub async fn revoke_api_key(&self, api_key_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE `api_keys` SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL
+            "#,
+            chrono::Utc::now().naive_utc(),
+            api_key_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to revoke API key {}", api_key_id))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn save_adjustment(&self, adjustment: &Adjustment) -> anyhow::Result<i64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO `adjustments` (
+                buyer_wallet, group_id, kind, amount_lamports, reason, status, created_by
+            ) VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            adjustment.buyer_wallet,
+            adjustment.group_id,
+            adjustment.kind,
+            adjustment.amount_lamports,
+            adjustment.reason,
+            adjustment.status,
+            adjustment.created_by
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!(
+            "Failed to save {} for buyer {}",
+            adjustment.kind, adjustment.buyer_wallet
+        ))?;
+        Ok(result.last_insert_id() as i64)
+    }
+
+    pub async fn get_adjustment(&self, adjustment_id: i64) -> anyhow::Result<Option<Adjustment>> {
+        let adjustment = sqlx::query_as!(
+            Adjustment,
+            r#"
+            SELECT * FROM `adjustments` WHERE id = ?
+            "#,
+            adjustment_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get adjustment {}", adjustment_id))?;
+        Ok(adjustment)
+    }
+
+    pub async fn get_adjustments_by_wallet(&self, wallet: &str) -> anyhow::Result<Vec<Adjustment>> {
+        let adjustments = sqlx::query_as!(
+            Adjustment,
+            r#"
+            SELECT * FROM `adjustments` WHERE buyer_wallet = ? ORDER BY id
+            "#,
+            wallet
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context(format!("Failed to get adjustments of buyer {}", wallet))?;
+        Ok(adjustments)
+    }
+
+    /// Manual transfers queued by the CLI, waiting for the runner.
+    pub async fn get_pending_transfers(&self) -> anyhow::Result<Vec<Adjustment>> {
+        let adjustments = sqlx::query_as!(
+            Adjustment,
+            r#"
+            SELECT * FROM `adjustments`
+            WHERE kind = 'transfer' AND status = 'pending'
+            ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get pending transfers")?;
+        Ok(adjustments)
+    }
+
+    /// Manual transfers that were being sent, left over when the server stopped.
+    pub async fn get_processing_transfers(&self) -> anyhow::Result<Vec<Adjustment>> {
+        let adjustments = sqlx::query_as!(
+            Adjustment,
+            r#"
+            SELECT * FROM `adjustments`
+            WHERE kind = 'transfer' AND status = 'processing'
+            ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get processing transfers")?;
+        Ok(adjustments)
+    }
+
+    /// Marks a manual transfer with `status` as being sent. Returns false if someone else
+    /// took it, so it is never sent twice.
+    pub async fn claim_adjustment(&self, adjustment_id: i64, status: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE `adjustments` SET status = 'processing'
+            WHERE id = ? AND status = ?
+            "#,
+            adjustment_id,
+            status
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to claim adjustment {}", adjustment_id))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Posts an adjustment to the ledger, which adds it to the buyer's `received_spl_lamports`,
+    /// and marks it successful, at most once. Fails if the balance would become negative.
+    pub async fn apply_adjustment(
+        &self,
+        adjustment_id: i64,
+        transaction_id: Option<i64>,
+    ) -> anyhow::Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        let adjustment = sqlx::query_as!(
+            Adjustment,
+            r#"
+            SELECT * FROM `adjustments` WHERE id = ? FOR UPDATE
+            "#,
+            adjustment_id
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .context(format!("Failed to get adjustment {}", adjustment_id))?
+        .ok_or_else(|| anyhow::anyhow!("Adjustment {} not found", adjustment_id))?;
+        if adjustment.status == "success" {
+            return Ok(());
+        }
+
+        let wallet = adjustment.buyer_wallet.as_str();
+        lock_buyer(&mut tx, wallet).await?;
+        let received = Account::Buyer(wallet.to_string());
+        if account_balance(&mut tx, &received).await? + adjustment.amount_lamports < 0 {
+            anyhow::bail!(
+                "received_spl_lamports of buyer {} would become negative",
+                wallet
+            );
+        }
+        let movement = if adjustment.is_transfer() {
+            Movement::Transfer
+        } else {
+            Movement::Adjustment
+        };
+        let (debit, credit) = if adjustment.amount_lamports > 0 {
+            (&received, &Account::Treasury)
+        } else {
+            (&Account::Treasury, &received)
+        };
+        let entry = LedgerEntry::new(
+            movement,
+            debit,
+            credit,
+            adjustment.amount_lamports.unsigned_abs(),
+            Some(adjustment.group_id),
+            Some(wallet),
+        )
+        .with_reference(format!("adjustment:{}", adjustment.id));
+        post_entries(&mut tx, &[entry]).await?;
+        refresh_buyer_totals(&mut tx, wallet).await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE `adjustments`
+            SET status = 'success', transaction_id = ?, error_message = NULL
+            WHERE id = ?
+            "#,
+            transaction_id,
+            adjustment_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context(format!("Failed to update adjustment {}", adjustment_id))?;
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    pub async fn fail_adjustment(
+        &self,
+        adjustment_id: i64,
+        transaction_id: Option<i64>,
+        error_message: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE `adjustments`
+            SET status = 'failed', transaction_id = ?, error_message = ?
+            WHERE id = ? AND status <> 'success'
+            "#,
+            transaction_id,
+            error_message,
+            adjustment_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update adjustment {}", adjustment_id))?;
+        Ok(())
+    }
+
+    /// Marks a manual transfer that was being sent when it was interrupted. It may have
+    /// reached the chain, so it is left for an operator to resolve.
+    pub async fn interrupt_adjustment(
+        &self,
+        adjustment_id: i64,
+        error_message: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE `adjustments`
+            SET status = 'interrupted', error_message = ?
+            WHERE id = ? AND status = 'processing'
+            "#,
+            error_message,
+            adjustment_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update adjustment {}", adjustment_id))?;
+        Ok(())
+    }
+
+    pub async fn list_ledger(
+        &self,
+        filter: &LedgerFilter,
+        page: &PageQuery,
+        cursor: Option<&Cursor>,
+    ) -> anyhow::Result<Page<LedgerEntry>> {
+        let mut query = QueryBuilder::<MySql>::new("SELECT * FROM `ledger_entries` WHERE 1 = 1");
+        if let Some(wallet) = &filter.wallet {
+            query.push(" AND buyer_wallet = ").push_bind(wallet.clone());
+        }
+        if let Some(group_id) = filter.group_id {
+            query.push(" AND group_id = ").push_bind(group_id);
+        }
+        if let Some(movement) = &filter.movement {
+            query.push(" AND movement = ").push_bind(movement.clone());
+        }
+        if let Some(cursor) = cursor {
+            let key = id_key(cursor)?;
+            push_cursor(&mut query, "id", "id", page.order, cursor, key);
+        }
+        push_order(&mut query, "id", "id", page);
+
+        let entries = query
+            .build_query_as::<LedgerEntry>()
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list ledger entries")?;
+
+        Ok(Page::from_rows(entries, page.limit, LedgerEntry::cursor))
+    }
+
+    /// Debits minus credits of `account`.
+    pub async fn get_ledger_balance(&self, account: &Account) -> anyhow::Result<i64> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .context("Failed to get a database connection")?;
+        account_balance(&mut conn, account).await
+    }
+
+    /// Recomputes `received_spl_lamports` and `pending_spl_lamports` of every buyer and
+    /// `spl_total_lamports` of every group from the ledger. Returns how many buyers and
+    /// groups were out of line.
+    pub async fn rebuild_balances(&self) -> anyhow::Result<(u64, u64)> {
+        // Every group and buyer stays locked until all balances are written, so a transfer
+        // or edit running meanwhile waits instead of being overwritten with older sums.
+        // The rows are locked before the ledger is read, so the sums include its entries.
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+
+        let group_ids = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM `groups` ORDER BY id FOR UPDATE
+            "#
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to get groups")?;
+        let wallets = sqlx::query_scalar!(
+            r#"
+            SELECT wallet FROM `buyers` ORDER BY wallet FOR UPDATE
+            "#
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to get buyers")?;
+        let mut buyers = 0;
+        for wallet in wallets {
+            if refresh_buyer_totals(&mut tx, &wallet).await? {
+                buyers += 1;
+            }
+        }
+
+        let mut groups = 0;
+        for group_id in group_ids {
+            let funded = group_funding(&mut tx, group_id).await?;
+            let funded = funded.max(0) as u64;
+            // Only differing rows, rows_affected counts matched rows on MySQL
+            let result = sqlx::query!(
+                r#"
+                UPDATE `groups` SET spl_total_lamports = ?
+                WHERE id = ? AND spl_total_lamports <> ?
+                "#,
+                funded,
+                group_id,
+                funded
+            )
+            .execute(&mut *tx)
+            .await
+            .context(format!("Failed to update group {}", group_id))?;
+            groups += result.rows_affected();
+        }
+
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok((buyers, groups))
+    }
+
+    pub async fn save_approval(&self, approval: &Approval) -> anyhow::Result<i64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO `approvals` (
+                action, parameters, amount_lamports, status, requested_by, requested_by_id
+            )
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+            approval.action,
+            approval.parameters,
+            approval.amount_lamports,
+            approval.status,
+            approval.requested_by,
+            approval.requested_by_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!(
+            "Failed to save approval request `{}` of `{}`",
+            approval.action, approval.requested_by
+        ))?;
+        Ok(result.last_insert_id() as i64)
+    }
+
+    pub async fn get_approval(&self, approval_id: i64) -> anyhow::Result<Option<Approval>> {
+        let approval = sqlx::query_as!(
+            Approval,
+            r#"
+            SELECT * FROM `approvals` WHERE id = ?
+            "#,
+            approval_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get approval {}", approval_id))?;
+        Ok(approval)
+    }
+
+    /// Approval requests, newest first, optionally only those with `status`.
+    pub async fn get_approvals(&self, status: Option<&str>) -> anyhow::Result<Vec<Approval>> {
+        let approvals = sqlx::query_as!(
+            Approval,
+            r#"
+            SELECT * FROM `approvals`
+            WHERE ? IS NULL OR status = ?
+            ORDER BY id DESC
+            "#,
+            status,
+            status
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get approvals")?;
+        Ok(approvals)
+    }
+
+    /// Approves or rejects a pending request. Returns false if it was decided already,
+    /// so two approvers can't both run it.
+    pub async fn decide_approval(
+        &self,
+        approval_id: i64,
+        status: &str,
+        decided_by: &str,
+        reason: Option<&str>,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE `approvals`
+            SET status = ?, decided_by = ?, reason = ?, decided_at = ?
+            WHERE id = ? AND status = 'pending'
+            "#,
+            status,
+            decided_by,
+            reason,
+            chrono::Utc::now().naive_utc(),
+            approval_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to decide approval {}", approval_id))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Stores the outcome of an approved operation: `executed` or `failed`.
+    pub async fn finish_approval(
+        &self,
+        approval_id: i64,
+        status: &str,
+        result: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE `approvals` SET status = ?, result = ? WHERE id = ?
+            "#,
+            status,
+            result.to_string(),
+            approval_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to finish approval {}", approval_id))?;
+        Ok(())
+    }
+
+    pub async fn save_buyer_upload(&self, upload: &BuyerUpload) -> anyhow::Result<i64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO `buyer_uploads` (
+                sha256, filename, uploaded_by, mode, status, changes, summary
+            ) VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            upload.sha256,
+            upload.filename,
+            upload.uploaded_by,
+            upload.mode,
+            upload.status,
+            upload.changes,
+            upload.summary
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to save upload of file `{}`", upload.sha256))?;
+
+        Ok(result.last_insert_id() as i64)
+    }
+
+    pub async fn get_buyer_upload(&self, upload_id: i64) -> anyhow::Result<Option<BuyerUpload>> {
+        let row = sqlx::query_as!(
+            BuyerUpload,
+            r#"
+            SELECT * FROM `buyer_uploads` WHERE id = ?
+            "#,
+            upload_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get upload with id {}", upload_id))?;
+        Ok(row)
+    }
+
+    pub async fn get_buyer_upload_by_sha256(
+        &self,
+        sha256: &str,
+    ) -> anyhow::Result<Option<BuyerUpload>> {
+        let row = sqlx::query_as!(
+            BuyerUpload,
+            r#"
+            SELECT * FROM `buyer_uploads` WHERE sha256 = ?
+            "#,
+            sha256
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get upload of file `{}`", sha256))?;
+        Ok(row)
+    }
+
+    pub async fn get_all_buyer_uploads(&self) -> anyhow::Result<Vec<BuyerUpload>> {
+        let rows = sqlx::query_as!(
+            BuyerUpload,
+            r#"
+            SELECT * FROM `buyer_uploads` ORDER BY id DESC
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get buyer uploads")?;
+        Ok(rows)
+    }
+
+    /// Moves a pending sync upload to `applying`. Returns false if it isn't pending anymore,
+    /// so the changes of an upload are applied only once.
+    pub async fn claim_buyer_upload(&self, upload_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE `buyer_uploads` SET status = 'applying'
+            WHERE id = ? AND mode = 'sync' AND status = 'pending'
+            "#,
+            upload_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to claim upload {}", upload_id))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn update_buyer_upload_status(
+        &self,
+        upload_id: i64,
+        status: &str,
+        summary: Option<String>,
+    ) -> anyhow::Result<BuyerUpload> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE `buyer_uploads`
+            SET status = ?, summary = ?
+            WHERE id = ?
+            "#,
+            status,
+            summary,
+            upload_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update upload with id {}", upload_id))?;
+
+        if result.rows_affected() == 0 {
+            anyhow::bail!("No upload found with id {} to update", upload_id);
+        }
+
+        self.get_buyer_upload(upload_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Updated upload not found (id: {})", upload_id))
+    }
+    pub async fn save_webhook(
+        &self,
+        url: &str,
+        secret: &str,
+        events: &str,
+        created_by: Option<&str>,
+    ) -> anyhow::Result<i64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO `webhooks` (url, secret, events, created_by)
+            VALUES (?, ?, ?, ?)
+            "#,
+            url,
+            secret,
+            events,
+            created_by
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to save webhook")?;
+
+        Ok(result.last_insert_id() as i64)
+    }
+
+    pub async fn get_webhook(&self, webhook_id: i64) -> anyhow::Result<Option<Webhook>> {
+        let webhook = sqlx::query_as!(
+            Webhook,
+            r#"
+            SELECT
+                id, url, secret, events, active as `active: bool`,
+                created_by, created_at, updated_at
+            FROM `webhooks`
+            WHERE id = ?
+            "#,
+            webhook_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get webhook {}", webhook_id))?;
+        Ok(webhook)
+    }
+
+    pub async fn get_all_webhooks(&self) -> anyhow::Result<Vec<Webhook>> {
+        let webhooks = sqlx::query_as!(
+            Webhook,
+            r#"
+            SELECT
+                id, url, secret, events, active as `active: bool`,
+                created_by, created_at, updated_at
+            FROM `webhooks`
+            ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get webhooks")?;
+        Ok(webhooks)
+    }
+
+    pub async fn update_webhook(
+        &self,
+        webhook_id: i64,
+        url: &str,
+        events: &str,
+        active: bool,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE `webhooks`
+            SET url = ?, events = ?, active = ?
+            WHERE id = ?
+            "#,
+            url,
+            events,
+            active,
+            webhook_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update webhook {}", webhook_id))?;
+        Ok(())
+    }
+
+    pub async fn delete_webhook(&self, webhook_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM `webhooks` WHERE id = ?
+            "#,
+            webhook_id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to delete webhook {}", webhook_id))?;
+        Ok(())
+    }
+
+    pub async fn save_webhook_delivery(
+        &self,
+        webhook_id: i64,
+        event_type: &str,
+        payload: &str,
+        next_attempt_at: chrono::NaiveDateTime,
+    ) -> anyhow::Result<i64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO `webhook_deliveries` (webhook_id, event_type, payload, next_attempt_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+            webhook_id,
+            event_type,
+            payload,
+            next_attempt_at
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!(
+            "Failed to save delivery for webhook {}",
+            webhook_id
+        ))?;
+
+        Ok(result.last_insert_id() as i64)
+    }
+
+    pub async fn get_webhook_delivery(
+        &self,
+        delivery_id: i64,
+    ) -> anyhow::Result<Option<WebhookDelivery>> {
+        let delivery = sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+            SELECT * FROM `webhook_deliveries` WHERE id = ?
+            "#,
+            delivery_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context(format!("Failed to get webhook delivery {}", delivery_id))?;
+        Ok(delivery)
+    }
+
+    pub async fn get_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+        limit: u32,
+    ) -> anyhow::Result<Vec<WebhookDelivery>> {
+        let deliveries = sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+            SELECT * FROM `webhook_deliveries`
+            WHERE webhook_id = ?
+            ORDER BY id DESC
+            LIMIT ?
+            "#,
+            webhook_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context(format!(
+            "Failed to get deliveries of webhook {}",
+            webhook_id
+        ))?;
+        Ok(deliveries)
+    }
+
+    /// Pending deliveries whose next attempt is due, oldest first.
+    pub async fn get_due_webhook_deliveries(
+        &self,
+        now: chrono::NaiveDateTime,
+        limit: u32,
+    ) -> anyhow::Result<Vec<WebhookDelivery>> {
+        let deliveries = sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+            SELECT * FROM `webhook_deliveries`
+            WHERE status = 'pending' AND next_attempt_at <= ?
+            ORDER BY next_attempt_at
+            LIMIT ?
+            "#,
+            now,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get due webhook deliveries")?;
+        Ok(deliveries)
+    }
+
+    /// Stores the result of a delivery attempt.
+    pub async fn update_webhook_delivery(&self, delivery: &WebhookDelivery) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE `webhook_deliveries`
+            SET status = ?, attempts = ?, last_status_code = ?, last_error = ?,
+                next_attempt_at = ?, delivered_at = ?
+            WHERE id = ?
+            "#,
+            delivery.status,
+            delivery.attempts,
+            delivery.last_status_code,
+            delivery.last_error,
+            delivery.next_attempt_at,
+            delivery.delivered_at,
+            delivery.id
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to update webhook delivery {}", delivery.id))?;
+        Ok(())
+    }
+
+    pub async fn save_audit_entry(&self, entry: &AuditEntry) -> anyhow::Result<i64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO `audit_log` (
+                source, actor, action, parameters, result, status_code, result_message, ip_address
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            entry.source,
+            entry.actor,
+            entry.action,
+            entry.parameters,
+            entry.result,
+            entry.status_code,
+            entry.result_message,
+            entry.ip_address
+        )
+        .execute(&self.pool)
+        .await
+        .context(format!("Failed to save audit entry `{}`", entry.action))?;
+
+        Ok(result.last_insert_id() as i64)
+    }
+
+    pub async fn list_audit_log(
+        &self,
+        filter: &AuditFilter,
+        page: &PageQuery,
+        cursor: Option<&Cursor>,
+    ) -> anyhow::Result<Page<AuditEntry>> {
+        let mut query = QueryBuilder::<MySql>::new("SELECT * FROM `audit_log` WHERE 1 = 1");
+        if let Some(actor) = &filter.actor {
+            query.push(" AND actor = ").push_bind(actor.clone());
+        }
+        if let Some(action) = &filter.action {
+            query
+                .push(" AND action LIKE ")
+                .push_bind(format!("%{}%", action));
+        }
+        if let Some(source) = &filter.source {
+            query.push(" AND source = ").push_bind(source.clone());
+        }
+        if let Some(result) = &filter.result {
+            query.push(" AND result = ").push_bind(result.clone());
+        }
+        if let Some(ip_address) = &filter.ip_address {
+            query
+                .push(" AND ip_address = ")
+                .push_bind(ip_address.clone());
+        }
+        if let Some(from) = filter.from {
+            query.push(" AND created_at >= ").push_bind(from);
+        }
+        if let Some(to) = filter.to {
+            query.push(" AND created_at <= ").push_bind(to);
+        }
+        if let Some(cursor) = cursor {
+            let key = id_key(cursor)?;
+            push_cursor(&mut query, "id", "id", page.order, cursor, key);
+        }
+        push_order(&mut query, "id", "id", page);
+
+        let entries = query
+            .build_query_as::<AuditEntry>()
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list audit log")?;
+
+        Ok(Page::from_rows(entries, page.limit, AuditEntry::cursor))
+    }
+
+    /// Sum of all schedules that are not sent yet.
+    pub async fn get_unsent_schedules_amount(&self) -> anyhow::Result<u64> {
+        let amount = sqlx::query_scalar!(
+            r#"
+            SELECT CAST(COALESCE(SUM(amount_lamports), 0) AS UNSIGNED) AS `amount!: u64`
+            FROM `schedule`
+            WHERE status != 'success'
+            "#
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to sum unsent schedules")?;
+        Ok(amount)
+    }
+
+    pub async fn get_schedule_backlog(
+        &self,
+        now: chrono::NaiveDateTime,
+    ) -> anyhow::Result<Vec<ScheduleBacklog>> {
+        let backlog = sqlx::query_as!(
+            ScheduleBacklog,
+            r#"
+            SELECT
+                status,
+                COUNT(*) AS `schedules!: i64`,
+                MIN(scheduled_at) AS `oldest_scheduled_at: chrono::NaiveDateTime`
+            FROM `schedule`
+            WHERE status != 'success' AND scheduled_at <= ?
+            GROUP BY status
+            "#,
+            now
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get schedule backlog")?;
+        Ok(backlog)
+    }
+
+    /// Aggregates distribution progress of one group, or of all groups when `group_id` is `None`.
+    pub async fn get_distribution_stats(
+        &self,
+        group_id: Option<i64>,
+        window: StatsWindow,
+    ) -> anyhow::Result<DistributionStats> {
+        let spl_total = sqlx::query_scalar!(
+            r#"
+            SELECT CAST(COALESCE(SUM(spl_total_lamports), 0) AS UNSIGNED) AS `spl_total!: u64`
+            FROM `groups`
+            WHERE (? IS NULL OR id = ?)
+            "#,
+            group_id,
+            group_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to sum group totals")?;
+
+        let buyers = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) AS `total!: i64`,
+                CAST(COALESCE(SUM(b.paid_lamports DIV g.spl_price_lamports), 0) AS UNSIGNED)
+                    AS `allocated!: u64`,
+                CAST(COALESCE(SUM(b.received_spl_lamports), 0) AS UNSIGNED) AS `distributed!: u64`,
+                CAST(COALESCE(SUM(b.received_spl_lamports = 0), 0) AS SIGNED) AS `not_started!: i64`,
+                CAST(COALESCE(SUM(
+                    b.received_spl_lamports > 0
+                    AND b.received_spl_lamports < b.paid_lamports DIV g.spl_price_lamports
+                ), 0) AS SIGNED) AS `in_progress!: i64`,
+                CAST(COALESCE(SUM(
+                    b.received_spl_lamports > 0
+                    AND b.received_spl_lamports >= b.paid_lamports DIV g.spl_price_lamports
+                ), 0) AS SIGNED) AS `completed!: i64`
+            FROM `buyers` b
+            JOIN `groups` g ON g.id = b.group_id
+            WHERE (? IS NULL OR b.group_id = ?)
+            "#,
+            group_id,
+            group_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to aggregate buyers")?;
+
+        let overdue = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) AS `schedules!: i64`,
+                CAST(COALESCE(SUM(amount_lamports), 0) AS UNSIGNED) AS `amount_lamports!: u64`
+            FROM `schedule`
+            WHERE status != 'success' AND scheduled_at < ? AND (? IS NULL OR group_id = ?)
+            "#,
+            window.now,
+            group_id,
+            group_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to aggregate overdue schedules")?;
+
+        let upcoming = sqlx::query!(
+            r#"
+            SELECT
+                CASE WHEN ? = 'week'
+                    THEN DATE_SUB(DATE(scheduled_at), INTERVAL WEEKDAY(scheduled_at) DAY)
+                    ELSE DATE(scheduled_at)
+                END AS `period_start!: chrono::NaiveDate`,
+                COUNT(*) AS `schedules!: i64`,
+                CAST(COALESCE(SUM(amount_lamports), 0) AS UNSIGNED) AS `amount_lamports!: u64`
+            FROM `schedule`
+            WHERE status != 'success'
+                AND scheduled_at >= ? AND scheduled_at < ?
+                AND (? IS NULL OR group_id = ?)
+            GROUP BY period_start
+            ORDER BY period_start
+            "#,
+            window.bucket.as_str(),
+            window.now,
+            window.until,
+            group_id,
+            group_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to aggregate upcoming schedules")?;
+
+        let schedules = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) AS `total!: i64`,
+                CAST(COALESCE(SUM(status = 'failed'), 0) AS SIGNED) AS `failed!: i64`
+            FROM `schedule`
+            WHERE (? IS NULL OR group_id = ?)
+            "#,
+            group_id,
+            group_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to aggregate schedule failures")?;
+
+        let transactions = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) AS `total!: i64`,
+                CAST(COALESCE(SUM(status = 'failed'), 0) AS SIGNED) AS `failed!: i64`
+            FROM `transactions`
+            WHERE (? IS NULL OR group_id = ?)
+            "#,
+            group_id,
+            group_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to aggregate transaction failures")?;
+
+        Ok(DistributionStats {
+            group_id,
+            tokens: TokenStats {
+                spl_total_lamports: spl_total,
+                allocated_lamports: buyers.allocated,
+                distributed_lamports: buyers.distributed,
+                remaining_lamports: spl_total.saturating_sub(buyers.distributed),
+                unallocated_lamports: spl_total.saturating_sub(buyers.allocated),
+                outstanding_lamports: buyers.allocated.saturating_sub(buyers.distributed),
+            },
+            buyers: BuyerStats {
+                total: buyers.total,
+                not_started: buyers.not_started,
+                in_progress: buyers.in_progress,
+                completed: buyers.completed,
+            },
+            overdue: UnlockBucket {
+                period_start: None,
+                schedules: overdue.schedules,
+                amount_lamports: overdue.amount_lamports,
+            },
+            upcoming: upcoming
+                .into_iter()
+                .map(|row| UnlockBucket {
+                    period_start: Some(row.period_start),
+                    schedules: row.schedules,
+                    amount_lamports: row.amount_lamports,
+                })
+                .collect(),
+            failures: FailureStats::new(
+                schedules.total,
+                schedules.failed,
+                transactions.total,
+                transactions.failed,
+            ),
+        })
+    }
+
+    /// Checks every distribution invariant. The sums are compared by the database, which
+    /// returns only the buyers, schedules and groups that break a rule. The queries run in
+    /// one transaction, so they all read the same snapshot.
+    pub async fn check_invariants(&self) -> anyhow::Result<InvariantReport> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+
+        let checked = sqlx::query!(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM `buyers`) AS `buyers!: i64`,
+                (SELECT COUNT(*) FROM `groups`) AS `groups!: i64`
+            "#
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to count buyers and groups")?;
+        let mut report = InvariantReport::new(checked.buyers as usize, checked.groups as usize);
+
+        // Ledger accounts as written by `Account::Buyer` and `Account::BuyerPending`
+        let buyers = sqlx::query_as!(
+            BuyerTotals,
+            r#"
+            WITH
+            balances AS (
+                SELECT account, SUM(amount) AS balance
+                FROM (
+                    SELECT debit_account AS account, CAST(amount_lamports AS SIGNED) AS amount
+                    FROM `ledger_entries`
+                    WHERE debit_account LIKE 'buyer:%'
+                    UNION ALL
+                    SELECT credit_account, -CAST(amount_lamports AS SIGNED)
+                    FROM `ledger_entries`
+                    WHERE credit_account LIKE 'buyer:%'
+                ) flows
+                GROUP BY account
+            ),
+            planned AS (
+                SELECT
+                    buyer_wallet,
+                    SUM(amount_lamports) AS scheduled,
+                    SUM(CASE WHEN status = 'success' THEN amount_lamports ELSE 0 END) AS vested
+                FROM `schedule`
+                GROUP BY buyer_wallet
+            ),
+            succeeded AS (
+                SELECT
+                    buyer_wallet,
+                    id,
+                    percent,
+                    ROW_NUMBER() OVER (PARTITION BY buyer_wallet ORDER BY percent DESC, id DESC) AS n
+                FROM `schedule`
+                WHERE status = 'success'
+            ),
+            manual AS (
+                SELECT buyer_wallet, SUM(amount_lamports) AS sent
+                FROM `transactions`
+                WHERE schedule_id IS NULL AND status = 'success'
+                GROUP BY buyer_wallet
+            ),
+            transferred AS (
+                SELECT buyer_wallet, SUM(ABS(amount_lamports)) AS transferred
+                FROM `adjustments`
+                WHERE kind = 'transfer' AND status = 'success'
+                GROUP BY buyer_wallet
+            ),
+            totals AS (
+                SELECT
+                    b.wallet,
+                    b.received_spl_lamports,
+                    b.pending_spl_lamports,
+                    b.received_percent,
+                    CAST(COALESCE(b.paid_lamports DIV NULLIF(g.spl_price_lamports, 0), 0) AS SIGNED)
+                        AS allocation,
+                    CAST(COALESCE(p.scheduled, 0) AS SIGNED) AS scheduled,
+                    CAST(COALESCE(p.vested, 0) AS SIGNED) AS vested,
+                    s.id AS last_schedule_id,
+                    s.percent AS last_percent,
+                    CAST(COALESCE(m.sent, 0) AS SIGNED) AS sent_manually,
+                    CAST(COALESCE(t.transferred, 0) AS SIGNED) AS transferred,
+                    CAST(COALESCE(r.balance, 0) AS SIGNED) AS received_balance,
+                    CAST(COALESCE(pb.balance, 0) AS SIGNED) AS pending_balance
+                FROM `buyers` b
+                JOIN `groups` g ON g.id = b.group_id
+                LEFT JOIN planned p ON p.buyer_wallet = b.wallet
+                LEFT JOIN succeeded s ON s.buyer_wallet = b.wallet AND s.n = 1
+                LEFT JOIN manual m ON m.buyer_wallet = b.wallet
+                LEFT JOIN transferred t ON t.buyer_wallet = b.wallet
+                LEFT JOIN balances r ON r.account = CONCAT('buyer:', b.wallet)
+                LEFT JOIN balances pb ON pb.account = CONCAT('buyer:', b.wallet, ':pending')
+            )
+            SELECT
+                wallet AS `wallet!: String`,
+                received_spl_lamports AS `received_spl_lamports!: u64`,
+                pending_spl_lamports AS `pending_spl_lamports!: u64`,
+                received_percent AS `received_percent!: f64`,
+                CAST(allocation AS UNSIGNED) AS `allocation!: u64`,
+                CAST(scheduled AS UNSIGNED) AS `scheduled!: u64`,
+                CAST(vested AS UNSIGNED) AS `vested!: u64`,
+                last_schedule_id AS `last_schedule_id?: i64`,
+                last_percent AS `last_percent?: f64`,
+                CAST(sent_manually AS UNSIGNED) AS `sent_manually!: u64`,
+                CAST(transferred AS UNSIGNED) AS `transferred!: u64`,
+                received_balance AS `received_balance!: i64`,
+                pending_balance AS `pending_balance!: i64`
+            FROM totals
+            WHERE scheduled <> allocation
+                OR sent_manually <> transferred
+                OR ABS(received_percent - last_percent) > ?
+                OR pending_spl_lamports <> GREATEST(allocation - vested, 0)
+                OR received_spl_lamports <> received_balance
+                OR pending_spl_lamports <> pending_balance
+            ORDER BY wallet
+            "#,
+            PERCENT_TOLERANCE
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to check buyer totals")?;
+        for buyer in &buyers {
+            report.push_wallet(buyer.wallet.clone(), buyer.violations());
+        }
+
+        let duplicates = sqlx::query_as!(
+            DuplicatePercent,
+            r#"
+            SELECT
+                s.buyer_wallet,
+                CAST(ROUND(s.percent * 1000000) AS UNSIGNED) AS `percent_key!: u64`,
+                GROUP_CONCAT(s.id ORDER BY s.id) AS `schedule_ids!: String`
+            FROM `schedule` s
+            JOIN `buyers` b ON b.wallet = s.buyer_wallet
+            JOIN `groups` g ON g.id = b.group_id
+            GROUP BY s.buyer_wallet, percent_key
+            HAVING COUNT(*) > 1
+            ORDER BY s.buyer_wallet, percent_key
+            "#
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to check schedule percents")?;
+        for duplicate in &duplicates {
+            report.push_wallet(duplicate.buyer_wallet.clone(), vec![duplicate.violation()]);
+        }
+
+        let schedules = sqlx::query_as!(
+            ScheduleSends,
+            r#"
+            SELECT
+                s.id,
+                s.buyer_wallet,
+                s.status,
+                s.amount_lamports,
+                COUNT(t.id) AS `successes!: i64`,
+                MIN(t.id) AS `transaction_id?: i64`,
+                CAST(COALESCE(SUM(t.amount_lamports), 0) AS UNSIGNED) AS `sent_lamports!: u64`
+            FROM `schedule` s
+            JOIN `buyers` b ON b.wallet = s.buyer_wallet
+            JOIN `groups` g ON g.id = b.group_id
+            LEFT JOIN `transactions` t ON t.schedule_id = s.id AND t.status = 'success'
+            GROUP BY s.id, s.buyer_wallet, s.status, s.amount_lamports
+            HAVING (s.status = 'success' AND (COUNT(t.id) <> 1 OR SUM(t.amount_lamports) <> s.amount_lamports))
+                OR (s.status <> 'success' AND COUNT(t.id) > 0)
+            ORDER BY s.buyer_wallet, s.id
+            "#
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to check schedule transactions")?;
+        for schedule in &schedules {
+            if let Some(violation) = schedule.violation() {
+                report.push_wallet(schedule.buyer_wallet.clone(), vec![violation]);
+            }
+        }
+
+        let treasury = Account::Treasury.to_string();
+        // Group accounts as written by `Account::Group`
+        let groups = sqlx::query_as!(
+            GroupTotals,
+            r#"
+            WITH
+            allocated AS (
+                SELECT
+                    b.group_id,
+                    SUM(COALESCE(b.paid_lamports DIV NULLIF(g.spl_price_lamports, 0), 0)) AS allocated
+                FROM `buyers` b
+                JOIN `groups` g ON g.id = b.group_id
+                GROUP BY b.group_id
+            ),
+            funding AS (
+                SELECT account, SUM(amount) AS funding
+                FROM (
+                    SELECT debit_account AS account, CAST(amount_lamports AS SIGNED) AS amount
+                    FROM `ledger_entries`
+                    WHERE credit_account = ? AND debit_account LIKE 'group:%'
+                    UNION ALL
+                    SELECT credit_account, -CAST(amount_lamports AS SIGNED)
+                    FROM `ledger_entries`
+                    WHERE debit_account = ? AND credit_account LIKE 'group:%'
+                ) flows
+                GROUP BY account
+            )
+            SELECT
+                g.id,
+                g.spl_total_lamports,
+                CAST(COALESCE(a.allocated, 0) AS UNSIGNED) AS `allocated!: u64`,
+                CAST(COALESCE(f.funding, 0) AS SIGNED) AS `funding!: i64`
+            FROM `groups` g
+            LEFT JOIN allocated a ON a.group_id = g.id
+            LEFT JOIN funding f ON f.account = CONCAT('group:', g.id)
+            WHERE COALESCE(a.allocated, 0) > g.spl_total_lamports
+                OR CAST(g.spl_total_lamports AS SIGNED) <> COALESCE(f.funding, 0)
+            ORDER BY g.id
+            "#,
+            treasury,
+            treasury
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to check group totals")?;
+        for group in &groups {
+            report.push_group(group.id, group.violations());
+        }
+
+        tx.commit().await.context("Failed to commit transaction")?;
+        Ok(report)
+    }
+}
+
+/// Appends entries to the ledger. Entries without an amount are skipped.
+async fn post_entries(conn: &mut MySqlConnection, entries: &[LedgerEntry]) -> anyhow::Result<()> {
+    for entry in entries.iter().filter(|e| e.amount_lamports > 0) {
+        sqlx::query!(
+            r#"
+            INSERT INTO `ledger_entries` (
+                movement, debit_account, credit_account, amount_lamports, group_id,
+                buyer_wallet, reference
+            ) VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            entry.movement,
+            entry.debit_account,
+            entry.credit_account,
+            entry.amount_lamports,
+            entry.group_id,
+            entry.buyer_wallet,
+            entry.reference
+        )
+        .execute(&mut *conn)
+        .await
+        .context(format!(
+            "Failed to post {} of {} from {} to {}",
+            entry.movement, entry.amount_lamports, entry.credit_account, entry.debit_account
+        ))?;
+    }
+    Ok(())
+}
+
+async fn account_balance(conn: &mut MySqlConnection, account: &Account) -> anyhow::Result<i64> {
+    let account = account.to_string();
+    let balance = sqlx::query_scalar!(
+        r#"
+        SELECT CAST(
+            COALESCE(SUM(CASE WHEN debit_account = ? THEN amount_lamports ELSE 0 END), 0)
+            - COALESCE(SUM(CASE WHEN credit_account = ? THEN amount_lamports ELSE 0 END), 0)
+        AS SIGNED) AS `balance!: i64`
+        FROM `ledger_entries`
+        WHERE debit_account = ? OR credit_account = ?
+        "#,
+        account,
+        account,
+        account,
+        account
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .context(format!("Failed to get balance of {}", account))?;
+    Ok(balance)
+}
+
+/// Tokens the treasury moved to a group minus those it got back.
+async fn group_funding(conn: &mut MySqlConnection, group_id: i64) -> anyhow::Result<i64> {
+    let group = Account::Group(group_id).to_string();
+    let treasury = Account::Treasury.to_string();
+    let funding = sqlx::query_scalar!(
+        r#"
+        SELECT CAST(
+            COALESCE(SUM(CASE WHEN debit_account = ? THEN amount_lamports ELSE 0 END), 0)
+            - COALESCE(SUM(CASE WHEN credit_account = ? THEN amount_lamports ELSE 0 END), 0)
+        AS SIGNED) AS `funding!: i64`
+        FROM `ledger_entries`
+        WHERE (debit_account = ? AND credit_account = ?)
+            OR (debit_account = ? AND credit_account = ?)
+        "#,
+        group,
+        group,
+        group,
+        treasury,
+        treasury,
+        group
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .context(format!("Failed to get funding of group {}", group_id))?;
+    Ok(funding)
+}
+
+/// Stores a snapshot of `group` as its next version. The versions of the group are locked
+/// while the next number is picked, so two changes never get the same one.
+async fn insert_group_version(
+    conn: &mut MySqlConnection,
+    group: &Group,
+    change_type: &str,
+    changed_by: Option<&str>,
+) -> anyhow::Result<i64> {
+    let last_version = sqlx::query_scalar!(
+        r#"
+        SELECT MAX(version) FROM `group_versions` WHERE group_id = ? FOR UPDATE
+        "#,
+        group.id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .context(format!("Failed to get last version of group {}", group.id))?;
+    let version = last_version.unwrap_or(0) + 1;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO `group_versions` (
+            group_id, version, change_type, spl_share_percent, spl_total_lamports,
+            spl_price_lamports, initial_unlock_percent, unlock_interval_seconds,
+            unlock_percent_per_interval, changed_by
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        group.id,
+        version,
+        change_type,
+        group.spl_share_percent,
+        group.spl_total_lamports,
+        group.spl_price_lamports,
+        group.initial_unlock_percent,
+        group.unlock_interval_seconds,
+        group.unlock_percent_per_interval,
+        changed_by
+    )
+    .execute(&mut *conn)
+    .await
+    .context(format!(
+        "Failed to save version {} of group {}",
+        version, group.id
+    ))?;
+
+    Ok(version)
+}
+
+/// Locks the row of a buyer for the transaction and returns their group.
+async fn lock_buyer(conn: &mut MySqlConnection, wallet: &str) -> anyhow::Result<i64> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT group_id FROM `buyers` WHERE wallet = ? FOR UPDATE
+        "#,
+        wallet
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .context(format!("Failed to get buyer `{}`", wallet))?
+    .ok_or_else(|| anyhow::anyhow!("No buyer found with wallet `{}`", wallet))
+}
+
+/// Locks the row of a buyer for the transaction and returns it.
+async fn lock_buyer_row(conn: &mut MySqlConnection, wallet: &str) -> anyhow::Result<Buyer> {
+    let row = sqlx::query("SELECT * FROM `buyers` WHERE wallet = ? FOR UPDATE")
+        .bind(wallet)
+        .fetch_optional(&mut *conn)
+        .await
+        .context(format!("Failed to get buyer `{}`", wallet))?
+        .ok_or_else(|| anyhow::anyhow!("No buyer found with wallet `{}`", wallet))?;
+    buyer_from_row(&row)
+}
+
+/// Allocates or refunds the difference to bring a buyer's pending allocation to
+/// `pending_spl_lamports`, leaving the received balance alone.
+async fn set_buyer_pending(
+    conn: &mut MySqlConnection,
+    wallet: &str,
+    group_id: i64,
+    pending_spl_lamports: u64,
+) -> anyhow::Result<()> {
+    let current_pending = account_balance(conn, &Account::BuyerPending(wallet.to_string())).await?;
+    let change = pending_change(
+        wallet,
+        group_id,
+        pending_spl_lamports as i64 - current_pending,
+    );
+    post_entries(conn, &[change]).await?;
+    refresh_buyer_totals(conn, wallet).await?;
+    Ok(())
+}
+
+/// Whether anything may have been sent to the buyer or is queued for them: a schedule that
+/// left `pending`, a transaction or a manual transfer or adjustment.
+async fn has_transfers(conn: &mut MySqlConnection, wallet: &str) -> anyhow::Result<bool> {
+    let found = sqlx::query_scalar!(
+        r#"
+        SELECT (
+            EXISTS(SELECT 1 FROM `schedule` WHERE buyer_wallet = ? AND status <> 'pending')
+            OR EXISTS(SELECT 1 FROM `transactions` WHERE buyer_wallet = ?)
+            OR EXISTS(SELECT 1 FROM `adjustments` WHERE buyer_wallet = ?)
+        ) AS `found!: bool`
+        "#,
+        wallet,
+        wallet,
+        wallet
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .context(format!("Failed to check transfers of buyer `{}`", wallet))?;
+    Ok(found)
+}
+
+/// Deletes the `pending` schedules of the buyer in `group_id` and saves the ones `plan`
+/// returns for the schedules that stay, together with the new pending allocation. The
+/// buyer row must already be locked. Returns the removed and the created schedules.
+async fn replace_pending_schedules<F>(
+    conn: &mut MySqlConnection,
+    buyer: &Buyer,
+    group_id: i64,
+    plan: F,
+) -> anyhow::Result<(Vec<Schedule>, Vec<Schedule>)>
+where
+    F: FnOnce(&Buyer, &[Schedule]) -> (u64, Vec<Schedule>),
+{
+    let wallet = buyer.wallet.to_string();
+    let (removed, kept): (Vec<Schedule>, Vec<Schedule>) = sqlx::query_as!(
+        Schedule,
+        r#"
+        SELECT * FROM `schedule` WHERE buyer_wallet = ? AND group_id = ? FOR UPDATE
+        "#,
+        wallet,
+        group_id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .context(format!("Failed to lock schedules of buyer `{}`", wallet))?
+    .into_iter()
+    .partition(|s| s.status == "pending");
+    let (pending_spl_lamports, mut created) = plan(buyer, &kept);
+
+    sqlx::query!(
+        r#"
+        DELETE FROM `schedule`
+        WHERE buyer_wallet = ? AND group_id = ? AND status = 'pending'
+        "#,
+        wallet,
+        group_id
+    )
+    .execute(&mut *conn)
+    .await
+    .context(format!(
+        "Failed to delete pending schedules of buyer `{}`",
+        wallet
+    ))?;
+    set_buyer_pending(conn, &wallet, buyer.group_id, pending_spl_lamports).await?;
+    for schedule in created.iter_mut() {
+        schedule.id = insert_schedule(conn, schedule).await?;
+    }
+    Ok((removed, created))
+}
+
+async fn insert_schedule(conn: &mut MySqlConnection, schedule: &Schedule) -> anyhow::Result<i64> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO `schedule` (
+            group_id, buyer_wallet, scheduled_at, amount_lamports, percent, status
+        ) VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+        schedule.group_id,
+        schedule.buyer_wallet,
+        schedule.scheduled_at,
+        schedule.amount_lamports,
+        schedule.percent,
+        schedule.status
+    )
+    .execute(&mut *conn)
+    .await
+    .context(format!(
+        "Failed to insert schedule for group_id={} buyer={}",
+        schedule.group_id, schedule.buyer_wallet
+    ))?;
+    Ok(result.last_insert_id() as i64)
+}
+
+/// Writes the ledger balances of a buyer to `received_spl_lamports` and
+/// `pending_spl_lamports`. Returns whether they changed.
+async fn refresh_buyer_totals(conn: &mut MySqlConnection, wallet: &str) -> anyhow::Result<bool> {
+    let received = account_balance(conn, &Account::Buyer(wallet.to_string())).await?;
+    let pending = account_balance(conn, &Account::BuyerPending(wallet.to_string())).await?;
+    let (received, pending) = (received.max(0) as u64, pending.max(0) as u64);
+    // Only a differing row, rows_affected counts matched rows on MySQL
+    let result = sqlx::query!(
+        r#"
+        UPDATE `buyers` SET received_spl_lamports = ?, pending_spl_lamports = ?
+        WHERE wallet = ? AND (received_spl_lamports <> ? OR pending_spl_lamports <> ?)
+        "#,
+        received,
+        pending,
+        wallet,
+        received,
+        pending
+    )
+    .execute(&mut *conn)
+    .await
+    .context(format!("Failed to update balances of buyer `{}`", wallet))?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Allocation from the group for a positive `difference`, refund to it for a negative one.
+fn pending_change(wallet: &str, group_id: i64, difference: i64) -> LedgerEntry {
+    let pending = Account::BuyerPending(wallet.to_string());
+    if difference >= 0 {
+        LedgerEntry::new(
+            Movement::Allocation,
+            &pending,
+            &Account::Group(group_id),
+            difference as u64,
+            Some(group_id),
+            Some(wallet),
+        )
+    } else {
+        LedgerEntry::new(
+            Movement::Refund,
+            &Account::Group(group_id),
+            &pending,
+            difference.unsigned_abs(),
+            Some(group_id),
+            Some(wallet),
+        )
+    }
+}
+
+fn buyer_from_row(row: &MySqlRow) -> anyhow::Result<Buyer> {
+    let wallet: String = row.try_get("wallet")?;
+    let wallet_pk = Pubkey::from_str(&wallet)
+        .with_context(|| format!("Failed to read buyer. Invalid Pubkey `{}`", wallet))?;
+    Ok(Buyer {
+        wallet: wallet_pk,
+        paid_lamports: row.try_get("paid_lamports")?,
+        group_id: row.try_get("group_id")?,
+        received_spl_lamports: row.try_get("received_spl_lamports")?,
+        received_percent: row.try_get("received_percent")?,
+        pending_spl_lamports: row.try_get("pending_spl_lamports")?,
+        error: row.try_get("error")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn id_key(cursor: &Cursor) -> anyhow::Result<SortValue> {
+    let id = cursor
+        .key
+        .parse::<u64>()
+        .map_err(|_| anyhow::anyhow!("Invalid cursor key `{}`", cursor.key))?;
+    Ok(SortValue::Unsigned(id))
+}
+
+fn push_sort_value(query: &mut QueryBuilder<'_, MySql>, value: &SortValue) {
+    match value {
+        SortValue::Unsigned(v) => query.push_bind(*v),
+        SortValue::Float(v) => query.push_bind(*v),
+        SortValue::Time(v) => query.push_bind(*v),
+        SortValue::Text(v) => query.push_bind(v.clone()),
+    };
+}
+
+/// Keyset condition: rows after `(sort value, key)` of the cursor in the requested order.
+fn push_cursor(
+    query: &mut QueryBuilder<'_, MySql>,
+    column: &str,
+    key_column: &str,
+    order: SortOrder,
+    cursor: &Cursor,
+    key: SortValue,
+) {
+    let after = order.after_sql();
+    if column == key_column {
+        query.push(format!(" AND {} {} ", key_column, after));
+        push_sort_value(query, &key);
+        return;
+    }
+    query.push(format!(" AND ({} {} ", column, after));
+    push_sort_value(query, &cursor.value);
+    query.push(format!(" OR ({} = ", column));
+    push_sort_value(query, &cursor.value);
+    query.push(format!(" AND {} {} ", key_column, after));
+    push_sort_value(query, &key);
+    query.push("))");
+}
+
+fn push_order(
+    query: &mut QueryBuilder<'_, MySql>,
+    column: &str,
+    key_column: &str,
+    page: &PageQuery,
+) {
+    let direction = page.order.as_sql();
+    if column == key_column {
+        query.push(format!(" ORDER BY {} {}", key_column, direction));
+    } else {
+        query.push(format!(
+            " ORDER BY {} {}, {} {}",
+            column, direction, key_column, direction
+        ));
+    }
+    // One extra row tells whether there is a next page
+    query.push(" LIMIT ").push_bind(page.limit + 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Invariant;
+
+    async fn setup(pool: MySqlPool) -> (Database, Group, Buyer) {
+        let db = Database::from_pool(pool);
+        let group = Group {
+            id: 1,
+            spl_share_percent: 0.5,
+            spl_total_lamports: 1_000_000,
+            spl_price_lamports: 1,
+            initial_unlock_percent: 0.25,
+            unlock_interval_seconds: 60,
+            unlock_percent_per_interval: 0.25,
+            created_at: None,
+            updated_at: None,
+        };
+        db.save_group(&group).await.unwrap();
+        let buyer = Buyer {
+            wallet: Pubkey::new_unique(),
+            paid_lamports: 1000,
+            group_id: group.id,
+            received_spl_lamports: 0,
+            received_percent: 0.0,
+            pending_spl_lamports: 1000,
+            error: None,
+            created_at: None,
+            updated_at: None,
+        };
+        db.save_buyer(&buyer).await.unwrap();
+        (db, group, buyer)
+    }
+
+    /// Plans the rest of the allocation as one tranche after the kept schedules.
+    fn plan_rest(buyer: &Buyer, kept: &[Schedule]) -> (u64, Vec<Schedule>) {
+        let planned: u64 = kept.iter().map(|s| s.amount_lamports).sum();
+        let rest = Schedule::new(
+            buyer.group_id,
+            buyer.wallet.to_string(),
+            chrono::Utc::now().naive_utc(),
+            buyer.paid_lamports - planned,
+            1.0,
+        );
+        (buyer.paid_lamports, vec![rest])
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn replan_during_tick_keeps_claimed_schedule(pool: MySqlPool) {
+        let (db, group, buyer) = setup(pool).await;
+        let wallet = buyer.wallet.to_string();
+        let now = chrono::Utc::now().naive_utc();
+        let mut due = Vec::new();
+        for percent in [0.5, 1.0] {
+            let mut schedule = Schedule::new(group.id, wallet.clone(), now, 500, percent);
+            schedule.id = db.save_schedule(&schedule).await.unwrap();
+            due.push(schedule);
+        }
+
+        // The runner loaded both schedules and is sending the first one
+        assert!(db.claim_schedule(due[0].id, "pending").await.unwrap());
+        let (removed, created) = db
+            .replan_schedules(&wallet, group.id, plan_rest)
+            .await
+            .unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].id, due[1].id);
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].amount_lamports, 500);
+
+        // The replaced schedule is gone, the runner must not send it anymore
+        assert!(!db.claim_schedule(due[1].id, "pending").await.unwrap());
+        assert!(!db.claim_schedule(due[0].id, "pending").await.unwrap());
+
+        let schedules = db
+            .get_schedules_by_buyer_and_group(&wallet, group.id)
+            .await
+            .unwrap();
+        let total: u64 = schedules.iter().map(|s| s.amount_lamports).sum();
+        assert_eq!(total, buyer.paid_lamports);
+        assert!(
+            schedules
+                .iter()
+                .any(|s| s.id == due[0].id && s.status == "processing")
+        );
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn replan_keeps_failed_schedules(pool: MySqlPool) {
+        let (db, group, buyer) = setup(pool).await;
+        let wallet = buyer.wallet.to_string();
+        let schedule = Schedule::new(
+            group.id,
+            wallet.clone(),
+            chrono::Utc::now().naive_utc(),
+            250,
+            0.25,
+        );
+        let failed_id = db.save_schedule(&schedule).await.unwrap();
+        db.update_schedule_status(failed_id, "failed", Some("timeout".to_string()))
+            .await
+            .unwrap();
+
+        let (removed, created) = db
+            .replan_schedules(&wallet, group.id, plan_rest)
+            .await
+            .unwrap();
+        assert!(removed.is_empty());
+        assert_eq!(created[0].amount_lamports, 750);
+        let failed = db.get_schedule_by_id(failed_id).await.unwrap().unwrap();
+        assert_eq!(failed.status, "failed");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn buyer_with_failed_schedule_is_kept(pool: MySqlPool) {
+        let (db, group, buyer) = setup(pool).await;
+        let wallet = buyer.wallet.to_string();
+        let schedule = Schedule::new(
+            group.id,
+            wallet.clone(),
+            chrono::Utc::now().naive_utc(),
+            250,
+            0.25,
+        );
+        let schedule_id = db.save_schedule(&schedule).await.unwrap();
+        assert!(!db.buyer_has_transfers(&wallet).await.unwrap());
+        assert!(db.claim_schedule(schedule_id, "pending").await.unwrap());
+        db.update_schedule_status(schedule_id, "failed", Some("timeout".to_string()))
+            .await
+            .unwrap();
+
+        assert!(db.buyer_has_transfers(&wallet).await.unwrap());
+        assert!(!db.delete_buyer(&wallet).await.unwrap());
+        let moved = db
+            .update_buyer_details(&wallet, buyer.paid_lamports, group.id + 1, plan_rest)
+            .await
+            .unwrap();
+        assert!(moved.is_none());
+        assert!(db.get_buyer_by_wallet(&wallet).await.unwrap().is_some());
+        assert!(db.get_schedule_by_id(schedule_id).await.unwrap().is_some());
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn buyer_without_transfers_is_deleted_with_pending_schedules(pool: MySqlPool) {
+        let (db, group, buyer) = setup(pool).await;
+        let wallet = buyer.wallet.to_string();
+        let schedule = Schedule::new(
+            group.id,
+            wallet.clone(),
+            chrono::Utc::now().naive_utc(),
+            1000,
+            1.0,
+        );
+        let schedule_id = db.save_schedule(&schedule).await.unwrap();
+
+        assert!(db.delete_buyer(&wallet).await.unwrap());
+        assert!(db.get_buyer_by_wallet(&wallet).await.unwrap().is_none());
+        assert!(db.get_schedule_by_id(schedule_id).await.unwrap().is_none());
+        let pending = db
+            .get_ledger_balance(&Account::BuyerPending(wallet))
+            .await
+            .unwrap();
+        assert_eq!(pending, 0);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn buyer_update_keeps_claimed_schedule(pool: MySqlPool) {
+        let (db, group, buyer) = setup(pool).await;
+        let wallet = buyer.wallet.to_string();
+        let schedule = Schedule::new(
+            group.id,
+            wallet.clone(),
+            chrono::Utc::now().naive_utc(),
+            500,
+            0.5,
+        );
+        let claimed_id = db.save_schedule(&schedule).await.unwrap();
+        assert!(db.claim_schedule(claimed_id, "pending").await.unwrap());
+
+        let (removed, created) = db
+            .update_buyer_details(&wallet, 2000, group.id, plan_rest)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(removed.is_empty());
+        assert_eq!(created[0].amount_lamports, 1500);
+        let claimed = db.get_schedule_by_id(claimed_id).await.unwrap().unwrap();
+        assert_eq!(claimed.status, "processing");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn upload_is_claimed_once(pool: MySqlPool) {
+        let db = Database::from_pool(pool);
+        let upload = BuyerUpload::new("ab".repeat(32), None, None, "sync", "pending");
+        let upload_id = db.save_buyer_upload(&upload).await.unwrap();
+
+        assert!(db.claim_buyer_upload(upload_id).await.unwrap());
+        assert!(!db.claim_buyer_upload(upload_id).await.unwrap());
+        let upload = db.get_buyer_upload(upload_id).await.unwrap().unwrap();
+        assert_eq!(upload.status, "applying");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn rebuild_balances_restores_cached_columns(pool: MySqlPool) {
+        let (db, group, buyer) = setup(pool).await;
+        let wallet = buyer.wallet.to_string();
+        sqlx::query("UPDATE `buyers` SET pending_spl_lamports = 5 WHERE wallet = ?")
+            .bind(&wallet)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE `groups` SET spl_total_lamports = 1 WHERE id = ?")
+            .bind(group.id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        assert_eq!(db.rebuild_balances().await.unwrap(), (1, 1));
+        let buyer = db.get_buyer_by_wallet(&wallet).await.unwrap().unwrap();
+        assert_eq!(buyer.pending_spl_lamports, 1000);
+        let group = db.get_group(group.id).await.unwrap().unwrap();
+        assert_eq!(group.spl_total_lamports, 1_000_000);
+        // Nothing left to correct
+        assert_eq!(db.rebuild_balances().await.unwrap(), (0, 0));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn check_invariants_reports_only_violations(pool: MySqlPool) {
+        let (db, _group, buyer) = setup(pool).await;
+        let wallet = buyer.wallet.to_string();
+        let invariants = |report: &InvariantReport| -> Vec<Invariant> {
+            report.wallets[&wallet]
+                .iter()
+                .map(|v| v.invariant)
+                .collect()
+        };
+
+        // No schedules were planned yet
+        let report = db.check_invariants().await.unwrap();
+        assert_eq!((report.buyers_checked, report.groups_checked), (1, 1));
+        assert_eq!(invariants(&report), [Invariant::ScheduleTotal]);
+        assert!(report.groups.is_empty());
+
+        sqlx::query("UPDATE `buyers` SET pending_spl_lamports = 5 WHERE wallet = ?")
+            .bind(&wallet)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let report = db.check_invariants().await.unwrap();
+        assert_eq!(
+            invariants(&report),
+            [
+                Invariant::ScheduleTotal,
+                Invariant::PendingBalance,
+                Invariant::LedgerBalance
+            ]
+        );
+        assert_eq!(report.violations, 3);
+    }
 }
@@ -0,0 +1,57 @@
+use std::path::Path;
+
+/// Format of an imported buyers or groups file, picked by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Csv,
+    Json,
+    Ndjson,
+    Yaml,
+}
+
+impl FileFormat {
+    pub fn from_path(path: &str) -> anyhow::Result<Self> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "csv" => Ok(FileFormat::Csv),
+            "json" => Ok(FileFormat::Json),
+            "ndjson" | "jsonl" => Ok(FileFormat::Ndjson),
+            "yaml" | "yml" => Ok(FileFormat::Yaml),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported file format of `{}`. Expected csv, json, ndjson or yaml",
+                path
+            )),
+        }
+    }
+}
+
+/// Splits a JSON array or NDJSON content into records with their row numbers.
+/// For JSON the row is the position in the array, for NDJSON the line number.
+/// A line that isn't valid JSON is returned as an error message instead of failing the file.
+pub fn json_records(
+    content: &str,
+    format: FileFormat,
+) -> anyhow::Result<Vec<(usize, Result<serde_json::Value, String>)>> {
+    match format {
+        FileFormat::Json => {
+            let values: Vec<serde_json::Value> = serde_json::from_str(content)
+                .map_err(|e| anyhow::anyhow!("Expected a JSON array: {}", e))?;
+            Ok(values
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| (i + 1, Ok(v)))
+                .collect())
+        }
+        FileFormat::Ndjson => Ok(content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| (i + 1, serde_json::from_str(line).map_err(|e| e.to_string())))
+            .collect()),
+        _ => Err(anyhow::anyhow!("{:?} is not a JSON format", format)),
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use anyhow::{Context, anyhow};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ed25519_compact::{KeyPair, PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+
+/// Ed25519 keys used to sign and verify JWTs, stored as base64.
+///
+/// Tokens are signed with `signing_key` and accepted when they verify against its public key
+/// or any of `verifying_keys`. Rotating keeps the public keys of previous signing keys, so tokens
+/// issued before the rotation stay valid until they expire.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtKeys {
+    pub signing_key: String,
+    /// Public keys of previous signing keys, newest first.
+    #[serde(default)]
+    pub verifying_keys: Vec<String>,
+}
+
+impl JwtKeys {
+    pub fn generate() -> Self {
+        JwtKeys {
+            signing_key: STANDARD.encode(&KeyPair::generate().sk[..]),
+            verifying_keys: Vec::new(),
+        }
+    }
+
+    /// Replaces the signing key with a new one and keeps at most `keep` previous public keys.
+    pub fn rotate(&mut self, keep: usize) -> anyhow::Result<()> {
+        let previous = self.secret_key()?.public_key();
+        self.signing_key = STANDARD.encode(&KeyPair::generate().sk[..]);
+        self.verifying_keys
+            .insert(0, STANDARD.encode(&previous[..]));
+        self.verifying_keys.truncate(keep);
+        Ok(())
+    }
+
+    /// Loads the keys from `JWT_KEYS_FILE`, or from `JWT_SIGNING_KEY` and the comma separated
+    /// `JWT_VERIFYING_KEYS`. Returns `None` if neither is set.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        if let Ok(path) = std::env::var("JWT_KEYS_FILE") {
+            return JwtKeys::load(&path).map(Some);
+        }
+        let Ok(signing_key) = std::env::var("JWT_SIGNING_KEY") else {
+            return Ok(None);
+        };
+        let verifying_keys = std::env::var("JWT_VERIFYING_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .map(str::to_string)
+            .collect();
+        let keys = JwtKeys {
+            signing_key: signing_key.trim().to_string(),
+            verifying_keys,
+        };
+        keys.validate()
+            .context("Invalid JWT_SIGNING_KEY or JWT_VERIFYING_KEYS")?;
+        Ok(Some(keys))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read JWT keys from `{}`", path.display()))?;
+        let keys: JwtKeys = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid JSON in `{}`", path.display()))?;
+        keys.validate()
+            .with_context(|| format!("Invalid JWT keys in `{}`", path.display()))?;
+        Ok(keys)
+    }
+
+    /// Writes the keys to `path`, readable only by the owner on Unix.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let data = serde_json::to_vec_pretty(self).context("Failed to serialize JWT keys")?;
+        std::fs::write(path, data)
+            .with_context(|| format!("Failed to write JWT keys to `{}`", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("Failed to set permissions of `{}`", path.display()))?;
+        }
+        Ok(())
+    }
+
+    pub fn secret_key(&self) -> anyhow::Result<SecretKey> {
+        let bytes = STANDARD
+            .decode(&self.signing_key)
+            .context("Signing key is not valid base64")?;
+        SecretKey::from_slice(&bytes).map_err(|e| anyhow!("Invalid signing key: {e}"))
+    }
+
+    /// Public key of the signing key followed by the previous public keys.
+    pub fn public_keys(&self) -> anyhow::Result<Vec<PublicKey>> {
+        let mut keys = vec![self.secret_key()?.public_key()];
+        for key in &self.verifying_keys {
+            let bytes = STANDARD
+                .decode(key)
+                .with_context(|| format!("Verifying key `{}` is not valid base64", key))?;
+            let public_key = PublicKey::from_slice(&bytes)
+                .map_err(|e| anyhow!("Invalid verifying key `{}`: {e}", key))?;
+            keys.push(public_key);
+        }
+        Ok(keys)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        self.public_keys().map(|_| ())
+    }
+}
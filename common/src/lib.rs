@@ -1,9 +1,17 @@
+mod approval_policy;
 mod db;
+mod file_format;
+mod jwt_keys;
 
 mod schema;
 mod spl_token;
+mod totp;
 
+pub use approval_policy::*;
 pub use db::*;
+pub use file_format::*;
+pub use jwt_keys::*;
 
 pub use schema::*;
 pub use spl_token::*;
+pub use totp::*;
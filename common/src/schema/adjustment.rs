@@ -0,0 +1,107 @@
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Longest reason accepted for a manual change.
+const MAX_REASON_LENGTH: usize = 1000;
+
+/// A manual change of a buyer's balance outside the vesting schedules, e.g. a missed bonus
+/// or a compensation after a bug. Both kinds add `amount_lamports` to `received_spl_lamports`
+/// and leave the pending allocation alone.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Adjustment {
+    pub id: i64,
+    pub buyer_wallet: String,
+    pub group_id: i64,
+    pub kind: String, // "transfer", "adjustment"
+    /// Signed; transfers are always positive.
+    pub amount_lamports: i64,
+    pub reason: String,
+    pub status: String, // "pending", "processing", "success", "failed", "interrupted"
+    pub transaction_id: Option<i64>,
+    pub error_message: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl Adjustment {
+    /// Tokens sent to the buyer on top of their schedules.
+    pub fn transfer(
+        buyer_wallet: &str,
+        group_id: i64,
+        amount_lamports: u64,
+        reason: &str,
+        created_by: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let amount_lamports = i64::try_from(amount_lamports)
+            .ok()
+            .filter(|a| *a > 0)
+            .ok_or_else(|| anyhow!("Transfer amount must be positive."))?;
+        Self::new(
+            buyer_wallet,
+            group_id,
+            "transfer",
+            amount_lamports,
+            reason,
+            created_by,
+        )
+    }
+
+    /// Correction of the recorded balance without sending anything, e.g. for tokens that
+    /// were moved outside the system.
+    pub fn correction(
+        buyer_wallet: &str,
+        group_id: i64,
+        amount_lamports: i64,
+        reason: &str,
+        created_by: Option<String>,
+    ) -> anyhow::Result<Self> {
+        if amount_lamports == 0 {
+            return Err(anyhow!("Adjustment amount must not be zero."));
+        }
+        Self::new(
+            buyer_wallet,
+            group_id,
+            "adjustment",
+            amount_lamports,
+            reason,
+            created_by,
+        )
+    }
+
+    fn new(
+        buyer_wallet: &str,
+        group_id: i64,
+        kind: &str,
+        amount_lamports: i64,
+        reason: &str,
+        created_by: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let reason = reason.trim();
+        if reason.is_empty() || reason.len() > MAX_REASON_LENGTH {
+            return Err(anyhow!(
+                "Reason is required and must be at most {} characters.",
+                MAX_REASON_LENGTH
+            ));
+        }
+        Ok(Adjustment {
+            id: 0, //set by DB
+            buyer_wallet: buyer_wallet.to_string(),
+            group_id,
+            kind: kind.to_string(),
+            amount_lamports,
+            reason: reason.to_string(),
+            status: "pending".to_string(),
+            transaction_id: None,
+            error_message: None,
+            created_by,
+            created_at: None, //set by DB
+            updated_at: None, //set by DB
+        })
+    }
+
+    pub fn is_transfer(&self) -> bool {
+        self.kind == "transfer"
+    }
+}
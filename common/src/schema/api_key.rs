@@ -0,0 +1,207 @@
+use anyhow::anyhow;
+use chrono::{NaiveDateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::schema::{Role, User};
+
+/// Every API key starts with this, so it's told apart from a JWT.
+pub const API_KEY_PREFIX: &str = "spl_";
+
+/// Start of the username requests with a key act as; real usernames can't contain `:`.
+pub const API_KEY_USER_PREFIX: &str = "api-key:";
+
+/// Roles a key can have. Approving and managing users need a person who logged in.
+pub const API_KEY_ROLES: [Role; 2] = [Role::Viewer, Role::Operator];
+
+/// A key of a service account. It acts for its owner with the permissions of its role.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    /// First characters of the key, shown to tell keys apart.
+    pub prefix: String,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    pub role: String,
+    /// Owner the key acts for, `None` once they were deleted.
+    pub user_id: Option<i64>,
+    pub created_by: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl ApiKey {
+    /// Creates a new key of `owner`. Returns it together with the plain key, which isn't
+    /// stored and can only be shown now.
+    pub fn generate(
+        name: &str,
+        role: Role,
+        expires_at: Option<NaiveDateTime>,
+        owner: &User,
+    ) -> anyhow::Result<(ApiKey, String)> {
+        let name = name.trim();
+        if name.is_empty() || name.len() > 100 {
+            return Err(anyhow!("Name must be between 1 and 100 characters."));
+        }
+        if !API_KEY_ROLES.contains(&role) {
+            return Err(anyhow!(
+                "API keys can only have the viewer or operator role."
+            ));
+        }
+        if !owner.role().includes(role) {
+            return Err(anyhow!(
+                "`{}` can't create a key with the {} role.",
+                owner.username,
+                role.as_str()
+            ));
+        }
+        if expires_at.is_some_and(|t| t <= Utc::now().naive_utc()) {
+            return Err(anyhow!("Expiry must be in the future."));
+        }
+
+        let bytes: [u8; 32] = rand::rng().random();
+        let secret: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let key = format!("{}{}", API_KEY_PREFIX, secret);
+        let api_key = ApiKey {
+            id: 0, //set by DB
+            name: name.to_string(),
+            prefix: key.chars().take(API_KEY_PREFIX.len() + 8).collect(),
+            key_hash: ApiKey::hash(&key),
+            role: role.as_str().to_string(),
+            user_id: Some(owner.id),
+            created_by: Some(owner.username.clone()),
+            expires_at,
+            last_used_at: None,
+            revoked_at: None,
+            created_at: None, //set by DB
+        };
+        Ok((api_key, key))
+    }
+
+    /// SHA-256 of the key, hex encoded. Keys are random, so a fast hash is enough.
+    pub fn hash(key: &str) -> String {
+        format!("{:x}", Sha256::digest(key.as_bytes()))
+    }
+
+    pub fn role(&self) -> Role {
+        Role::parse(&self.role).unwrap_or(Role::Viewer)
+    }
+
+    /// Neither revoked nor expired.
+    pub fn is_valid(&self) -> bool {
+        self.revoked_at.is_none()
+            && self
+                .expires_at
+                .is_none_or(|expires_at| expires_at > Utc::now().naive_utc())
+    }
+
+    /// The key can be used by `owner`: they are active and their role still has
+    /// every permission of the key's.
+    pub fn allowed_for(&self, owner: &User) -> bool {
+        let role = Role::parse(&self.role);
+        self.user_id == Some(owner.id)
+            && owner.active
+            && role.is_some_and(|role| API_KEY_ROLES.contains(&role) && owner.role().includes(role))
+    }
+
+    /// The user requests with this key act as: the owner with the key's role,
+    /// named `api-key:<name>`.
+    pub fn user(&self, owner: &User) -> User {
+        User {
+            id: owner.id,
+            username: format!("{}{}", API_KEY_USER_PREFIX, self.name),
+            email: owner.email.clone(),
+            password_hash: String::new(),
+            role: self.role().as_str().to_string(),
+            is_superuser: false,
+            active: owner.active,
+            session_id: None,
+            created_at: self.created_at,
+            updated_at: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(role: Role) -> User {
+        User {
+            id: 7,
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password_hash: String::new(),
+            role: role.as_str().to_string(),
+            is_superuser: role == Role::Admin,
+            active: true,
+            session_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn keys_cant_approve_or_manage_users() {
+        for role in [Role::Admin, Role::Approver] {
+            assert!(ApiKey::generate("job", role, None, &owner(Role::Admin)).is_err());
+        }
+        for role in API_KEY_ROLES {
+            assert!(ApiKey::generate("job", role, None, &owner(Role::Admin)).is_ok());
+        }
+    }
+
+    #[test]
+    fn key_role_is_limited_by_the_owner() {
+        let viewer = owner(Role::Viewer);
+        assert!(ApiKey::generate("job", Role::Operator, None, &viewer).is_err());
+        assert!(ApiKey::generate("job", Role::Viewer, None, &viewer).is_ok());
+        let approver = owner(Role::Approver);
+        assert!(ApiKey::generate("job", Role::Operator, None, &approver).is_err());
+    }
+
+    #[test]
+    fn key_works_only_for_its_active_owner() {
+        let mut alice = owner(Role::Operator);
+        let (api_key, key) = ApiKey::generate("job", Role::Operator, None, &alice).unwrap();
+        assert!(key.starts_with(API_KEY_PREFIX));
+        assert_eq!(api_key.user_id, Some(alice.id));
+        assert!(api_key.allowed_for(&alice));
+
+        let mut bob = owner(Role::Admin);
+        bob.id = 8;
+        assert!(!api_key.allowed_for(&bob));
+
+        // Demoted owners can't use keys above their role anymore
+        alice.role = Role::Viewer.as_str().to_string();
+        assert!(!api_key.allowed_for(&alice));
+
+        alice.role = Role::Operator.as_str().to_string();
+        alice.active = false;
+        assert!(!api_key.allowed_for(&alice));
+    }
+
+    #[test]
+    fn stored_admin_key_is_not_allowed() {
+        let admin = owner(Role::Admin);
+        let (mut api_key, _) = ApiKey::generate("job", Role::Viewer, None, &admin).unwrap();
+        api_key.role = Role::Admin.as_str().to_string();
+        assert!(!api_key.allowed_for(&admin));
+    }
+
+    #[test]
+    fn key_user_is_the_owner_with_the_key_role() {
+        let admin = owner(Role::Admin);
+        let (api_key, _) = ApiKey::generate("job", Role::Operator, None, &admin).unwrap();
+        let user = api_key.user(&admin);
+        assert_eq!(user.id, admin.id);
+        assert_eq!(user.username, "api-key:job");
+        assert_eq!(user.role(), Role::Operator);
+        assert!(!user.is_superuser);
+        assert!(user.session_id.is_none());
+    }
+}
@@ -0,0 +1,195 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{Permission, User, parameters_to_json};
+
+/// Operations that can be held for a four-eyes approval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalAction {
+    /// Resend failed schedules
+    RetrySchedules,
+    UpdateGroup,
+    DeleteGroup,
+    /// Remove a buyer, revoking their unsent allocation
+    DeleteBuyer,
+    /// Send tokens to a buyer outside their schedules
+    ManualTransfer,
+    /// Correct the received balance of a buyer
+    AdjustBalance,
+}
+
+impl ApprovalAction {
+    pub const ALL: [ApprovalAction; 6] = [
+        ApprovalAction::RetrySchedules,
+        ApprovalAction::UpdateGroup,
+        ApprovalAction::DeleteGroup,
+        ApprovalAction::DeleteBuyer,
+        ApprovalAction::ManualTransfer,
+        ApprovalAction::AdjustBalance,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApprovalAction::RetrySchedules => "retry_schedules",
+            ApprovalAction::UpdateGroup => "update_group",
+            ApprovalAction::DeleteGroup => "delete_group",
+            ApprovalAction::DeleteBuyer => "delete_buyer",
+            ApprovalAction::ManualTransfer => "manual_transfer",
+            ApprovalAction::AdjustBalance => "adjust_balance",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<ApprovalAction> {
+        ApprovalAction::ALL
+            .into_iter()
+            .find(|a| a.as_str() == action)
+    }
+
+    /// Permission the requester needs, also when the request is approved later.
+    pub fn permission(&self) -> Permission {
+        match self {
+            ApprovalAction::RetrySchedules | ApprovalAction::ManualTransfer => {
+                Permission::SendTokens
+            }
+            ApprovalAction::UpdateGroup | ApprovalAction::DeleteGroup => Permission::ManageGroups,
+            ApprovalAction::DeleteBuyer | ApprovalAction::AdjustBalance => Permission::ManageBuyers,
+        }
+    }
+}
+
+impl std::fmt::Display for ApprovalAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+fn result_to_json<S>(result: &Option<String>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match result {
+        Some(result) => parameters_to_json(result, s),
+        None => s.serialize_none(),
+    }
+}
+
+/// An operation waiting for, or decided by, a second user with the approver role.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Approval {
+    pub id: i64,
+    pub action: String,
+    #[serde(serialize_with = "parameters_to_json")]
+    pub parameters: String, // JSON
+    /// SPL lamports the operation moves or revokes.
+    pub amount_lamports: u64,
+    pub status: String, // "pending", "approved", "rejected", "executed", "failed"
+    pub requested_by: String,
+    /// User behind the request; for API keys their owner.
+    pub requested_by_id: Option<i64>,
+    pub decided_by: Option<String>,
+    pub reason: Option<String>,
+    #[serde(serialize_with = "result_to_json")]
+    pub result: Option<String>, // JSON
+    pub created_at: Option<NaiveDateTime>,
+    pub decided_at: Option<NaiveDateTime>,
+}
+
+impl Approval {
+    pub fn new(
+        action: ApprovalAction,
+        parameters: serde_json::Value,
+        amount_lamports: u64,
+        requested_by: &User,
+    ) -> Self {
+        Approval {
+            id: 0, //set by DB
+            action: action.as_str().to_string(),
+            parameters: parameters.to_string(),
+            amount_lamports,
+            status: "pending".to_string(),
+            requested_by: requested_by.username.clone(),
+            requested_by_id: Some(requested_by.id),
+            decided_by: None,
+            reason: None,
+            result: None,
+            created_at: None, //set by DB
+            decided_at: None,
+        }
+    }
+
+    pub fn action(&self) -> Option<ApprovalAction> {
+        ApprovalAction::parse(&self.action)
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.status == "pending"
+    }
+
+    /// Made by `user`, directly or with one of their API keys.
+    pub fn is_requested_by(&self, user: &User) -> bool {
+        self.requested_by == user.username || self.requested_by_id == Some(user.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{ApiKey, Role};
+
+    fn user(id: i64, username: &str, role: Role) -> User {
+        User {
+            id,
+            username: username.to_string(),
+            email: format!("{}@example.com", username),
+            password_hash: String::new(),
+            role: role.as_str().to_string(),
+            is_superuser: role == Role::Admin,
+            active: true,
+            session_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn request_of(requester: &User) -> Approval {
+        Approval::new(
+            ApprovalAction::DeleteBuyer,
+            serde_json::json!({ "wallet": "7G9" }),
+            1000,
+            requester,
+        )
+    }
+
+    #[test]
+    fn request_of_an_api_key_belongs_to_its_owner() {
+        let alice = user(7, "alice", Role::Admin);
+        let (api_key, _) = ApiKey::generate("job", Role::Operator, None, &alice).unwrap();
+        let approval = request_of(&api_key.user(&alice));
+        assert_eq!(approval.requested_by, "api-key:job");
+        assert_eq!(approval.requested_by_id, Some(alice.id));
+        assert!(approval.is_requested_by(&alice));
+        assert!(!approval.is_requested_by(&user(8, "bob", Role::Approver)));
+    }
+
+    #[test]
+    fn old_requests_are_matched_by_username() {
+        let alice = user(7, "alice", Role::Operator);
+        let mut approval = request_of(&alice);
+        approval.requested_by_id = None;
+        assert!(approval.is_requested_by(&alice));
+    }
+
+    #[test]
+    fn api_keys_never_approve() {
+        let admin = user(7, "alice", Role::Admin);
+        let (mut api_key, _) = ApiKey::generate("job", Role::Viewer, None, &admin).unwrap();
+        // Even a key stored with a role that can approve
+        api_key.role = Role::Approver.as_str().to_string();
+        let key_user = api_key.user(&admin);
+        assert!(key_user.is_api_key());
+        assert!(!key_user.can(Permission::Approve));
+        assert!(key_user.can(Permission::Read));
+        assert!(admin.can(Permission::Approve));
+    }
+}
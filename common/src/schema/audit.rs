@@ -0,0 +1,99 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{Cursor, SortValue};
+
+/// Keys whose values are replaced before parameters are stored.
+const REDACTED_KEYS: [&str; 5] = ["password", "secret", "token", "code", "challenge"];
+
+pub(crate) fn parameters_to_json<S>(parameters: &str, s: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let value: serde_json::Value = serde_json::from_str(parameters).unwrap_or_default();
+    value.serialize(s)
+}
+
+/// One administrative action, done through the API or the CLI.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub source: String, // "api", "cli"
+    pub actor: Option<String>,
+    pub action: String,
+    #[serde(serialize_with = "parameters_to_json")]
+    pub parameters: String, // JSON
+    pub result: String, // "success", "failure"
+    pub status_code: Option<i32>,
+    pub result_message: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl AuditEntry {
+    /// A successful action; use `failed` to record an error.
+    pub fn new(
+        source: &str,
+        actor: Option<String>,
+        action: impl Into<String>,
+        mut parameters: serde_json::Value,
+    ) -> Self {
+        redact(&mut parameters);
+        AuditEntry {
+            id: 0,
+            source: source.to_string(),
+            actor,
+            action: action.into(),
+            parameters: parameters.to_string(),
+            result: "success".to_string(),
+            status_code: None,
+            result_message: None,
+            ip_address: None,
+            created_at: None,
+        }
+    }
+
+    pub fn failed(mut self, message: impl Into<String>) -> Self {
+        self.result = "failure".to_string();
+        self.result_message = Some(message.into());
+        self
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        Cursor {
+            value: SortValue::Unsigned(self.id as u64),
+            key: self.id.to_string(),
+        }
+    }
+}
+
+/// Replaces passwords, secrets, tokens and one-time codes anywhere in `value`.
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if REDACTED_KEYS.iter().any(|k| key.contains(k)) {
+                    *value = serde_json::Value::String("***".to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// `from`/`to` filter on `created_at`. Entries are always sorted by `id`.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    /// Substring of the action, e.g. `/groups`
+    pub action: Option<String>,
+    pub source: Option<String>,
+    pub result: Option<String>,
+    pub ip_address: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
@@ -5,6 +5,7 @@ use tokio_stream::StreamExt;
 use serde::{Deserialize, Serialize};
 use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey, signer::Signer};
 
+use crate::file_format::{FileFormat, json_records};
 use crate::schema::Group;
 
 fn pubkey_to_string<S>(pk: &Pubkey, s: S) -> Result<S::Ok, S::Error>
@@ -45,43 +46,184 @@ pub struct Buyer {
     pub updated_at: Option<chrono::NaiveDateTime>,
 }
 
+/// Why a row of an uploaded buyers file was not imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowIssueReason {
+    ParseError,
+    InvalidPubkey,
+    UnknownGroup,
+    DuplicateInFile,
+    OverCap,
+}
+
+/// A rejected row of a buyers file. `row` is the line number (the CSV header is line 1),
+/// or the position of the record in a JSON array.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowIssue {
+    pub row: usize,
+    pub wallet: Option<String>,
+    pub reason: RowIssueReason,
+    pub message: String,
+}
+
+/// Result of parsing a buyers file: valid buyers with their row numbers and rejected rows.
+#[derive(Debug, Default)]
+pub struct BuyersReport {
+    pub buyers: Vec<(usize, Buyer)>,
+    pub issues: Vec<RowIssue>,
+    seen: std::collections::HashSet<Pubkey>,
+}
+
+impl BuyersReport {
+    /// Adds a parsed row, rejecting it if it failed to deserialize, has an invalid wallet,
+    /// an unknown `group_id` or repeats an earlier wallet.
+    fn push_row(
+        &mut self,
+        row: usize,
+        raw_wallet: Option<String>,
+        parsed: Result<Buyer, String>,
+        groups: &[Group],
+    ) {
+        let mut buyer = match parsed {
+            Ok(buyer) => buyer,
+            Err(message) => {
+                let invalid_pubkey = raw_wallet
+                    .as_deref()
+                    .is_some_and(|w| Pubkey::from_str(w.trim()).is_err());
+                self.issues.push(RowIssue {
+                    row,
+                    wallet: raw_wallet,
+                    reason: if invalid_pubkey {
+                        RowIssueReason::InvalidPubkey
+                    } else {
+                        RowIssueReason::ParseError
+                    },
+                    message,
+                });
+                return;
+            }
+        };
+
+        let Some(group) = groups.iter().find(|g| g.id == buyer.group_id) else {
+            self.issues.push(RowIssue {
+                row,
+                wallet: Some(buyer.wallet.to_string()),
+                reason: RowIssueReason::UnknownGroup,
+                message: format!("Group not found: group_id={}", buyer.group_id),
+            });
+            return;
+        };
+        if !self.seen.insert(buyer.wallet) {
+            self.issues.push(RowIssue {
+                row,
+                wallet: Some(buyer.wallet.to_string()),
+                reason: RowIssueReason::DuplicateInFile,
+                message: "Wallet appears more than once in the file".to_string(),
+            });
+            return;
+        }
+
+        if buyer.pending_spl_lamports == 0 {
+            buyer.pending_spl_lamports = buyer.paid_lamports / group.spl_price_lamports;
+        }
+        self.buyers.push((row, buyer));
+    }
+}
+
 impl Buyer {
-    pub async fn load_from_csv(path: &str, groups: &[Group]) -> anyhow::Result<Vec<Buyer>> {
+    /// Loads buyers from a CSV, JSON or NDJSON file. Rejected rows are logged and skipped.
+    pub async fn load_from_file(path: &str, groups: &[Group]) -> anyhow::Result<Vec<Buyer>> {
+        let report = Buyer::load_report(path, groups).await?;
+        for issue in &report.issues {
+            match issue.reason {
+                RowIssueReason::UnknownGroup => log::warn!(
+                    "Row {}: {} ({})",
+                    issue.row,
+                    issue.message,
+                    issue.wallet.as_deref().unwrap_or("-")
+                ),
+                _ => log::error!(
+                    "Error deserializing buyer on row {}: {}",
+                    issue.row,
+                    issue.message
+                ),
+            }
+        }
+
+        let buyers: Vec<Buyer> = report.buyers.into_iter().map(|(_, b)| b).collect();
+        log::debug!("Loaded buyers from file: {:#?}", buyers);
+        if buyers.is_empty() {
+            return Err(anyhow::anyhow!("No buyers found in the file"));
+        }
+        Ok(buyers)
+    }
+
+    pub async fn load_report(path: &str, groups: &[Group]) -> anyhow::Result<BuyersReport> {
+        let format = FileFormat::from_path(path)?;
         let content = tokio::fs::read_to_string(path).await?;
-        let mut rdr = csv_async::AsyncReaderBuilder::new()
-            .has_headers(true)
-            .create_deserializer(content.as_bytes());
-        let mut records = rdr.deserialize::<Buyer>();
-        let mut buyers = Vec::new();
-        while let Some(buyer_result) = records.next().await {
-            match buyer_result {
-                Ok(mut buyer) => {
-                    if buyer.pending_spl_lamports == 0 {
-                        if let Some(group) = groups.iter().find(|g| g.id == buyer.group_id) {
-                            buyer.pending_spl_lamports =
-                                buyer.paid_lamports / group.spl_price_lamports;
-                        } else {
-                            log::warn!(
-                                "Group not found for buyer: {} group_id={}",
-                                buyer.wallet,
-                                buyer.group_id
-                            );
+        Buyer::parse_report(&content, format, groups).await
+    }
+
+    /// Validates every record of a buyers file in the given format.
+    pub async fn parse_report(
+        content: &str,
+        format: FileFormat,
+        groups: &[Group],
+    ) -> anyhow::Result<BuyersReport> {
+        match format {
+            FileFormat::Csv => Buyer::parse_csv_report(content, groups).await,
+            FileFormat::Json | FileFormat::Ndjson => {
+                let mut report = BuyersReport::default();
+                for (row, record) in json_records(content, format)? {
+                    match record {
+                        Ok(value) => {
+                            let raw_wallet = value
+                                .get("wallet")
+                                .and_then(|w| w.as_str())
+                                .map(|w| w.to_string());
+                            let parsed =
+                                serde_json::from_value::<Buyer>(value).map_err(|e| e.to_string());
+                            report.push_row(row, raw_wallet, parsed, groups);
                         }
+                        Err(message) => report.push_row(row, None, Err(message), groups),
                     }
-
-                    buyers.push(buyer);
-                }
-                Err(e) => {
-                    log::error!("Error deserializing buyer: {}", e);
-                    continue;
                 }
+                Ok(report)
             }
+            FileFormat::Yaml => Err(anyhow::anyhow!(
+                "Buyers can be imported only from csv, json or ndjson files"
+            )),
         }
-        log::debug!("Loaded buyers from CSV file: {:#?}", buyers);
-        if buyers.is_empty() {
-            return Err(anyhow::anyhow!("No buyers found in the CSV file"));
+    }
+
+    /// Validates every row of a buyers CSV.
+    pub async fn parse_csv_report(content: &str, groups: &[Group]) -> anyhow::Result<BuyersReport> {
+        let mut rdr = csv_async::AsyncReaderBuilder::new()
+            .has_headers(true)
+            .create_reader(content.as_bytes());
+        let headers = rdr.headers().await?.clone();
+        let wallet_idx = headers.iter().position(|h| h == "wallet");
+
+        let mut report = BuyersReport::default();
+        let mut records = rdr.records();
+        let mut row = 1;
+        while let Some(record_result) = records.next().await {
+            row += 1;
+            match record_result {
+                Ok(record) => {
+                    let raw_wallet = wallet_idx
+                        .and_then(|idx| record.get(idx))
+                        .map(|w| w.trim().to_string());
+                    let parsed = record
+                        .deserialize::<Buyer>(Some(&headers))
+                        .map_err(|e| e.to_string());
+                    report.push_row(row, raw_wallet, parsed, groups);
+                }
+                Err(e) => report.push_row(row, None, Err(e.to_string()), groups),
+            }
         }
-        Ok(buyers)
+        Ok(report)
     }
 
     //Remove in production
@@ -1,4 +1,9 @@
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+use tokio_stream::StreamExt;
+
+use super::Schedule;
+use crate::file_format::{FileFormat, json_records};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Group {
     pub id: i64,
     pub spl_share_percent: f64,
@@ -15,12 +20,94 @@ pub struct Group {
 }
 
 impl Group {
-    pub async fn from_yaml_file(path: &str, total_amount: u64) -> anyhow::Result<Vec<Group>> {
+    /// Loads groups from a YAML, JSON, NDJSON or CSV file, picked by the file extension.
+    pub async fn from_file(path: &str, total_amount: u64) -> anyhow::Result<Vec<Group>> {
+        let format = FileFormat::from_path(path)?;
         let content = tokio::fs::read_to_string(path).await?;
-        let mut groups: Vec<Group> = serde_yaml::from_str(&content)?;
+        let mut groups: Vec<Group> = match format {
+            FileFormat::Yaml => serde_yaml::from_str(&content)?,
+            FileFormat::Json => serde_json::from_str(&content)?,
+            FileFormat::Ndjson => json_records(&content, format)?
+                .into_iter()
+                .map(|(row, record)| {
+                    record
+                        .and_then(|v| serde_json::from_value(v).map_err(|e| e.to_string()))
+                        .map_err(|e| anyhow::anyhow!("Invalid group on line {}: {}", row, e))
+                })
+                .collect::<anyhow::Result<_>>()?,
+            FileFormat::Csv => {
+                let mut rdr = csv_async::AsyncDeserializer::from_reader(content.as_bytes());
+                let mut records = rdr.deserialize::<Group>();
+                let mut groups = Vec::new();
+                while let Some(group) = records.next().await {
+                    groups.push(group?);
+                }
+                groups
+            }
+        };
         groups.iter_mut().for_each(|g| {
             g.spl_total_lamports = (g.spl_share_percent * total_amount as f64).round() as u64;
         });
         Ok(groups)
     }
+
+    /// Checks that the vesting parameters describe a schedule that can actually be generated.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(self.spl_share_percent > 0.0 && self.spl_share_percent <= 1.0) {
+            anyhow::bail!("spl_share_percent must be in range (0, 1]");
+        }
+        if self.spl_price_lamports == 0 {
+            anyhow::bail!("spl_price_lamports must be greater than 0");
+        }
+        if !(0.0..=1.0).contains(&self.initial_unlock_percent) {
+            anyhow::bail!("initial_unlock_percent must be in range [0, 1]");
+        }
+        if self.unlock_interval_seconds <= 0 {
+            anyhow::bail!("unlock_interval_seconds must be greater than 0");
+        }
+        if !(self.unlock_percent_per_interval > 0.0 && self.unlock_percent_per_interval <= 1.0) {
+            anyhow::bail!("unlock_percent_per_interval must be in range (0, 1]");
+        }
+        Ok(())
+    }
+
+    /// Returns true if any parameter that affects generated schedules differs from `other`.
+    pub fn vesting_changed(&self, other: &Group) -> bool {
+        self.spl_price_lamports != other.spl_price_lamports
+            || self.initial_unlock_percent != other.initial_unlock_percent
+            || self.unlock_interval_seconds != other.unlock_interval_seconds
+            || self.unlock_percent_per_interval != other.unlock_percent_per_interval
+    }
+}
+
+/// Snapshot of a group configuration, stored every time the group is created, changed or deleted.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct GroupVersion {
+    pub id: i64,
+    pub group_id: i64,
+    pub version: i64,
+    pub change_type: String, // "create", "update", "delete"
+    pub spl_share_percent: f64,
+    pub spl_total_lamports: u64,
+    pub spl_price_lamports: u64,
+    pub initial_unlock_percent: f64,
+    pub unlock_interval_seconds: i64,
+    pub unlock_percent_per_interval: f64,
+    pub changed_by: Option<String>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+}
+
+/// Outcome of `Database::update_group`.
+#[derive(Debug, Clone)]
+pub enum GroupUpdate {
+    /// The group was saved as `version`, with the schedules its re-plan removed and created.
+    Updated {
+        group: Group,
+        version: i64,
+        removed: Vec<Schedule>,
+        created: Vec<Schedule>,
+    },
+    /// The buyers of the group would be owed `allocation`, more than it holds, so nothing
+    /// was written.
+    OverAllocated { allocation: u64 },
 }
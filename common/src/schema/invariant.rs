@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::schema::Account;
+
+/// Percents closer than this are the same tranche.
+pub const PERCENT_TOLERANCE: f64 = 1e-6;
+
+/// Consistency rules of the distribution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Invariant {
+    /// The schedules of a buyer add up to their allocation
+    ScheduleTotal,
+    /// Every successful schedule has one successful transaction of the same amount, and
+    /// transactions without a schedule match the successful manual transfers
+    ScheduleTransactions,
+    /// `received_percent` is the percent of the last successful schedule
+    ReceivedPercent,
+    /// `pending_spl_lamports` is the allocation minus what the successful schedules sent
+    PendingBalance,
+    /// The balance columns of buyers and groups match the ledger
+    LedgerBalance,
+    /// The allocations of a group's buyers fit in its `spl_total_lamports`
+    GroupTotal,
+    /// No buyer has two schedules at the same percent
+    DuplicatePercent,
+}
+
+impl Invariant {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Invariant::ScheduleTotal => "schedule_total",
+            Invariant::ScheduleTransactions => "schedule_transactions",
+            Invariant::ReceivedPercent => "received_percent",
+            Invariant::PendingBalance => "pending_balance",
+            Invariant::LedgerBalance => "ledger_balance",
+            Invariant::GroupTotal => "group_total",
+            Invariant::DuplicatePercent => "duplicate_percent",
+        }
+    }
+}
+
+impl std::fmt::Display for Invariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub invariant: Invariant,
+    pub message: String,
+}
+
+/// Totals of a buyer that breaks at least one invariant, summed by the database.
+#[derive(Debug, Clone)]
+pub struct BuyerTotals {
+    pub wallet: String,
+    pub received_spl_lamports: u64,
+    pub pending_spl_lamports: u64,
+    pub received_percent: f64,
+    /// `paid_lamports / spl_price_lamports`
+    pub allocation: u64,
+    /// Sum of all schedules
+    pub scheduled: u64,
+    /// Sum of the successful schedules
+    pub vested: u64,
+    /// Successful schedule with the highest percent
+    pub last_schedule_id: Option<i64>,
+    pub last_percent: Option<f64>,
+    /// Sent by successful transactions without a schedule
+    pub sent_manually: u64,
+    /// Sum of the successful manual transfers
+    pub transferred: u64,
+    pub received_balance: i64,
+    pub pending_balance: i64,
+}
+
+impl BuyerTotals {
+    /// Manual transfers and adjustments count in `received_spl_lamports` but not in the
+    /// schedules, so the schedule checks use only what the successful schedules sent.
+    pub fn violations(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        if self.scheduled != self.allocation {
+            violations.push(Violation {
+                invariant: Invariant::ScheduleTotal,
+                message: format!(
+                    "Schedules add up to {} lamports but the allocation is {}",
+                    self.scheduled, self.allocation
+                ),
+            });
+        }
+
+        if self.sent_manually != self.transferred {
+            violations.push(Violation {
+                invariant: Invariant::ScheduleTransactions,
+                message: format!(
+                    "Transactions without a schedule sent {} lamports but successful manual transfers add up to {}",
+                    self.sent_manually, self.transferred
+                ),
+            });
+        }
+
+        if let Some((id, percent)) = self
+            .last_schedule_id
+            .zip(self.last_percent)
+            .filter(|(_, percent)| (self.received_percent - percent).abs() > PERCENT_TOLERANCE)
+        {
+            violations.push(Violation {
+                invariant: Invariant::ReceivedPercent,
+                message: format!(
+                    "received_percent is {} but the last successful schedule {} is at {}",
+                    self.received_percent, id, percent
+                ),
+            });
+        }
+
+        let expected_pending = self.allocation.saturating_sub(self.vested);
+        if self.pending_spl_lamports != expected_pending {
+            violations.push(Violation {
+                invariant: Invariant::PendingBalance,
+                message: format!(
+                    "pending_spl_lamports is {} but the allocation {} minus {} sent by schedules is {}",
+                    self.pending_spl_lamports, self.allocation, self.vested, expected_pending
+                ),
+            });
+        }
+
+        for (column, cached, account, balance) in [
+            (
+                "received_spl_lamports",
+                self.received_spl_lamports,
+                Account::Buyer(self.wallet.clone()),
+                self.received_balance,
+            ),
+            (
+                "pending_spl_lamports",
+                self.pending_spl_lamports,
+                Account::BuyerPending(self.wallet.clone()),
+                self.pending_balance,
+            ),
+        ] {
+            if cached as i64 != balance {
+                violations.push(Violation {
+                    invariant: Invariant::LedgerBalance,
+                    message: format!(
+                        "{} is {} but the balance of {} is {}",
+                        column, cached, account, balance
+                    ),
+                });
+            }
+        }
+
+        violations
+    }
+}
+
+/// A schedule whose successful transactions don't match its status or amount.
+#[derive(Debug, Clone)]
+pub struct ScheduleSends {
+    pub id: i64,
+    pub buyer_wallet: String,
+    pub status: String,
+    pub amount_lamports: u64,
+    /// Number of successful transactions
+    pub successes: i64,
+    /// First successful transaction
+    pub transaction_id: Option<i64>,
+    /// Sum of the successful transactions
+    pub sent_lamports: u64,
+}
+
+impl ScheduleSends {
+    pub fn violation(&self) -> Option<Violation> {
+        let message = match (
+            self.status == "success",
+            self.successes,
+            self.transaction_id,
+        ) {
+            (true, 0, _) => format!(
+                "Schedule {} succeeded without a successful transaction",
+                self.id
+            ),
+            (true, 1, Some(transaction_id)) if self.sent_lamports != self.amount_lamports => {
+                format!(
+                    "Schedule {} is for {} lamports but transaction {} sent {}",
+                    self.id, self.amount_lamports, transaction_id, self.sent_lamports
+                )
+            }
+            (true, 1, _) => return None,
+            (true, successes, _) => format!(
+                "Schedule {} has {} successful transactions",
+                self.id, successes
+            ),
+            (false, 0, _) => return None,
+            (false, _, _) => format!(
+                "Schedule {} is `{}` but has a successful transaction",
+                self.id, self.status
+            ),
+        };
+        Some(Violation {
+            invariant: Invariant::ScheduleTransactions,
+            message,
+        })
+    }
+}
+
+/// Schedules of a buyer at the same percent.
+#[derive(Debug, Clone)]
+pub struct DuplicatePercent {
+    pub buyer_wallet: String,
+    /// Same key as the schedule planning, `percent * 1_000_000` rounded
+    pub percent_key: u64,
+    /// Comma separated ids of the schedules
+    pub schedule_ids: String,
+}
+
+impl DuplicatePercent {
+    pub fn violation(&self) -> Violation {
+        Violation {
+            invariant: Invariant::DuplicatePercent,
+            message: format!(
+                "Schedules [{}] are all at {}%",
+                self.schedule_ids.replace(',', ", "),
+                self.percent_key as f64 / 10_000.0
+            ),
+        }
+    }
+}
+
+/// Totals of a group that breaks at least one invariant, summed by the database.
+#[derive(Debug, Clone)]
+pub struct GroupTotals {
+    pub id: i64,
+    pub spl_total_lamports: u64,
+    /// Sum of the allocations of the group's buyers
+    pub allocated: u64,
+    /// Tokens the treasury moved to the group minus those it got back
+    pub funding: i64,
+}
+
+impl GroupTotals {
+    pub fn violations(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if self.allocated > self.spl_total_lamports {
+            violations.push(Violation {
+                invariant: Invariant::GroupTotal,
+                message: format!(
+                    "Buyers are allocated {} lamports but spl_total_lamports is {}",
+                    self.allocated, self.spl_total_lamports
+                ),
+            });
+        }
+        if self.spl_total_lamports as i64 != self.funding {
+            violations.push(Violation {
+                invariant: Invariant::LedgerBalance,
+                message: format!(
+                    "spl_total_lamports is {} but the treasury funded the group with {}",
+                    self.spl_total_lamports, self.funding
+                ),
+            });
+        }
+        violations
+    }
+}
+
+/// Result of checking every invariant, with the violations by wallet and by group.
+#[derive(Debug, Clone, Serialize)]
+pub struct InvariantReport {
+    pub checked_at: NaiveDateTime,
+    pub buyers_checked: usize,
+    pub groups_checked: usize,
+    pub violations: usize,
+    pub wallets: BTreeMap<String, Vec<Violation>>,
+    pub groups: BTreeMap<i64, Vec<Violation>>,
+}
+
+impl InvariantReport {
+    pub fn new(buyers_checked: usize, groups_checked: usize) -> Self {
+        InvariantReport {
+            checked_at: chrono::Utc::now().naive_utc(),
+            buyers_checked,
+            groups_checked,
+            violations: 0,
+            wallets: BTreeMap::new(),
+            groups: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.violations == 0
+    }
+
+    pub fn push_wallet(&mut self, wallet: String, violations: Vec<Violation>) {
+        if !violations.is_empty() {
+            self.violations += violations.len();
+            self.wallets.entry(wallet).or_default().extend(violations);
+        }
+    }
+
+    pub fn push_group(&mut self, group_id: i64, violations: Vec<Violation>) {
+        if !violations.is_empty() {
+            self.violations += violations.len();
+            self.groups.entry(group_id).or_default().extend(violations);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn totals() -> BuyerTotals {
+        BuyerTotals {
+            wallet: "wallet".to_string(),
+            received_spl_lamports: 500_000,
+            pending_spl_lamports: 500_000,
+            received_percent: 50.0,
+            allocation: 1_000_000,
+            scheduled: 1_000_000,
+            vested: 500_000,
+            last_schedule_id: Some(2),
+            last_percent: Some(50.0),
+            sent_manually: 0,
+            transferred: 0,
+            received_balance: 500_000,
+            pending_balance: 500_000,
+        }
+    }
+
+    #[test]
+    fn consistent_buyer_has_no_violations() {
+        assert!(totals().violations().is_empty());
+        let unsent = BuyerTotals {
+            received_percent: 0.0,
+            last_schedule_id: None,
+            last_percent: None,
+            ..totals()
+        };
+        assert!(unsent.violations().is_empty());
+    }
+
+    #[test]
+    fn drifted_pending_breaks_the_schedule_and_the_ledger() {
+        let buyer = BuyerTotals {
+            pending_spl_lamports: 750_000,
+            ..totals()
+        };
+        let invariants: Vec<Invariant> = buyer.violations().iter().map(|v| v.invariant).collect();
+        assert_eq!(
+            invariants,
+            [Invariant::PendingBalance, Invariant::LedgerBalance]
+        );
+    }
+
+    #[test]
+    fn schedule_sends_are_matched_to_the_status() {
+        let sends = |status: &str, successes, sent_lamports| ScheduleSends {
+            id: 1,
+            buyer_wallet: "wallet".to_string(),
+            status: status.to_string(),
+            amount_lamports: 100,
+            successes,
+            transaction_id: (successes > 0).then_some(7),
+            sent_lamports,
+        };
+        assert!(sends("success", 1, 100).violation().is_none());
+        assert!(sends("pending", 0, 0).violation().is_none());
+        for broken in [
+            sends("success", 0, 0),
+            sends("success", 1, 90),
+            sends("success", 2, 200),
+            sends("failed", 1, 100),
+        ] {
+            let violation = broken.violation().unwrap();
+            assert_eq!(violation.invariant, Invariant::ScheduleTransactions);
+        }
+    }
+
+    #[test]
+    fn report_collects_violations_of_a_wallet() {
+        let mut report = InvariantReport::new(1, 0);
+        let duplicate = DuplicatePercent {
+            buyer_wallet: "wallet".to_string(),
+            percent_key: 250_000,
+            schedule_ids: "3,4".to_string(),
+        };
+        report.push_wallet("wallet".to_string(), vec![duplicate.violation()]);
+        report.push_wallet("wallet".to_string(), totals().violations());
+        report.push_wallet(
+            "wallet".to_string(),
+            BuyerTotals {
+                scheduled: 0,
+                ..totals()
+            }
+            .violations(),
+        );
+
+        assert_eq!(report.violations, 2);
+        assert_eq!(
+            report.wallets["wallet"][0].message,
+            "Schedules [3, 4] are all at 25%"
+        );
+        assert!(!report.is_consistent());
+    }
+}
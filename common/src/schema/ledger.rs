@@ -0,0 +1,179 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{Cursor, SortValue};
+
+/// Kinds of token movements recorded in the ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Movement {
+    /// Tokens assigned from the treasury to a group, or from a group to a buyer
+    Allocation,
+    /// A schedule sent to the buyer
+    Unlock,
+    /// A manual transfer to the buyer
+    Transfer,
+    /// A manual correction of the buyer's received balance
+    Adjustment,
+    /// An allocation taken back: buyer removed or moved, group shrunk or deleted
+    Revocation,
+    /// Allocation given back because the buyer's purchase was lowered
+    Refund,
+}
+
+impl Movement {
+    pub const ALL: [Movement; 6] = [
+        Movement::Allocation,
+        Movement::Unlock,
+        Movement::Transfer,
+        Movement::Adjustment,
+        Movement::Revocation,
+        Movement::Refund,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Movement::Allocation => "allocation",
+            Movement::Unlock => "unlock",
+            Movement::Transfer => "transfer",
+            Movement::Adjustment => "adjustment",
+            Movement::Revocation => "revocation",
+            Movement::Refund => "refund",
+        }
+    }
+
+    pub fn parse(movement: &str) -> Option<Movement> {
+        Movement::ALL.into_iter().find(|m| m.as_str() == movement)
+    }
+}
+
+impl std::fmt::Display for Movement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Accounts of the ledger. The balance of an account is its debits minus its credits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Account {
+    /// Tokens of the main wallet not assigned to any group
+    Treasury,
+    /// Tokens of a group not allocated to a buyer yet
+    Group(i64),
+    /// Allocation of a buyer that wasn't sent yet, `pending_spl_lamports`
+    BuyerPending(String),
+    /// Tokens a buyer received, `received_spl_lamports`
+    Buyer(String),
+}
+
+impl std::fmt::Display for Account {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Account::Treasury => write!(f, "treasury"),
+            Account::Group(group_id) => write!(f, "group:{}", group_id),
+            Account::BuyerPending(wallet) => write!(f, "buyer:{}:pending", wallet),
+            Account::Buyer(wallet) => write!(f, "buyer:{}", wallet),
+        }
+    }
+}
+
+/// One append-only movement of `amount_lamports` from the credited to the debited account.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct LedgerEntry {
+    pub id: i64,
+    pub movement: String,
+    pub debit_account: String,
+    pub credit_account: String,
+    pub amount_lamports: u64,
+    pub group_id: Option<i64>,
+    pub buyer_wallet: Option<String>,
+    /// What caused the movement, e.g. `schedule:12` or `adjustment:3`.
+    pub reference: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl LedgerEntry {
+    pub fn new(
+        movement: Movement,
+        debit: &Account,
+        credit: &Account,
+        amount_lamports: u64,
+        group_id: Option<i64>,
+        buyer_wallet: Option<&str>,
+    ) -> Self {
+        LedgerEntry {
+            id: 0, //set by DB
+            movement: movement.to_string(),
+            debit_account: debit.to_string(),
+            credit_account: credit.to_string(),
+            amount_lamports,
+            group_id,
+            buyer_wallet: buyer_wallet.map(str::to_string),
+            reference: None,
+            created_at: None, //set by DB
+        }
+    }
+
+    pub fn with_reference(mut self, reference: String) -> Self {
+        self.reference = Some(reference);
+        self
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        Cursor {
+            value: SortValue::Unsigned(self.id as u64),
+            key: self.id.to_string(),
+        }
+    }
+}
+
+/// Filter of `GET /ledger`. Entries are always sorted by `id`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LedgerFilter {
+    pub wallet: Option<String>,
+    pub group_id: Option<i64>,
+    pub movement: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn movements_parse_their_names() {
+        for movement in Movement::ALL {
+            assert_eq!(Movement::parse(movement.as_str()), Some(movement));
+        }
+        assert_eq!(Movement::parse("gift"), None);
+    }
+
+    #[test]
+    fn accounts_of_a_buyer_are_distinct() {
+        let wallet = "7G9abc".to_string();
+        assert_eq!(Account::Buyer(wallet.clone()).to_string(), "buyer:7G9abc");
+        assert_eq!(
+            Account::BuyerPending(wallet).to_string(),
+            "buyer:7G9abc:pending"
+        );
+        assert_eq!(Account::Group(3).to_string(), "group:3");
+        assert_eq!(Account::Treasury.to_string(), "treasury");
+    }
+
+    #[test]
+    fn entry_records_both_accounts() {
+        let entry = LedgerEntry::new(
+            Movement::Unlock,
+            &Account::Buyer("7G9abc".to_string()),
+            &Account::BuyerPending("7G9abc".to_string()),
+            250,
+            Some(1),
+            Some("7G9abc"),
+        )
+        .with_reference("schedule:12".to_string());
+        assert_eq!(entry.movement, "unlock");
+        assert_eq!(entry.debit_account, "buyer:7G9abc");
+        assert_eq!(entry.credit_account, "buyer:7G9abc:pending");
+        assert_eq!(entry.reference.as_deref(), Some("schedule:12"));
+        assert_eq!(entry.cursor().key, entry.id.to_string());
+    }
+}
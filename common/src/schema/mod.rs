@@ -1,11 +1,35 @@
+mod adjustment;
+mod api_key;
+mod approval;
+mod audit;
 mod buyer;
 mod group;
+mod invariant;
+mod ledger;
+mod page;
 mod schedule;
+mod session;
+mod stats;
 mod transaction;
+mod upload;
 mod users;
+mod vesting;
+mod webhook;
 
+pub use adjustment::*;
+pub use api_key::*;
+pub use approval::*;
+pub use audit::*;
 pub use buyer::*;
 pub use group::*;
+pub use invariant::*;
+pub use ledger::*;
+pub use page::*;
 pub use schedule::*;
+pub use session::*;
+pub use stats::*;
 pub use transaction::*;
+pub use upload::*;
 pub use users::*;
+pub use vesting::*;
+pub use webhook::*;
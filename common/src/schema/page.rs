@@ -0,0 +1,243 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{Buyer, Schedule, Transaction};
+
+pub const DEFAULT_PAGE_LIMIT: u32 = 100;
+pub const MAX_PAGE_LIMIT: u32 = 1000;
+
+/// One page of a list endpoint. `next_cursor` is `None` on the last page.
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from rows fetched with `limit + 1`, the extra row only tells that more rows exist.
+    pub fn from_rows(mut rows: Vec<T>, limit: u32, cursor: impl Fn(&T) -> Cursor) -> Self {
+        let has_more = rows.len() > limit as usize;
+        rows.truncate(limit as usize);
+        let next_cursor = if has_more {
+            rows.last().map(|last| cursor(last).encode())
+        } else {
+            None
+        };
+        Page {
+            items: rows,
+            next_cursor,
+        }
+    }
+}
+
+/// Value of the sort column of the last row on a page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SortValue {
+    Unsigned(u64),
+    Float(f64),
+    Time(NaiveDateTime),
+    Text(String),
+}
+
+/// Position after which the next page starts: sort value and primary key of the last row.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cursor {
+    pub value: SortValue,
+    pub key: String,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(cursor: &str) -> anyhow::Result<Self> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|e| anyhow::anyhow!("Invalid cursor: {}", e))?;
+        serde_json::from_slice(&bytes).map_err(|e| anyhow::anyhow!("Invalid cursor: {}", e))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+
+    /// Comparison that selects rows after the cursor in this order.
+    pub fn after_sql(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => ">",
+            SortOrder::Desc => "<",
+        }
+    }
+}
+
+/// Paging parameters shared by all list endpoints.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PageQuery {
+    #[serde(default = "default_page_limit")]
+    pub limit: u32,
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+fn default_page_limit() -> u32 {
+    DEFAULT_PAGE_LIMIT
+}
+
+impl PageQuery {
+    /// Checks the limit and decodes the cursor.
+    pub fn validate(&self) -> anyhow::Result<Option<Cursor>> {
+        if self.limit == 0 || self.limit > MAX_PAGE_LIMIT {
+            anyhow::bail!("limit must be in range [1, {}]", MAX_PAGE_LIMIT);
+        }
+        self.cursor.as_deref().map(Cursor::decode).transpose()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuyerSort {
+    #[default]
+    Wallet,
+    PaidLamports,
+    ReceivedPercent,
+    PendingSplLamports,
+}
+
+impl BuyerSort {
+    pub fn column(&self) -> &'static str {
+        match self {
+            BuyerSort::Wallet => "wallet",
+            BuyerSort::PaidLamports => "paid_lamports",
+            BuyerSort::ReceivedPercent => "received_percent",
+            BuyerSort::PendingSplLamports => "pending_spl_lamports",
+        }
+    }
+
+    pub fn cursor(&self, buyer: &Buyer) -> Cursor {
+        let value = match self {
+            BuyerSort::Wallet => SortValue::Text(buyer.wallet.to_string()),
+            BuyerSort::PaidLamports => SortValue::Unsigned(buyer.paid_lamports),
+            BuyerSort::ReceivedPercent => SortValue::Float(buyer.received_percent),
+            BuyerSort::PendingSplLamports => SortValue::Unsigned(buyer.pending_spl_lamports),
+        };
+        Cursor {
+            value,
+            key: buyer.wallet.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleSort {
+    #[default]
+    Id,
+    ScheduledAt,
+    AmountLamports,
+}
+
+impl ScheduleSort {
+    pub fn column(&self) -> &'static str {
+        match self {
+            ScheduleSort::Id => "id",
+            ScheduleSort::ScheduledAt => "scheduled_at",
+            ScheduleSort::AmountLamports => "amount_lamports",
+        }
+    }
+
+    pub fn cursor(&self, schedule: &Schedule) -> Cursor {
+        let value = match self {
+            ScheduleSort::Id => SortValue::Unsigned(schedule.id as u64),
+            ScheduleSort::ScheduledAt => SortValue::Time(schedule.scheduled_at),
+            ScheduleSort::AmountLamports => SortValue::Unsigned(schedule.amount_lamports),
+        };
+        Cursor {
+            value,
+            key: schedule.id.to_string(),
+        }
+    }
+}
+
+/// Transactions are sorted by `id` by default, which is the order they were sent in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionSort {
+    #[default]
+    Id,
+    AmountLamports,
+}
+
+impl TransactionSort {
+    pub fn column(&self) -> &'static str {
+        match self {
+            TransactionSort::Id => "id",
+            TransactionSort::AmountLamports => "amount_lamports",
+        }
+    }
+
+    pub fn cursor(&self, transaction: &Transaction) -> Cursor {
+        let value = match self {
+            TransactionSort::Id => SortValue::Unsigned(transaction.id as u64),
+            TransactionSort::AmountLamports => SortValue::Unsigned(transaction.amount_lamports),
+        };
+        Cursor {
+            value,
+            key: transaction.id.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct BuyerFilter {
+    pub wallet: Option<String>,
+    pub group_id: Option<i64>,
+    pub min_paid_lamports: Option<u64>,
+    pub max_paid_lamports: Option<u64>,
+    #[serde(default)]
+    pub sort: BuyerSort,
+}
+
+/// `from`/`to` filter on `scheduled_at`, amount filters on `amount_lamports`.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ScheduleFilter {
+    pub wallet: Option<String>,
+    pub group_id: Option<i64>,
+    pub status: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub min_amount: Option<u64>,
+    pub max_amount: Option<u64>,
+    #[serde(default)]
+    pub sort: ScheduleSort,
+}
+
+/// `from`/`to` filter on `sent_at`, amount filters on `amount_lamports`.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct TransactionFilter {
+    pub wallet: Option<String>,
+    pub group_id: Option<i64>,
+    pub status: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub min_amount: Option<u64>,
+    pub max_amount: Option<u64>,
+    #[serde(default)]
+    pub sort: TransactionSort,
+}
@@ -9,7 +9,7 @@ pub struct Schedule {
     pub scheduled_at: NaiveDateTime,
     pub amount_lamports: u64,
     pub percent: f64,
-    pub status: String, // "pending",  "success", "failed"
+    pub status: String, // "pending", "processing", "success", "failed", "interrupted"
     pub error_message: Option<String>,
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
@@ -0,0 +1,51 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+
+/// One login of a user. Its id is carried in the user's tokens, and a revoked or expired
+/// session can't get new access tokens.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Session {
+    pub id: String,
+    pub user_id: i64,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub last_seen_at: Option<NaiveDateTime>,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    /// Password checked, waiting for the TOTP code. No tokens are issued for it yet.
+    pub pending_totp: bool,
+}
+
+impl Session {
+    pub fn new(
+        user_id: i64,
+        lifetime: Duration,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+    ) -> Self {
+        let bytes: [u8; 16] = rand::rng().random();
+        let now = Utc::now().naive_utc();
+        Session {
+            id: bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            user_id,
+            ip_address,
+            user_agent,
+            created_at: Some(now),
+            last_seen_at: Some(now),
+            expires_at: now + lifetime,
+            revoked_at: None,
+            pending_totp: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.pending_totp && self.is_open()
+    }
+
+    /// Neither revoked nor expired.
+    pub fn is_open(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > Utc::now().naive_utc()
+    }
+}
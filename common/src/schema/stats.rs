@@ -0,0 +1,112 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// Aggregated distribution progress of one group or of all groups.
+#[derive(Debug, Clone, Serialize)]
+pub struct DistributionStats {
+    pub group_id: Option<i64>,
+    pub tokens: TokenStats,
+    pub buyers: BuyerStats,
+    pub overdue: UnlockBucket,
+    pub upcoming: Vec<UnlockBucket>,
+    pub failures: FailureStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenStats {
+    pub spl_total_lamports: u64,
+    /// Sum of buyer allocations (`paid_lamports / spl_price_lamports`).
+    pub allocated_lamports: u64,
+    pub distributed_lamports: u64,
+    /// `spl_total_lamports - distributed_lamports`
+    pub remaining_lamports: u64,
+    /// `spl_total_lamports - allocated_lamports`
+    pub unallocated_lamports: u64,
+    /// `allocated_lamports - distributed_lamports`, still owed to buyers.
+    pub outstanding_lamports: u64,
+}
+
+/// Buyers by completion state.
+#[derive(Debug, Clone, Serialize)]
+pub struct BuyerStats {
+    pub total: i64,
+    pub not_started: i64,
+    pub in_progress: i64,
+    pub completed: i64,
+}
+
+/// Unsent schedules grouped by the day or week (starting on Monday) of `scheduled_at`.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnlockBucket {
+    pub period_start: Option<NaiveDate>,
+    pub schedules: i64,
+    pub amount_lamports: u64,
+}
+
+/// Unsent schedules that are already due, by status.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleBacklog {
+    pub status: String,
+    pub schedules: i64,
+    pub oldest_scheduled_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureStats {
+    pub schedules_total: i64,
+    pub schedules_failed: i64,
+    pub schedule_failure_rate: f64,
+    pub transactions_total: i64,
+    pub transactions_failed: i64,
+    pub transaction_failure_rate: f64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsBucket {
+    #[default]
+    Day,
+    Week,
+}
+
+impl StatsBucket {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatsBucket::Day => "day",
+            StatsBucket::Week => "week",
+        }
+    }
+}
+
+/// Range of the upcoming unlocks in the stats.
+#[derive(Debug, Clone, Copy)]
+pub struct StatsWindow {
+    pub now: NaiveDateTime,
+    pub until: NaiveDateTime,
+    pub bucket: StatsBucket,
+}
+
+impl FailureStats {
+    pub fn new(
+        schedules_total: i64,
+        schedules_failed: i64,
+        transactions_total: i64,
+        transactions_failed: i64,
+    ) -> Self {
+        let rate = |failed: i64, total: i64| {
+            if total == 0 {
+                0.0
+            } else {
+                failed as f64 / total as f64
+            }
+        };
+        FailureStats {
+            schedules_total,
+            schedules_failed,
+            schedule_failure_rate: rate(schedules_failed, schedules_total),
+            transactions_total,
+            transactions_failed,
+            transaction_failure_rate: rate(transactions_failed, transactions_total),
+        }
+    }
+}
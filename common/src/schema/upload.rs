@@ -0,0 +1,99 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::Buyer;
+
+/// A buyers file uploaded through the API.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BuyerUpload {
+    pub id: i64,
+    pub sha256: String,
+    pub filename: Option<String>,
+    pub uploaded_by: Option<String>,
+    pub mode: String,   // "append", "sync"
+    pub status: String, // "pending", "applying", "applied"
+    pub changes: Option<String>,
+    pub summary: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl BuyerUpload {
+    pub fn new(
+        sha256: String,
+        filename: Option<String>,
+        uploaded_by: Option<String>,
+        mode: &str,
+        status: &str,
+    ) -> Self {
+        BuyerUpload {
+            id: 0, //set by DB
+            sha256,
+            filename,
+            uploaded_by,
+            mode: mode.to_string(),
+            status: status.to_string(),
+            changes: None,
+            summary: None,
+            created_at: None, //set by DB
+            updated_at: None, //set by DB
+        }
+    }
+}
+
+/// Difference between a full buyers list and the buyers stored in the database.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncDiff {
+    pub additions: Vec<Buyer>,
+    pub removals: Vec<Buyer>,
+    pub changes: Vec<BuyerChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuyerChange {
+    pub wallet: String,
+    pub old_paid_lamports: u64,
+    pub new_paid_lamports: u64,
+    pub old_group_id: i64,
+    pub new_group_id: i64,
+}
+
+impl SyncDiff {
+    /// Compares the buyers of a file with the stored ones, keyed by wallet.
+    pub fn between(stored: &[Buyer], uploaded: &[Buyer]) -> Self {
+        let stored_map: std::collections::HashMap<_, _> =
+            stored.iter().map(|b| (b.wallet, b)).collect();
+        let uploaded_wallets: std::collections::HashSet<_> =
+            uploaded.iter().map(|b| b.wallet).collect();
+
+        let mut diff = SyncDiff::default();
+        for buyer in uploaded {
+            match stored_map.get(&buyer.wallet) {
+                None => diff.additions.push(buyer.clone()),
+                Some(current) => {
+                    if current.paid_lamports != buyer.paid_lamports
+                        || current.group_id != buyer.group_id
+                    {
+                        diff.changes.push(BuyerChange {
+                            wallet: buyer.wallet.to_string(),
+                            old_paid_lamports: current.paid_lamports,
+                            new_paid_lamports: buyer.paid_lamports,
+                            old_group_id: current.group_id,
+                            new_group_id: buyer.group_id,
+                        });
+                    }
+                }
+            }
+        }
+        diff.removals = stored
+            .iter()
+            .filter(|b| !uploaded_wallets.contains(&b.wallet))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.additions.is_empty() && self.removals.is_empty() && self.changes.is_empty()
+    }
+}
@@ -6,6 +6,8 @@ use fancy_regex::Regex;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
+use crate::schema::API_KEY_USER_PREFIX;
+
 #[derive(
     Debug, Clone, sqlx::FromRow, Serialize, Deserialize, actix_jwt_auth_middleware::FromRequest,
 )]
@@ -13,18 +15,49 @@ pub struct User {
     pub id: i64,
     pub username: String,
     pub email: String,
+    /// Never serialized, so it isn't sent in responses or tokens.
+    #[serde(default, skip_serializing)]
     pub password_hash: String,
+    /// One of `Role`, unknown values get the viewer permissions.
+    #[serde(default)]
+    pub role: String,
+    /// Kept in sync with `role`: only admins are superusers.
     pub is_superuser: bool,
+    /// Deactivated users can't log in.
+    #[serde(default = "default_active")]
+    pub active: bool,
+    /// Session of the login the token was issued for, only set in tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
 }
 
+fn default_active() -> bool {
+    true
+}
+
 impl User {
     pub fn new(
         username: &str,
         email: &str,
         password: &str,
         is_superuser: bool,
+    ) -> anyhow::Result<Self> {
+        let role = if is_superuser {
+            Role::Admin
+        } else {
+            Role::Viewer
+        };
+        User::with_role(username, email, password, role)
+    }
+
+    /// Validates the input and hashes the password of a new user.
+    pub fn with_role(
+        username: &str,
+        email: &str,
+        password: &str,
+        role: Role,
     ) -> anyhow::Result<Self> {
         if !validate_username(username)? {
             return Err(anyhow!(
@@ -32,10 +65,33 @@ impl User {
             ));
         }
 
+        let mut user = User {
+            id: 0, //set by DB
+            username: username.to_string(),
+            email: String::new(),
+            password_hash: String::new(),
+            role: role.as_str().to_string(),
+            is_superuser: role == Role::Admin,
+            active: true,
+            session_id: None,
+            created_at: None, //set by DB
+            updated_at: None, //set by DB
+        };
+        user.set_email(email)?;
+        user.set_password(password)?;
+        Ok(user)
+    }
+
+    pub fn set_email(&mut self, email: &str) -> anyhow::Result<()> {
         if !validate_email(email)? {
             return Err(anyhow!("Invalid email address."));
         }
+        self.email = email.to_string();
+        Ok(())
+    }
 
+    /// Validates the password and stores its Argon2 hash.
+    pub fn set_password(&mut self, password: &str) -> anyhow::Result<()> {
         if !validate_password(password)? {
             return Err(anyhow!(
                 "Password must be at least 8 characters long and include at least one lowercase letter, one uppercase letter, and one number."
@@ -44,20 +100,28 @@ impl User {
 
         // Hash the password
         let salt = SaltString::generate(&mut OsRng);
-        let password_hash = Argon2::default()
+        self.password_hash = Argon2::default()
             .hash_password(password.as_bytes(), &salt)
             .map_err(|e| anyhow!("Failed to hash password: {e}"))?
             .to_string();
+        Ok(())
+    }
 
-        Ok(User {
-            id: 0, //set by DB
-            username: username.to_string(),
-            email: email.to_string(),
-            password_hash,
-            is_superuser,
-            created_at: None, //set by DB
-            updated_at: None, //set by DB
-        })
+    pub fn role(&self) -> Role {
+        Role::parse(&self.role).unwrap_or(Role::Viewer)
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        // Approving needs a person who logged in, whatever role a key has
+        if permission == Permission::Approve && self.is_api_key() {
+            return false;
+        }
+        self.role().permissions().contains(&permission)
+    }
+
+    /// Acting with an API key rather than a login.
+    pub fn is_api_key(&self) -> bool {
+        self.username.starts_with(API_KEY_USER_PREFIX)
     }
 
     pub fn verify_password(&self, password: &str) -> anyhow::Result<()> {
@@ -69,6 +133,96 @@ impl User {
     }
 }
 
+/// Second factor of a user.
+#[derive(Debug, Clone, Default, Serialize, sqlx::FromRow)]
+pub struct UserTotp {
+    /// Base32 secret, set while enrolling or enrolled.
+    #[serde(skip_serializing)]
+    pub secret: Option<String>,
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+impl UserTotp {
+    /// Enrollment started but not confirmed with a code yet.
+    pub fn is_pending(&self) -> bool {
+        self.secret.is_some() && !self.enabled
+    }
+}
+
+/// Roles of users, each with a fixed set of permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Approver,
+    Admin,
+}
+
+/// What a role is allowed to do; every route requires one of these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Read buyers, groups, schedules, transactions, stats and events
+    Read,
+    ManageBuyers,
+    ManageGroups,
+    /// Trigger transfers, e.g. retrying failed schedules
+    SendTokens,
+    ControlRunner,
+    /// Approve changes requested by other users
+    Approve,
+    ManageWebhooks,
+    ViewAudit,
+    ManageUsers,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Viewer, Role::Operator, Role::Approver, Role::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Approver => "approver",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|r| r.as_str() == role)
+    }
+
+    pub fn permissions(&self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Role::Viewer => &[Read],
+            Role::Operator => &[Read, ManageBuyers, ManageGroups, SendTokens, ControlRunner],
+            Role::Approver => &[Read, Approve, ViewAudit],
+            Role::Admin => &[
+                Read,
+                ManageBuyers,
+                ManageGroups,
+                SendTokens,
+                ControlRunner,
+                Approve,
+                ManageWebhooks,
+                ViewAudit,
+                ManageUsers,
+            ],
+        }
+    }
+
+    /// Has every permission of `other`.
+    pub fn includes(&self, other: Role) -> bool {
+        other
+            .permissions()
+            .iter()
+            .all(|p| self.permissions().contains(p))
+    }
+}
+
 fn validate_username(username: &str) -> anyhow::Result<bool> {
     static RE: Lazy<Option<Regex>> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_]{3,}$").ok());
     match &*RE {
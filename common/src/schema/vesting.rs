@@ -0,0 +1,131 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::schema::{Buyer, Schedule, Transaction};
+
+/// Vesting progress of one buyer, built from `buyers`, `schedule` and `transactions`.
+#[derive(Debug, Clone, Serialize)]
+pub struct VestingSummary {
+    pub wallet: String,
+    pub group_id: i64,
+    pub total_allocation: u64,
+    pub received_spl_lamports: u64,
+    pub pending_spl_lamports: u64,
+    pub percent_vested: f64,
+    pub next_unlock: Option<NextUnlock>,
+    pub timeline: Vec<Tranche>,
+    /// Transactions that are not linked to a schedule (sent before schedules were recorded on them).
+    pub unlinked_transactions: Vec<TransactionLink>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NextUnlock {
+    pub schedule_id: i64,
+    pub scheduled_at: NaiveDateTime,
+    pub amount_lamports: u64,
+    pub overdue: bool,
+}
+
+/// One scheduled unlock with the transactions that tried to send it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Tranche {
+    pub schedule_id: i64,
+    pub scheduled_at: NaiveDateTime,
+    pub amount_lamports: u64,
+    pub percent: f64,
+    pub status: String,
+    pub error_message: Option<String>,
+    pub transactions: Vec<TransactionLink>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionLink {
+    pub id: i64,
+    pub status: String,
+    pub signature: Option<String>,
+    pub sent_at: Option<NaiveDateTime>,
+}
+
+impl From<&Transaction> for TransactionLink {
+    fn from(tx: &Transaction) -> Self {
+        TransactionLink {
+            id: tx.id,
+            status: tx.status.clone(),
+            signature: tx.signature.clone(),
+            sent_at: tx.sent_at,
+        }
+    }
+}
+
+impl VestingSummary {
+    /// `allocation` is the total amount of tokens the buyer gets in the group.
+    pub fn new(
+        buyer: &Buyer,
+        allocation: u64,
+        mut schedules: Vec<Schedule>,
+        transactions: &[Transaction],
+        now: NaiveDateTime,
+    ) -> Self {
+        schedules.sort_by_key(|s| (s.scheduled_at, s.id));
+
+        let timeline: Vec<Tranche> = schedules
+            .iter()
+            .map(|s| Tranche {
+                schedule_id: s.id,
+                scheduled_at: s.scheduled_at,
+                amount_lamports: s.amount_lamports,
+                percent: s.percent,
+                status: s.status.clone(),
+                error_message: s.error_message.clone(),
+                transactions: transactions
+                    .iter()
+                    .filter(|t| t.schedule_id == Some(s.id))
+                    .map(TransactionLink::from)
+                    .collect(),
+            })
+            .collect();
+
+        let unlinked_transactions = transactions
+            .iter()
+            .filter(|t| {
+                t.schedule_id
+                    .is_none_or(|id| !schedules.iter().any(|s| s.id == id))
+            })
+            .map(TransactionLink::from)
+            .collect();
+
+        let next_unlock = schedules
+            .iter()
+            .find(|s| s.status != "success")
+            .map(|s| NextUnlock {
+                schedule_id: s.id,
+                scheduled_at: s.scheduled_at,
+                amount_lamports: s.amount_lamports,
+                overdue: s.scheduled_at <= now,
+            });
+
+        // Manual adjustments are part of `received_spl_lamports` but not of the vesting progress
+        let vested: u64 = schedules
+            .iter()
+            .filter(|s| s.status == "success")
+            .map(|s| s.amount_lamports)
+            .sum();
+        let percent_vested = if allocation == 0 {
+            0.0
+        } else {
+            vested as f64 / allocation as f64
+        };
+
+        VestingSummary {
+            wallet: buyer.wallet.to_string(),
+            group_id: buyer.group_id,
+            total_allocation: allocation,
+            received_spl_lamports: buyer.received_spl_lamports,
+            pending_spl_lamports: allocation.saturating_sub(vested),
+            percent_vested,
+            next_unlock,
+            timeline,
+            unlinked_transactions,
+        }
+    }
+}
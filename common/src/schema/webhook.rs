@@ -0,0 +1,79 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Events that can be sent to webhooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    ScheduleSucceeded,
+    ScheduleFailed,
+    BuyerVested,
+    TreasuryLow,
+    RunnerStalled,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::ScheduleSucceeded => "schedule_succeeded",
+            WebhookEvent::ScheduleFailed => "schedule_failed",
+            WebhookEvent::BuyerVested => "buyer_vested",
+            WebhookEvent::TreasuryLow => "treasury_low",
+            WebhookEvent::RunnerStalled => "runner_stalled",
+        }
+    }
+
+    /// Joins event types for the `events` column.
+    pub fn join(events: &[WebhookEvent]) -> String {
+        events
+            .iter()
+            .map(|e| e.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+fn events_to_list<S>(events: &str, s: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    s.collect_seq(events.split(',').filter(|e| !e.is_empty()))
+}
+
+/// A webhook subscription.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    #[serde(serialize_with = "events_to_list")]
+    pub events: String, // comma separated event types
+    pub active: bool,
+    pub created_by: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl Webhook {
+    pub fn subscribed(&self, event: WebhookEvent) -> bool {
+        self.events.split(',').any(|e| e == event.as_str())
+    }
+}
+
+/// One event sent, or to be sent, to one webhook.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event_type: String,
+    pub payload: String, // JSON data of the event
+    pub status: String,  // "pending", "success", "failed"
+    pub attempts: i32,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
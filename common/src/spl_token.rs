@@ -4,7 +4,7 @@ use anyhow::{Context, Result};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
     commitment_config::CommitmentConfig, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey,
-    signature::Keypair, signer::Signer, system_instruction::create_account,
+    signature::Keypair, signature::Signature, signer::Signer, system_instruction::create_account,
     transaction::Transaction,
 };
 use spl_associated_token_account::{
@@ -274,7 +274,7 @@ impl SplToken {
         destination_token_account: &Pubkey,
         amount: u64,
         decimals: u8,
-    ) -> Result<()> {
+    ) -> Result<Signature> {
         let recent_blockhash = self.client.get_latest_blockhash().await?;
 
         let transfer_instruction = transfer_checked(
@@ -295,9 +295,10 @@ impl SplToken {
             recent_blockhash,
         );
 
-        self.client
+        let signature = self
+            .client
             .send_and_confirm_transaction(&transaction)
             .await?;
-        Ok(())
+        Ok(signature)
     }
 }
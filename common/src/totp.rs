@@ -0,0 +1,112 @@
+use anyhow::anyhow;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Name shown in authenticator apps.
+const ISSUER: &str = "SPL Giver";
+const DIGITS: u32 = 6;
+const STEP_SECONDS: i64 = 30;
+/// Steps before and after the current one that are accepted, for clock drift.
+const ALLOWED_DRIFT: i64 = 1;
+const RECOVERY_CODES: usize = 10;
+
+/// Time-based one-time passwords (RFC 6238) with HMAC-SHA1, 6 digits and 30 second steps,
+/// the defaults of authenticator apps.
+#[derive(Debug, Clone)]
+pub struct Totp {
+    /// Base32 encoded secret.
+    secret: String,
+}
+
+impl Totp {
+    pub fn generate() -> Self {
+        let bytes: [u8; 20] = rand::rng().random();
+        Totp {
+            secret: BASE32_NOPAD.encode(&bytes),
+        }
+    }
+
+    pub fn from_secret(secret: &str) -> anyhow::Result<Self> {
+        let secret = secret.trim().to_uppercase();
+        BASE32_NOPAD
+            .decode(secret.as_bytes())
+            .map_err(|e| anyhow!("Invalid TOTP secret: {e}"))?;
+        Ok(Totp { secret })
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// `otpauth://` URI for QR codes of authenticator apps.
+    pub fn uri(&self, username: &str) -> String {
+        let issuer = ISSUER.replace(' ', "%20");
+        format!(
+            "otpauth://totp/{issuer}:{username}?secret={}&issuer={issuer}&digits={DIGITS}&period={STEP_SECONDS}",
+            self.secret
+        )
+    }
+
+    /// Code of the time step `step`, i.e. unix time / 30.
+    pub fn code_at(&self, step: i64) -> String {
+        let key = BASE32_NOPAD
+            .decode(self.secret.as_bytes())
+            .expect("secret is checked when created");
+        let mut mac = Hmac::<Sha1>::new_from_slice(&key).expect("HMAC accepts any key length");
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+
+        // Dynamic truncation
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+        format!(
+            "{:0width$}",
+            binary % 10u32.pow(DIGITS),
+            width = DIGITS as usize
+        )
+    }
+
+    /// Checks `code` at unix time `now`. Returns the time step it belongs to, so a code
+    /// can be refused when its step was already used.
+    pub fn verify(&self, code: &str, now: i64) -> Option<i64> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() != DIGITS as usize {
+            return None;
+        }
+        let current = now / STEP_SECONDS;
+        (current - ALLOWED_DRIFT..=current + ALLOWED_DRIFT).find(|step| self.code_at(*step) == code)
+    }
+}
+
+/// One-time codes that replace a TOTP code when the authenticator is lost,
+/// formatted like `k3x9-p2mf`.
+pub fn generate_recovery_codes() -> Vec<String> {
+    const ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+    let mut rng = rand::rng();
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let chars: String = (0..8)
+                .map(|_| ALPHABET[rng.random_range(0..ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &chars[..4], &chars[4..])
+        })
+        .collect()
+}
+
+/// SHA-256 of a recovery code, ignoring case, spaces and dashes.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
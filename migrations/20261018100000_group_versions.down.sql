@@ -0,0 +1 @@
+DROP TABLE IF EXISTS `group_versions`;
\ No newline at end of file
@@ -0,0 +1,16 @@
+-- Group configuration history for MySQL
+CREATE TABLE IF NOT EXISTS `group_versions` (
+    id BIGINT PRIMARY KEY AUTO_INCREMENT,
+    group_id BIGINT NOT NULL,
+    version BIGINT NOT NULL,
+    change_type VARCHAR(20) NOT NULL, -- 'create', 'update', 'delete'
+    spl_share_percent DOUBLE NOT NULL,
+    spl_total_lamports BIGINT UNSIGNED NOT NULL,
+    spl_price_lamports BIGINT UNSIGNED NOT NULL,
+    initial_unlock_percent DOUBLE NOT NULL,
+    unlock_interval_seconds BIGINT NOT NULL,
+    unlock_percent_per_interval DOUBLE NOT NULL,
+    changed_by VARCHAR(100),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE KEY uq_group_version (group_id, version)
+);
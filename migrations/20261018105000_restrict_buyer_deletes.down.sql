@@ -0,0 +1,9 @@
+ALTER TABLE `schedule` DROP FOREIGN KEY `schedule_ibfk_2`;
+ALTER TABLE `schedule`
+    ADD CONSTRAINT `schedule_ibfk_2`
+    FOREIGN KEY (buyer_wallet) REFERENCES `buyers`(wallet) ON DELETE CASCADE;
+
+ALTER TABLE `transactions` DROP FOREIGN KEY `transactions_ibfk_1`;
+ALTER TABLE `transactions`
+    ADD CONSTRAINT `transactions_ibfk_1`
+    FOREIGN KEY (buyer_wallet) REFERENCES `buyers`(wallet) ON DELETE CASCADE;
\ No newline at end of file
@@ -0,0 +1,11 @@
+-- Deleting a buyer must not silently drop the record of what was sent to them.
+-- The application only deletes buyers without transfers, these keys make sure of it for MySQL.
+ALTER TABLE `transactions` DROP FOREIGN KEY `transactions_ibfk_1`;
+ALTER TABLE `transactions`
+    ADD CONSTRAINT `transactions_ibfk_1`
+    FOREIGN KEY (buyer_wallet) REFERENCES `buyers`(wallet) ON DELETE RESTRICT;
+
+ALTER TABLE `schedule` DROP FOREIGN KEY `schedule_ibfk_2`;
+ALTER TABLE `schedule`
+    ADD CONSTRAINT `schedule_ibfk_2`
+    FOREIGN KEY (buyer_wallet) REFERENCES `buyers`(wallet) ON DELETE RESTRICT;
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS `buyer_uploads`;
\ No newline at end of file
@@ -0,0 +1,13 @@
+-- Buyers file uploads history for MySQL
+CREATE TABLE IF NOT EXISTS `buyer_uploads` (
+    id BIGINT PRIMARY KEY AUTO_INCREMENT,
+    sha256 CHAR(64) NOT NULL UNIQUE,
+    filename VARCHAR(255),
+    uploaded_by VARCHAR(100),
+    mode VARCHAR(20) NOT NULL, -- 'append', 'sync'
+    status VARCHAR(20) NOT NULL, -- 'pending', 'applied'
+    changes TEXT, -- JSON diff of a sync upload
+    summary TEXT, -- JSON result of applying the upload
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+);
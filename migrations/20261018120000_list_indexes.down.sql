@@ -0,0 +1,11 @@
+DROP INDEX idx_transactions_amount_lamports ON `transactions`;
+DROP INDEX idx_transactions_sent_at ON `transactions`;
+DROP INDEX idx_transactions_status ON `transactions`;
+
+DROP INDEX idx_schedule_amount_lamports ON `schedule`;
+DROP INDEX idx_schedule_scheduled_at ON `schedule`;
+DROP INDEX idx_schedule_status_scheduled_at ON `schedule`;
+
+DROP INDEX idx_buyers_pending_spl_lamports ON `buyers`;
+DROP INDEX idx_buyers_received_percent ON `buyers`;
+DROP INDEX idx_buyers_paid_lamports ON `buyers`;
\ No newline at end of file
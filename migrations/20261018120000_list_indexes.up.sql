@@ -0,0 +1,13 @@
+-- Indexes for filtering, sorting and keyset pagination of list endpoints.
+-- InnoDB appends the primary key to secondary indexes, so `(column)` also serves `ORDER BY column, id`.
+CREATE INDEX idx_buyers_paid_lamports ON `buyers` (paid_lamports);
+CREATE INDEX idx_buyers_received_percent ON `buyers` (received_percent);
+CREATE INDEX idx_buyers_pending_spl_lamports ON `buyers` (pending_spl_lamports);
+
+CREATE INDEX idx_schedule_status_scheduled_at ON `schedule` (status, scheduled_at);
+CREATE INDEX idx_schedule_scheduled_at ON `schedule` (scheduled_at);
+CREATE INDEX idx_schedule_amount_lamports ON `schedule` (amount_lamports);
+
+CREATE INDEX idx_transactions_status ON `transactions` (status);
+CREATE INDEX idx_transactions_sent_at ON `transactions` (sent_at);
+CREATE INDEX idx_transactions_amount_lamports ON `transactions` (amount_lamports);
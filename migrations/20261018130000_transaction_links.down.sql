@@ -0,0 +1,4 @@
+ALTER TABLE `transactions`
+    DROP INDEX idx_transactions_schedule_id,
+    DROP COLUMN signature,
+    DROP COLUMN schedule_id;
\ No newline at end of file
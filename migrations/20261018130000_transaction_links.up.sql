@@ -0,0 +1,5 @@
+-- Link transactions to the schedule they paid and store the on-chain signature
+ALTER TABLE `transactions`
+    ADD COLUMN schedule_id BIGINT NULL AFTER id,
+    ADD COLUMN signature VARCHAR(100) NULL AFTER error_message,
+    ADD INDEX idx_transactions_schedule_id (schedule_id);
@@ -0,0 +1,22 @@
+-- Append-only log of administrative actions for MySQL
+CREATE TABLE IF NOT EXISTS `audit_log` (
+    id BIGINT PRIMARY KEY AUTO_INCREMENT,
+    source VARCHAR(10) NOT NULL, -- 'api', 'cli'
+    actor VARCHAR(100), -- Username, NULL if the request was not authenticated
+    action VARCHAR(255) NOT NULL, -- 'POST /groups/{group_id}' or CLI command
+    parameters TEXT NOT NULL, -- JSON with path, query and body, secrets redacted
+    result VARCHAR(20) NOT NULL, -- 'success', 'failure'
+    status_code INT, -- HTTP status of API actions
+    result_message TEXT,
+    ip_address VARCHAR(64),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    INDEX idx_audit_log_actor (actor),
+    INDEX idx_audit_log_action (action),
+    INDEX idx_audit_log_created_at (created_at)
+);
+
+CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON `audit_log`
+FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'audit_log is append-only';
+
+CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON `audit_log`
+FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'audit_log is append-only';
@@ -0,0 +1,3 @@
+-- Role of each user: 'viewer', 'operator', 'approver', 'admin'
+ALTER TABLE `users` ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'viewer' AFTER password_hash;
+UPDATE `users` SET role = 'admin' WHERE is_superuser = TRUE;
@@ -0,0 +1,2 @@
+-- Deactivated users can't log in
+ALTER TABLE `users` ADD COLUMN active BOOLEAN NOT NULL DEFAULT TRUE AFTER is_superuser;
@@ -0,0 +1,13 @@
+-- Login sessions for MySQL, referenced by the session id in access and refresh tokens
+CREATE TABLE IF NOT EXISTS `sessions` (
+    id CHAR(32) PRIMARY KEY, -- Random hex id
+    user_id BIGINT NOT NULL,
+    ip_address VARCHAR(64),
+    user_agent VARCHAR(255),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_seen_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    expires_at DATETIME NOT NULL,
+    revoked_at DATETIME,
+    INDEX idx_sessions_user (user_id, revoked_at),
+    FOREIGN KEY (user_id) REFERENCES `users`(id) ON DELETE CASCADE
+);
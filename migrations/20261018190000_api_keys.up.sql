@@ -0,0 +1,15 @@
+-- API keys of service accounts for MySQL; only the SHA-256 hash of a key is stored
+CREATE TABLE IF NOT EXISTS `api_keys` (
+    id BIGINT PRIMARY KEY AUTO_INCREMENT,
+    name VARCHAR(100) NOT NULL,
+    prefix VARCHAR(16) NOT NULL, -- Start of the key, to recognize it
+    key_hash CHAR(64) NOT NULL UNIQUE,
+    role VARCHAR(20) NOT NULL DEFAULT 'viewer',
+    user_id BIGINT, -- Owner the key acts for; deleted owners leave the key record
+    created_by VARCHAR(100),
+    expires_at DATETIME,
+    last_used_at DATETIME,
+    revoked_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES `users`(id) ON DELETE SET NULL
+);
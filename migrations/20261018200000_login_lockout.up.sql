@@ -0,0 +1,4 @@
+-- Wrong passwords in a row and the temporary lockout they cause
+ALTER TABLE `users`
+    ADD COLUMN failed_logins INT NOT NULL DEFAULT 0 AFTER active,
+    ADD COLUMN locked_until DATETIME AFTER failed_logins;
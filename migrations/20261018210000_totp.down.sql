@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS `recovery_codes`;
+ALTER TABLE `sessions` DROP COLUMN pending_totp;
+ALTER TABLE `users` DROP COLUMN totp_last_step, DROP COLUMN totp_enabled, DROP COLUMN totp_secret;
\ No newline at end of file
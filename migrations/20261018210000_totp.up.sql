@@ -0,0 +1,17 @@
+-- TOTP second factor of users, its recovery codes and login sessions waiting for it
+ALTER TABLE `users`
+    ADD COLUMN totp_secret VARCHAR(64) AFTER locked_until, -- Base32, set while enrolling or enrolled
+    ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT FALSE AFTER totp_secret,
+    ADD COLUMN totp_last_step BIGINT AFTER totp_enabled; -- Time step of the last used code, against replays
+
+ALTER TABLE `sessions` ADD COLUMN pending_totp BOOLEAN NOT NULL DEFAULT FALSE AFTER revoked_at;
+
+CREATE TABLE IF NOT EXISTS `recovery_codes` (
+    id BIGINT PRIMARY KEY AUTO_INCREMENT,
+    user_id BIGINT NOT NULL,
+    code_hash CHAR(64) NOT NULL,
+    used_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    INDEX idx_recovery_codes_user (user_id, code_hash),
+    FOREIGN KEY (user_id) REFERENCES `users`(id) ON DELETE CASCADE
+);
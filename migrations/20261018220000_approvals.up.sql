@@ -0,0 +1,16 @@
+-- Four-eyes approval requests for MySQL: operations above the configured threshold wait here
+-- until a different user with the approver role accepts them
+CREATE TABLE IF NOT EXISTS `approvals` (
+    id BIGINT PRIMARY KEY AUTO_INCREMENT,
+    action VARCHAR(50) NOT NULL, -- 'retry_schedules', 'update_group', 'delete_group', 'delete_buyer'
+    parameters TEXT NOT NULL, -- JSON needed to run the operation once approved
+    amount_lamports BIGINT UNSIGNED NOT NULL DEFAULT 0, -- SPL lamports the operation moves or revokes
+    status VARCHAR(20) NOT NULL DEFAULT 'pending', -- 'pending', 'approved', 'rejected', 'executed', 'failed'
+    requested_by VARCHAR(100) NOT NULL,
+    decided_by VARCHAR(100),
+    reason TEXT, -- Given when rejecting
+    result TEXT, -- JSON response of the executed operation or its error
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    decided_at DATETIME,
+    INDEX idx_approvals_status (status)
+);
@@ -0,0 +1,19 @@
+-- Manual transfers and balance adjustments of buyers for MySQL, each with a required reason
+CREATE TABLE IF NOT EXISTS `adjustments` (
+    id BIGINT PRIMARY KEY AUTO_INCREMENT,
+    buyer_wallet VARCHAR(50) NOT NULL,
+    group_id BIGINT NOT NULL,
+    kind VARCHAR(20) NOT NULL, -- 'transfer' sends tokens, 'adjustment' only corrects the balance
+    amount_lamports BIGINT NOT NULL, -- Signed SPL lamports added to received_spl_lamports
+    reason TEXT NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending', -- 'pending', 'processing', 'success', 'failed'
+    transaction_id BIGINT, -- Transaction of a sent transfer
+    error_message TEXT,
+    created_by VARCHAR(100),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    INDEX idx_adjustments_buyer (buyer_wallet),
+    INDEX idx_adjustments_status (status),
+    FOREIGN KEY (buyer_wallet) REFERENCES `buyers`(wallet) ON DELETE RESTRICT,
+    FOREIGN KEY (group_id) REFERENCES `groups`(id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS ledger_entries_no_delete;
+DROP TRIGGER IF EXISTS ledger_entries_no_update;
+DROP TABLE IF EXISTS `ledger_entries`;
\ No newline at end of file
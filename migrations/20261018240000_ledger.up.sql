@@ -0,0 +1,63 @@
+-- Append-only double-entry ledger of token movements for MySQL.
+-- Each entry moves amount_lamports from the credited to the debited account,
+-- the balance of an account is its debits minus its credits.
+CREATE TABLE IF NOT EXISTS `ledger_entries` (
+    id BIGINT PRIMARY KEY AUTO_INCREMENT,
+    movement VARCHAR(20) NOT NULL, -- 'allocation', 'unlock', 'transfer', 'adjustment', 'revocation', 'refund'
+    debit_account VARCHAR(80) NOT NULL, -- 'treasury', 'group:<id>', 'buyer:<wallet>:pending' or 'buyer:<wallet>'
+    credit_account VARCHAR(80) NOT NULL,
+    amount_lamports BIGINT UNSIGNED NOT NULL,
+    group_id BIGINT,
+    buyer_wallet VARCHAR(50),
+    reference VARCHAR(100), -- 'schedule:<id>', 'adjustment:<id>'
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE KEY uq_ledger_entries_reference (movement, reference),
+    INDEX idx_ledger_entries_debit (debit_account),
+    INDEX idx_ledger_entries_credit (credit_account),
+    INDEX idx_ledger_entries_buyer (buyer_wallet),
+    INDEX idx_ledger_entries_group (group_id)
+);
+
+CREATE TRIGGER ledger_entries_no_update BEFORE UPDATE ON `ledger_entries`
+FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'ledger_entries is append-only';
+
+CREATE TRIGGER ledger_entries_no_delete BEFORE DELETE ON `ledger_entries`
+FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'ledger_entries is append-only';
+
+-- Opening balances of the existing groups and buyers
+
+INSERT INTO `ledger_entries` (movement, debit_account, credit_account, amount_lamports, group_id)
+SELECT 'allocation', CONCAT('group:', id), 'treasury', spl_total_lamports, id
+FROM `groups`
+WHERE spl_total_lamports > 0;
+
+-- Manual transfers and adjustments are part of received_spl_lamports, but not of the allocation
+INSERT INTO `ledger_entries` (movement, debit_account, credit_account, amount_lamports, group_id, buyer_wallet)
+SELECT 'allocation', CONCAT('buyer:', b.wallet, ':pending'), CONCAT('group:', b.group_id),
+    CAST(b.pending_spl_lamports AS SIGNED) + CAST(b.received_spl_lamports AS SIGNED) - COALESCE(a.adjusted, 0),
+    b.group_id, b.wallet
+FROM `buyers` b
+LEFT JOIN (
+    SELECT buyer_wallet, SUM(amount_lamports) AS adjusted
+    FROM `adjustments` WHERE status = 'success' GROUP BY buyer_wallet
+) a ON a.buyer_wallet = b.wallet
+WHERE CAST(b.pending_spl_lamports AS SIGNED) + CAST(b.received_spl_lamports AS SIGNED) - COALESCE(a.adjusted, 0) > 0;
+
+INSERT INTO `ledger_entries` (movement, debit_account, credit_account, amount_lamports, group_id, buyer_wallet)
+SELECT 'unlock', CONCAT('buyer:', b.wallet), CONCAT('buyer:', b.wallet, ':pending'),
+    CAST(b.received_spl_lamports AS SIGNED) - COALESCE(a.adjusted, 0),
+    b.group_id, b.wallet
+FROM `buyers` b
+LEFT JOIN (
+    SELECT buyer_wallet, SUM(amount_lamports) AS adjusted
+    FROM `adjustments` WHERE status = 'success' GROUP BY buyer_wallet
+) a ON a.buyer_wallet = b.wallet
+WHERE CAST(b.received_spl_lamports AS SIGNED) - COALESCE(a.adjusted, 0) > 0;
+
+INSERT INTO `ledger_entries` (movement, debit_account, credit_account, amount_lamports, group_id, buyer_wallet, reference)
+SELECT kind,
+    CASE WHEN amount_lamports > 0 THEN CONCAT('buyer:', buyer_wallet) ELSE 'treasury' END,
+    CASE WHEN amount_lamports > 0 THEN 'treasury' ELSE CONCAT('buyer:', buyer_wallet) END,
+    ABS(amount_lamports), group_id, buyer_wallet, CONCAT('adjustment:', id)
+FROM `adjustments`
+WHERE status = 'success';
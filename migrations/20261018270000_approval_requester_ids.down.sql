@@ -0,0 +1 @@
+ALTER TABLE `approvals` DROP COLUMN requested_by_id;
\ No newline at end of file
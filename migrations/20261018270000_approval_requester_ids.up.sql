@@ -0,0 +1,5 @@
+-- The user behind a request, also when it was made with one of their API keys,
+-- so nobody approves their own request under another name
+ALTER TABLE `approvals` ADD COLUMN requested_by_id BIGINT AFTER requested_by;
+
+UPDATE `approvals` a JOIN `users` u ON u.username = a.requested_by SET a.requested_by_id = u.id;
@@ -0,0 +1,146 @@
+use actix_web::{
+    Error, HttpMessage,
+    body::{BoxBody, MessageBody, to_bytes},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::{Method, header::CONTENT_TYPE},
+    middleware::Next,
+    web::{self, Bytes},
+};
+use common::{AuditEntry, User};
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+/// Longest error response body kept in `result_message`.
+const MAX_RESULT_MESSAGE: usize = 1000;
+
+/// Middleware that writes every mutating request to the audit log: the user, route,
+/// path, query and JSON body, the response status and the client IP.
+/// Failing to write the entry is logged and doesn't change the response.
+pub async fn audit_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if !is_mutating(req.method()) {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    }
+
+    let app_state = req.app_data::<web::Data<AppState>>().cloned();
+    let ip_address = app_state
+        .as_ref()
+        .and_then(|s| s.trusted_proxies.client_ip(req.request()));
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let query: Value = query_params(req.query_string());
+    let body = read_body(&mut req).await?;
+
+    let result = next.call(req).await;
+
+    let Some(app_state) = app_state else {
+        return result.map(ServiceResponse::map_into_boxed_body);
+    };
+
+    match result {
+        Ok(res) => {
+            let request = res.request();
+            let route = request.match_pattern().unwrap_or_else(|| path.clone());
+            let path_params: serde_json::Map<String, Value> = request
+                .match_info()
+                .iter()
+                .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+                .collect();
+            let actor = request
+                .extensions()
+                .get::<User>()
+                .map(|u| u.username.clone());
+            let status = res.status();
+
+            let mut entry = AuditEntry::new(
+                "api",
+                actor,
+                format!("{} {}", method, route),
+                json!({ "path": path_params, "query": query, "body": body }),
+            );
+            entry.status_code = Some(status.as_u16() as i32);
+            entry.ip_address = ip_address;
+
+            let res = if status.is_client_error() || status.is_server_error() {
+                // Keep the error message, then give the body back to the client
+                let (request, response) = res.into_parts();
+                let (response, body) = response.into_parts();
+                let bytes = to_bytes(body).await.unwrap_or_default();
+                let message = String::from_utf8_lossy(&bytes)
+                    .chars()
+                    .take(MAX_RESULT_MESSAGE)
+                    .collect::<String>();
+                entry = entry.failed(message);
+                ServiceResponse::new(request, response.set_body(BoxBody::new(bytes)))
+            } else {
+                res.map_into_boxed_body()
+            };
+
+            save_entry(&app_state, &entry).await;
+            Ok(res)
+        }
+        Err(e) => {
+            // Rejected before reaching a handler, e.g. by authentication
+            let mut entry = AuditEntry::new(
+                "api",
+                None,
+                format!("{} {}", method, path),
+                json!({ "query": query, "body": body }),
+            )
+            .failed(e.to_string());
+            entry.status_code = Some(e.as_response_error().status_code().as_u16() as i32);
+            entry.ip_address = ip_address;
+            save_entry(&app_state, &entry).await;
+            Err(e)
+        }
+    }
+}
+
+fn is_mutating(method: &Method) -> bool {
+    [Method::POST, Method::PUT, Method::PATCH, Method::DELETE].contains(method)
+}
+
+fn query_params(query_string: &str) -> Value {
+    let params = web::Query::<serde_json::Map<String, Value>>::from_query(query_string)
+        .map(|q| q.into_inner())
+        .unwrap_or_default();
+    Value::Object(params)
+}
+
+/// Reads a JSON body and puts it back for the handler. Other bodies, like file uploads,
+/// are only recorded by their content type, and a body that isn't valid JSON by its length,
+/// since it can't be redacted.
+async fn read_body(req: &mut ServiceRequest) -> Result<Value, Error> {
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    match content_type {
+        Some(content_type) if content_type.starts_with("application/json") => {
+            let bytes = req.extract::<Bytes>().await?;
+            req.set_payload(Payload::from(bytes.clone()));
+            Ok(serde_json::from_slice(&bytes)
+                .unwrap_or_else(|_| json!({ "invalid_json": true, "length": bytes.len() })))
+        }
+        Some(content_type) => Ok(json!({ "content_type": content_type })),
+        None => Ok(Value::Null),
+    }
+}
+
+async fn save_entry(app_state: &AppState, entry: &AuditEntry) {
+    if let Err(e) = app_state.db.save_audit_entry(entry).await {
+        log::error!(
+            "Failed to write audit entry `{}` by `{}`: {:#}",
+            entry.action,
+            entry.actor.as_deref().unwrap_or("-"),
+            e
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::net::IpAddr;
+
+use actix_web::HttpRequest;
+use anyhow::Context;
+
+/// Reverse proxies whose `X-Forwarded-For` header is believed, read from the environment.
+/// Any client can send the header, so it is ignored on connections from other addresses.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Vec<IpAddr>);
+
+impl TrustedProxies {
+    /// `TRUSTED_PROXIES`, comma separated IP addresses; none if not set.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let Ok(value) = std::env::var("TRUSTED_PROXIES") else {
+            return Ok(TrustedProxies::default());
+        };
+        let proxies = value
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                p.parse::<IpAddr>()
+                    .with_context(|| format!("TRUSTED_PROXIES: `{}` is not an IP address", p))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(TrustedProxies(proxies))
+    }
+
+    /// IP address of the client: the peer address, or behind trusted proxies the last
+    /// address in `X-Forwarded-For` that isn't one of them. Addresses before it were
+    /// sent by the client and can be anything; if it isn't an address, the peer is used.
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<String> {
+        let peer = req.peer_addr()?.ip();
+        if !self.0.contains(&peer) {
+            return Some(peer.to_string());
+        }
+
+        // Each proxy appends the address it saw, so the header is read from the end
+        let forwarded: Vec<Option<IpAddr>> = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|ip| ip.trim().parse::<IpAddr>().ok())
+            .collect();
+        let client = forwarded
+            .into_iter()
+            .rev()
+            .find(|ip| ip.is_none_or(|ip| !self.0.contains(&ip)))
+            .flatten()
+            .unwrap_or(peer);
+        Some(client.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    const PROXY: &str = "10.0.0.1";
+
+    fn request(peer: &str, forwarded_for: Option<&str>) -> HttpRequest {
+        let mut req = TestRequest::default().peer_addr(format!("{}:4000", peer).parse().unwrap());
+        if let Some(forwarded_for) = forwarded_for {
+            req = req.insert_header(("X-Forwarded-For", forwarded_for));
+        }
+        req.to_http_request()
+    }
+
+    fn proxies() -> TrustedProxies {
+        TrustedProxies(vec![PROXY.parse().unwrap()])
+    }
+
+    #[test]
+    fn forwarded_for_from_clients_is_ignored() {
+        let req = request("203.0.113.7", Some("198.51.100.1"));
+        assert_eq!(proxies().client_ip(&req).as_deref(), Some("203.0.113.7"));
+        assert_eq!(
+            TrustedProxies::default().client_ip(&req).as_deref(),
+            Some("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn trusted_proxy_gives_the_client_it_saw() {
+        // The client made up the first address, the proxy appended the real one
+        let req = request(PROXY, Some("198.51.100.1, 203.0.113.7"));
+        assert_eq!(proxies().client_ip(&req).as_deref(), Some("203.0.113.7"));
+    }
+
+    #[test]
+    fn chain_of_trusted_proxies_is_skipped() {
+        let proxies = TrustedProxies(vec![PROXY.parse().unwrap(), "10.0.0.2".parse().unwrap()]);
+        let req = request(PROXY, Some("198.51.100.1, 203.0.113.7, 10.0.0.2"));
+        assert_eq!(proxies.client_ip(&req).as_deref(), Some("203.0.113.7"));
+    }
+
+    #[test]
+    fn proxy_without_forwarded_for_is_the_client() {
+        let req = request(PROXY, None);
+        assert_eq!(proxies().client_ip(&req).as_deref(), Some(PROXY));
+    }
+}
@@ -1,5 +1,9 @@
 use anyhow::Context;
+use common::{ApprovalPolicy, JwtKeys};
+use ed25519_compact::{KeyPair, PublicKey, SecretKey};
 
+use crate::client_ip::TrustedProxies;
+use crate::login_guard::LoginPolicy;
 use crate::state::AppState;
 
 pub struct AppConfig {
@@ -10,6 +14,10 @@ pub struct AppConfig {
     pub mint: String,
     pub database_url: String,
     pub client_url: String,
+    pub jwt_keys: Option<JwtKeys>,
+    pub login_policy: LoginPolicy,
+    pub approval_policy: ApprovalPolicy,
+    pub trusted_proxies: TrustedProxies,
 }
 
 impl AppConfig {
@@ -29,6 +37,14 @@ impl AppConfig {
 
         let mint = std::env::var("MINT_PUBKEY").context("MINT_PUBKEY must be set")?;
 
+        let jwt_keys = JwtKeys::from_env().context("Failed to load JWT keys")?;
+
+        let login_policy = LoginPolicy::from_env()?;
+
+        let approval_policy = ApprovalPolicy::from_env()?;
+
+        let trusted_proxies = TrustedProxies::from_env()?;
+
         Ok(Self {
             pending_json,
             groups_yaml,
@@ -37,18 +53,41 @@ impl AppConfig {
             mint,
             database_url,
             client_url,
+            jwt_keys,
+            login_policy,
+            approval_policy,
+            trusted_proxies,
         })
     }
 
     pub async fn create_app_state(&self) -> anyhow::Result<AppState> {
-        AppState::new(
+        let mut app_state = AppState::new(
             &self.database_url,
             &self.client_url,
             &self.wallet,
             &self.mint,
             &self.pending_json,
+            self.login_policy.clone(),
+            self.approval_policy.clone(),
         )
         .await
-        .context("Failed to initialize AppState")
+        .context("Failed to initialize AppState")?;
+        app_state.trusted_proxies = self.trusted_proxies.clone();
+        Ok(app_state)
+    }
+
+    /// Signing key and verifying keys for JWTs. Without configured keys a temporary key is
+    /// generated, so tokens stop working after a restart.
+    pub fn jwt_keys(&self) -> anyhow::Result<(SecretKey, Vec<PublicKey>)> {
+        match &self.jwt_keys {
+            Some(keys) => Ok((keys.secret_key()?, keys.public_keys()?)),
+            None => {
+                log::warn!(
+                    "JWT_KEYS_FILE or JWT_SIGNING_KEY not set, using a temporary signing key"
+                );
+                let KeyPair { pk, sk } = KeyPair::generate();
+                Ok((sk, vec![pk]))
+            }
+        }
     }
 }
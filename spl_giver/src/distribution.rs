@@ -1,11 +1,13 @@
-use crate::state::{AppState, PendingOp};
+use crate::state::{AppState, InFlightTransfer, PendingOp};
+use crate::webhooks;
 use actix_web::web;
 
-use chrono::Utc;
+use chrono::{NaiveDateTime, Utc};
 use common::SplToken;
-use common::{Buyer, Schedule, Transaction};
-use solana_sdk::pubkey::Pubkey;
-use tokio::time::{Duration, sleep};
+use common::{Adjustment, Buyer, Group, Schedule, Transaction, WebhookEvent};
+use serde_json::json;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use tokio::time::{Duration, Instant, sleep};
 
 pub async fn check_group_token_funding(data: &AppState) -> anyhow::Result<()> {
     let groups = data.db.get_all_groups().await?;
@@ -36,177 +38,566 @@ pub async fn initialize_schedules(app_state: &AppState) -> anyhow::Result<()> {
         log::info!("Distributing tokens for group: {}", group.id);
         let buyers = app_state.db.get_buyers_by_group(group.id).await?;
         for buyer in &buyers {
-            let buyer_spl = buyer.paid_lamports / group.spl_price_lamports;
-            let already_received_lamports = buyer.received_spl_lamports;
+            initialize_buyer_schedules(app_state, &group, buyer).await?;
+        }
+    }
+    log::info!("Schedules created successfully");
 
-            let mut remaining_percent = 1.0 - buyer.received_percent;
-            let mut current_percent = buyer.received_percent;
-            let mut remaining_spl_lamports = buyer_spl - already_received_lamports;
+    Ok(())
+}
 
-            if remaining_spl_lamports == 0 || remaining_percent <= 0.0 {
-                log::info!(
-                    "Buyer {} already received all tokens: received_spl_lamports {}, paid_lamports {}, spl_price_lamports {}",
-                    buyer.wallet,
-                    buyer.received_spl_lamports,
-                    buyer.paid_lamports,
-                    group.spl_price_lamports
-                );
-                continue;
-            }
+/// Total amount of SPL lamports the buyer is entitled to in the group.
+pub fn buyer_allocation(group: &Group, buyer: &Buyer) -> u64 {
+    buyer.paid_lamports / group.spl_price_lamports
+}
 
-            // Get existing schedule percents for this buyer
-            let existing_schedules = app_state
-                .db
-                .get_schedules_by_buyer_and_group(&buyer.wallet.to_string(), group.id)
-                .await?;
-            // 1_000_000.0 to avoid floating point issues
-            let existing_percents: std::collections::HashSet<u64> = existing_schedules
-                .iter()
-                .map(|s| (s.percent * 1_000_000.0).round() as u64)
-                .collect();
-
-            let mut unlock_time = Utc::now().naive_utc();
-            let mut unlocks = vec![];
-
-            // If buyer hasn't received anything, schedule initial unlock first
-            if already_received_lamports == 0 {
-                let percent = group.initial_unlock_percent.min(remaining_percent);
-                let initial_amount = (buyer_spl as f64 * percent).round() as u64;
-                current_percent += percent;
-                let percent_key = (current_percent * 1_000_000.0).round() as u64;
-                if !existing_percents.contains(&percent_key) {
-                    unlocks.push((unlock_time, initial_amount, current_percent));
-                }
-                remaining_spl_lamports -= initial_amount;
-                remaining_percent -= percent;
+/// Sum of the allocations of all buyers in the group, optionally leaving one wallet out.
+pub async fn group_allocation(
+    app_state: &AppState,
+    group: &Group,
+    exclude_wallet: Option<&str>,
+) -> anyhow::Result<u64> {
+    let buyers = app_state.db.get_buyers_by_group(group.id).await?;
+    Ok(buyers
+        .iter()
+        .filter(|b| exclude_wallet != Some(b.wallet.to_string().as_str()))
+        .map(|b| buyer_allocation(group, b))
+        .sum())
+}
+
+/// Creates the missing schedule entries of a single buyer and returns the saved ones.
+pub async fn initialize_buyer_schedules(
+    app_state: &AppState,
+    group: &Group,
+    buyer: &Buyer,
+) -> anyhow::Result<Vec<Schedule>> {
+    // Get existing schedules for this buyer
+    let existing_schedules = app_state
+        .db
+        .get_schedules_by_buyer_and_group(&buyer.wallet.to_string(), group.id)
+        .await?;
+
+    let planned = plan_buyer_schedules(group, buyer, &existing_schedules, Utc::now().naive_utc());
+
+    let mut saved = Vec::with_capacity(planned.len());
+    for mut schedule in planned {
+        // Save schedule entry to DB
+        match app_state.db.save_schedule(&schedule).await {
+            Ok(id) => {
+                schedule.id = id;
+                saved.push(schedule);
             }
+            Err(e) => log::error!("Failed to save schedule for {}: {}", buyer.wallet, e),
+        }
+    }
+    Ok(saved)
+}
 
-            // Schedule future unlocks for the rest
-            while remaining_spl_lamports > 0 && remaining_percent > 0.0 {
-                unlock_time += chrono::Duration::seconds(group.unlock_interval_seconds);
-                let percent = group.unlock_percent_per_interval.min(remaining_percent);
+/// Builds the unlock tranches still owed to the buyer under the group's vesting parameters,
+/// starting at `start`. `existing_schedules` stay as they are: their amounts are taken off
+/// the allocation and the new tranches continue after their highest percent.
+pub fn plan_buyer_schedules(
+    group: &Group,
+    buyer: &Buyer,
+    existing_schedules: &[Schedule],
+    start: NaiveDateTime,
+) -> Vec<Schedule> {
+    let buyer_spl = buyer_allocation(group, buyer);
+    let planned_lamports: u64 = existing_schedules.iter().map(|s| s.amount_lamports).sum();
+    let planned_percent = existing_schedules
+        .iter()
+        .map(|s| s.percent)
+        .fold(buyer.received_percent, f64::max);
 
-                //If this is the last unlock, adjust the amount to not exceed remaining SPL
-                let is_last = remaining_percent <= group.unlock_percent_per_interval
-                    || remaining_spl_lamports <= ((buyer_spl as f64 * percent).round() as u64);
+    let mut remaining_percent = 1.0 - planned_percent;
+    let mut current_percent = planned_percent;
+    let mut remaining_spl_lamports = buyer_spl.saturating_sub(planned_lamports);
 
-                let interval_amount = if is_last {
-                    remaining_spl_lamports
-                } else {
-                    (buyer_spl as f64 * percent).round() as u64
-                };
+    if remaining_spl_lamports == 0 || remaining_percent <= 0.0 {
+        log::info!(
+            "Buyer {} has all tokens scheduled: planned lamports {}, paid_lamports {}, spl_price_lamports {}",
+            buyer.wallet,
+            planned_lamports,
+            buyer.paid_lamports,
+            group.spl_price_lamports
+        );
+        return Vec::new();
+    }
 
-                current_percent += percent;
-                let percent_key = (current_percent * 1_000_000.0).round() as u64;
+    let mut unlock_time = start;
+    let mut unlocks = vec![];
 
-                if !existing_percents.contains(&percent_key) {
-                    unlocks.push((unlock_time, interval_amount, current_percent));
-                }
-                remaining_spl_lamports = remaining_spl_lamports.saturating_sub(interval_amount);
-                remaining_percent -= percent;
-            }
+    // If buyer has no schedules yet, schedule initial unlock first
+    if planned_lamports == 0 {
+        let percent = group.initial_unlock_percent.min(remaining_percent);
+        let initial_amount = (buyer_spl as f64 * percent).round() as u64;
+        current_percent += percent;
+        unlocks.push((unlock_time, initial_amount, current_percent));
+        remaining_spl_lamports -= initial_amount;
+        remaining_percent -= percent;
+    }
 
-            for (scheduled_at, amount_lamports, percent) in unlocks {
-                let schedule = Schedule::new(
-                    group.id,
-                    buyer.wallet.to_string(),
-                    scheduled_at,
-                    amount_lamports,
-                    percent,
-                );
+    // Schedule future unlocks for the rest
+    while remaining_spl_lamports > 0 && remaining_percent > 0.0 {
+        unlock_time += chrono::Duration::seconds(group.unlock_interval_seconds);
+        let percent = group.unlock_percent_per_interval.min(remaining_percent);
 
-                // Save schedule entry to DB
-                if let Err(e) = app_state.db.save_schedule(&schedule).await {
-                    log::error!("Failed to save schedule for {}: {}", buyer.wallet, e);
-                }
-            }
-        }
+        //If this is the last unlock, adjust the amount to not exceed remaining SPL
+        let is_last = remaining_percent <= group.unlock_percent_per_interval
+            || remaining_spl_lamports <= ((buyer_spl as f64 * percent).round() as u64);
+
+        let interval_amount = if is_last {
+            remaining_spl_lamports
+        } else {
+            (buyer_spl as f64 * percent).round() as u64
+        };
+
+        current_percent += percent;
+        unlocks.push((unlock_time, interval_amount, current_percent));
+        remaining_spl_lamports = remaining_spl_lamports.saturating_sub(interval_amount);
+        remaining_percent -= percent;
     }
-    log::info!("Schedules created successfully");
 
+    unlocks
+        .into_iter()
+        .map(|(scheduled_at, amount_lamports, percent)| {
+            Schedule::new(
+                group.id,
+                buyer.wallet.to_string(),
+                scheduled_at,
+                amount_lamports,
+                percent,
+            )
+        })
+        .collect()
+}
+
+/// Lamports sent by the successful schedules, i.e. `received_spl_lamports` without
+/// manual transfers and adjustments.
+pub fn vested_lamports(schedules: &[Schedule]) -> u64 {
+    schedules
+        .iter()
+        .filter(|s| s.status == "success")
+        .map(|s| s.amount_lamports)
+        .sum()
+}
+
+/// Unsent schedules replaced by a re-plan and the schedules generated in their place.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ScheduleDiff {
+    pub removed: Vec<Schedule>,
+    pub created: Vec<Schedule>,
+}
+
+impl ScheduleDiff {
+    fn extend(&mut self, other: ScheduleDiff) {
+        self.removed.extend(other.removed);
+        self.created.extend(other.created);
+    }
+}
+
+/// Shows how the unsent tranches of every buyer in the group would be regenerated from its
+/// vesting parameters, without writing anything. `Database::update_group` does the re-plan.
+pub async fn preview_group_schedules(
+    app_state: &AppState,
+    group: &Group,
+) -> anyhow::Result<ScheduleDiff> {
+    let buyers = app_state.db.get_buyers_by_group(group.id).await?;
+    let now = Utc::now().naive_utc();
+    let mut diff = ScheduleDiff::default();
+    for buyer in &buyers {
+        // Schedules that were sent, are being sent or failed stay, so nothing that may be
+        // on chain is planned again
+        let (removed, kept): (Vec<Schedule>, Vec<Schedule>) = app_state
+            .db
+            .get_schedules_by_buyer_and_group(&buyer.wallet.to_string(), group.id)
+            .await?
+            .into_iter()
+            .partition(|s| s.status == "pending");
+        diff.extend(ScheduleDiff {
+            created: plan_buyer_schedules(group, buyer, &kept, now),
+            removed,
+        });
+    }
+    Ok(diff)
+}
+
+/// Plan for `Database::replan_schedules`, `Database::update_buyer_details` and
+/// `Database::update_group`: the pending allocation is what the kept schedules didn't send
+/// yet, the rest goes to new tranches.
+pub fn replan(
+    group: &Group,
+    now: NaiveDateTime,
+) -> impl Fn(&Buyer, &[Schedule]) -> (u64, Vec<Schedule>) + '_ {
+    move |buyer, kept| {
+        (
+            buyer_allocation(group, buyer).saturating_sub(vested_lamports(kept)),
+            plan_buyer_schedules(group, buyer, kept, now),
+        )
+    }
+}
+
+/// Marks the schedules left `processing` by a previous run as interrupted. Their transfer
+/// may have reached the chain, so unlike failed schedules they are not retried until an
+/// operator resolves them.
+pub async fn recover_interrupted_schedules(app_state: &AppState) -> anyhow::Result<()> {
+    let interrupted = app_state.db.get_schedules_by_status("processing").await?;
+    for schedule in interrupted {
+        log::warn!(
+            "Schedule id={} was being sent when the server stopped, marking it interrupted",
+            schedule.id
+        );
+        app_state
+            .db
+            .update_schedule_status(
+                schedule.id,
+                "interrupted",
+                Some(INTERRUPTED_MESSAGE.to_string()),
+            )
+            .await?;
+    }
     Ok(())
 }
 
+/// Marks manual transfers left `processing` by a stop as interrupted, like schedules: the
+/// tokens may have been sent, so they are checked on chain instead of being sent again.
+pub async fn recover_interrupted_transfers(app_state: &AppState) -> anyhow::Result<()> {
+    let interrupted = app_state.db.get_processing_transfers().await?;
+    for adjustment in interrupted {
+        log::warn!(
+            "Manual transfer id={} was being sent when the server stopped, marking it interrupted",
+            adjustment.id
+        );
+        app_state
+            .db
+            .interrupt_adjustment(adjustment.id, INTERRUPTED_TRANSFER_MESSAGE)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Time between two runner ticks.
+const RUNNER_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the watchdog looks at the runner.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(30);
+/// The runner is restarted if it shows no progress for this long.
+pub const RUNNER_STALL_SECONDS: i64 = 300;
+/// Pause before the watchdog starts the runner again.
+const RUNNER_RESTART_DELAY: Duration = Duration::from_secs(5);
+/// Error of a schedule that was being sent when the server stopped.
+const INTERRUPTED_MESSAGE: &str =
+    "Server stopped while sending this schedule. Check the transfer on chain and resolve it.";
+/// Error of a manual transfer that was being sent when the server stopped.
+const INTERRUPTED_TRANSFER_MESSAGE: &str =
+    "Server stopped while sending this transfer. Check it on chain and resolve it.";
+
 pub async fn start_schedule_runner(app_state: web::Data<AppState>) -> anyhow::Result<()> {
     loop {
         let now = Utc::now().naive_utc();
-        let schedules = app_state.db.get_schedules_due(now).await?;
-        for schedule in schedules {
-            log::info!(
-                "Schedule ready: id={:?} buyer={} group={} amount_lamports={} scheduled_at={}",
-                schedule.id,
-                schedule.buyer_wallet,
-                schedule.group_id,
-                schedule.amount_lamports,
-                schedule.scheduled_at
-            );
+        let paused = {
+            let mut status = app_state.runner.lock().await;
+            status.last_tick = Some(now);
+            status.last_heartbeat = Some(now);
+            status.next_wake_up = None;
+            status.paused
+        };
+
+        app_state
+            .events
+            .runner("tick_started", json!({ "paused": paused }));
+        webhooks::check_treasury(&app_state).await;
+
+        let mut processed = 0;
+        if !paused {
+            let schedules = app_state.db.get_schedules_due(now).await?;
+            for schedule in schedules {
+                if app_state.runner.lock().await.paused {
+                    log::info!("Schedule runner paused, remaining schedules wait for resume");
+                    break;
+                }
+                // A re-plan may have replaced the schedule since it was loaded
+                if !app_state.db.claim_schedule(schedule.id, "pending").await? {
+                    continue;
+                }
+                log::info!(
+                    "Schedule ready: id={:?} buyer={} group={} amount_lamports={} scheduled_at={}",
+                    schedule.id,
+                    schedule.buyer_wallet,
+                    schedule.group_id,
+                    schedule.amount_lamports,
+                    schedule.scheduled_at
+                );
+
+                app_state.runner.lock().await.in_flight = Some(InFlightTransfer {
+                    schedule_id: Some(schedule.id),
+                    adjustment_id: None,
+                    started_at: Utc::now().naive_utc(),
+                });
+                if let Err(e) =
+                    process_schedule(&app_state, &schedule, app_state.spl_token.decimals).await
+                {
+                    log::error!("Failed to process schedule id={}: {:#}", schedule.id, e);
+                }
+                processed += 1;
+
+                let mut status = app_state.runner.lock().await;
+                status.in_flight = None;
+                status.last_heartbeat = Some(Utc::now().naive_utc());
+            }
 
-            if let Err(e) =
-                process_schedule(&app_state, &schedule, app_state.spl_token.decimals).await
+            // Manual transfers queued from the CLI
+            let transfers = app_state.db.get_pending_transfers().await?;
+            for transfer in transfers {
+                if app_state.runner.lock().await.paused {
+                    log::info!("Schedule runner paused, remaining transfers wait for resume");
+                    break;
+                }
+                if !app_state
+                    .db
+                    .claim_adjustment(transfer.id, "pending")
+                    .await?
+                {
+                    continue;
+                }
+                app_state.runner.lock().await.in_flight = Some(InFlightTransfer {
+                    schedule_id: None,
+                    adjustment_id: Some(transfer.id),
+                    started_at: Utc::now().naive_utc(),
+                });
+                if let Err(e) = process_manual_transfer(&app_state, &transfer).await {
+                    log::error!(
+                        "Failed to process manual transfer id={}: {:#}",
+                        transfer.id,
+                        e
+                    );
+                }
+                processed += 1;
+
+                let mut status = app_state.runner.lock().await;
+                status.in_flight = None;
+                status.last_heartbeat = Some(Utc::now().naive_utc());
+            }
+        }
+
+        let next_wake_up = Utc::now().naive_utc() + chrono::Duration::from_std(RUNNER_INTERVAL)?;
+        {
+            let mut status = app_state.runner.lock().await;
+            status.last_tick_processed = processed;
+            status.next_wake_up = Some(next_wake_up);
+        }
+        app_state.events.runner(
+            "tick_finished",
+            json!({ "processed": processed, "next_wake_up": next_wake_up }),
+        );
+        sleep(RUNNER_INTERVAL).await;
+    }
+}
+
+/// Runs the schedule runner and starts it again when it fails, panics or stalls,
+/// so a runner problem doesn't take the whole server down.
+pub async fn supervise_schedule_runner(app_state: web::Data<AppState>) {
+    loop {
+        let mut runner = tokio::spawn(start_schedule_runner(app_state.clone()));
+
+        let reason = loop {
+            tokio::select! {
+                result = &mut runner => {
+                    break match result {
+                        Ok(Ok(())) => "Schedule runner stopped".to_string(),
+                        Ok(Err(e)) => format!("Schedule runner failed: {:#}", e),
+                        Err(e) => format!("Schedule runner panicked: {}", e),
+                    };
+                }
+                _ = sleep(WATCHDOG_INTERVAL) => {
+                    if let Some(stalled) = runner_stalled_for(&app_state).await {
+                        runner.abort();
+                        let _ = (&mut runner).await;
+                        break format!("Schedule runner made no progress for {} seconds", stalled);
+                    }
+                }
+            }
+        };
+        log::error!("{}. Restarting it", reason);
+
+        let (in_flight, restarts) = {
+            let mut status = app_state.runner.lock().await;
+            status.restarts += 1;
+            status.last_error = Some(reason.clone());
+            status.next_wake_up = None;
+            (status.in_flight.take(), status.restarts)
+        };
+        app_state.events.runner(
+            "restarted",
+            json!({ "reason": reason, "restarts": restarts }),
+        );
+        webhooks::notify(
+            &app_state,
+            WebhookEvent::RunnerStalled,
+            json!({
+                "reason": reason,
+                "restarts": restarts,
+                "in_flight_schedule_id": in_flight.as_ref().and_then(|f| f.schedule_id),
+                "in_flight_adjustment_id": in_flight.as_ref().and_then(|f| f.adjustment_id),
+            }),
+        )
+        .await;
+
+        // The transfer may have reached the chain, so it must not be sent again automatically
+        if let Some(InFlightTransfer {
+            schedule_id: Some(schedule_id),
+            ..
+        }) = in_flight
+        {
+            let message = "Schedule runner was restarted while sending this schedule. \
+                Check the transfer on chain and resolve it."
+                .to_string();
+            if let Err(e) = app_state
+                .db
+                .update_schedule_status(schedule_id, "interrupted", Some(message.clone()))
+                .await
+            {
+                log::error!(
+                    "Failed to mark schedule id={} as interrupted: {}",
+                    schedule_id,
+                    e
+                );
+                if let Err(e) = app_state
+                    .retry_queue
+                    .push_and_persist(PendingOp::UpdateSchedule {
+                        schedule_id,
+                        status: "interrupted".into(),
+                        error_message: Some(message),
+                    })
+                    .await
+                {
+                    log::error!("Failed to enqueue UpdateSchedule: {}", e);
+                }
+            }
+        }
+        if let Some(InFlightTransfer {
+            adjustment_id: Some(adjustment_id),
+            ..
+        }) = in_flight
+        {
+            let message = "Schedule runner was restarted while sending this transfer. \
+                Check the transfer on chain and resolve it."
+                .to_string();
+            if let Err(e) = app_state
+                .db
+                .interrupt_adjustment(adjustment_id, &message)
+                .await
             {
-                log::error!("Failed to process schedule id={}: {:#}", schedule.id, e);
+                log::error!(
+                    "Failed to mark transfer id={} as interrupted: {}",
+                    adjustment_id,
+                    e
+                );
+                if let Err(e) = app_state
+                    .retry_queue
+                    .push_and_persist(PendingOp::InterruptAdjustment {
+                        adjustment_id,
+                        error_message: message,
+                    })
+                    .await
+                {
+                    log::error!("Failed to enqueue InterruptAdjustment: {}", e);
+                }
             }
         }
-        sleep(Duration::from_secs(60)).await;
+
+        sleep(RUNNER_RESTART_DELAY).await;
     }
 }
+
+/// Seconds since the last runner heartbeat, if it is longer than allowed.
+async fn runner_stalled_for(app_state: &AppState) -> Option<i64> {
+    let last_heartbeat = app_state.runner.lock().await.last_heartbeat?;
+    let stalled = (Utc::now().naive_utc() - last_heartbeat).num_seconds();
+    (stalled > RUNNER_STALL_SECONDS).then_some(stalled)
+}
 pub async fn transfer_tokens_for_schedule(
     data: &AppState,
     schedule: &Schedule,
     buyer: &Buyer,
     token_decimals: u8,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Signature> {
+    let signature = transfer_tokens(
+        data,
+        &buyer.wallet,
+        schedule.amount_lamports,
+        schedule.group_id,
+        token_decimals,
+    )
+    .await?;
+
+    log::info!(
+        "Transferred {} token lamports to {} for schedule id={:?}, signature {}",
+        schedule.amount_lamports,
+        buyer.wallet,
+        schedule.id,
+        signature,
+    );
+
+    Ok(signature)
+}
+
+/// Sends `amount_lamports` to the associated token account of `wallet`, creating it if needed.
+pub async fn transfer_tokens(
+    data: &AppState,
+    wallet: &Pubkey,
+    amount_lamports: u64,
+    group_id: i64,
+    token_decimals: u8,
+) -> anyhow::Result<Signature> {
     // Get or create ATA
     let ata = SplToken::get_or_create_associated_token_account(
         &data.spl_token.client,
-        &buyer.wallet,
+        wallet,
         &data.spl_token.main_wallet,
         &data.spl_token.mint,
     )
     .await
-    .map_err(|e| anyhow::anyhow!("ATA error: {}", e))?;
+    .map_err(|e| {
+        data.metrics.rpc_error("get_or_create_ata");
+        data.metrics.transfer_failed(group_id, "ata");
+        anyhow::anyhow!("ATA error: {}", e)
+    })?;
 
     // Transfer with retries
 
-    if let Err(e) = try_transfer_with_retries(
-        &data.spl_token,
+    try_transfer_with_retries(
+        data,
         &ata,
-        schedule.amount_lamports,
+        amount_lamports,
         token_decimals,
-        &buyer.wallet.to_string(),
+        &wallet.to_string(),
     )
     .await
-    {
-        return Err(anyhow::anyhow!("Transfer error: {}", e));
-    }
-
-    log::info!(
-        "Transferred {} token lamports to {} for schedule id={:?}",
-        schedule.amount_lamports,
-        buyer.wallet,
-        schedule.id,
-    );
-
-    Ok(())
+    .map_err(|e| {
+        data.metrics.transfer_failed(group_id, "transfer");
+        anyhow::anyhow!("Transfer error: {}", e)
+    })
 }
 pub async fn try_transfer_with_retries(
-    spl_token_context: &SplToken,
+    data: &AppState,
     ata: &Pubkey,
     to_unlock: u64,
     token_decimals: u8,
     buyer_wallet: &str,
-) -> Result<(), String> {
+) -> Result<Signature, String> {
     let mut attempt = 0;
     let mut last_err = None;
     while attempt < 4 {
-        match spl_token_context
+        match data
+            .spl_token
             .transfer_tokens(ata, to_unlock, token_decimals)
             .await
         {
-            Ok(_) => {
-                return Ok(());
+            Ok(signature) => {
+                return Ok(signature);
             }
             Err(e) => {
+                data.metrics.rpc_error("transfer");
                 last_err = Some(e.to_string());
                 log::warn!(
                     "Send error for {} (attempt {}/{}): {}",
@@ -233,12 +624,21 @@ pub async fn process_schedule(
         log::error!("Found pending DB operations. Failed save them to DB: {e}");
     }
 
-    //Load Group
-    let group = match app_state.db.get_group(schedule.group_id).await {
-        Ok(Some(g)) => g,
+    app_state.metrics.transfer_attempted(schedule.group_id);
+    app_state.events.schedule(schedule, "processing", None);
+
+    //Check Group
+    match app_state.db.get_group(schedule.group_id).await {
+        Ok(Some(_)) => {}
         Ok(None) => {
             let err_msg = format!("Group not found for schedule id={}", schedule.id);
             log::error!("{}", err_msg);
+            app_state
+                .metrics
+                .transfer_failed(schedule.group_id, "group_not_found");
+            app_state
+                .events
+                .schedule(schedule, "failed", Some(&err_msg));
             return app_state
                 .db
                 .update_schedule_status(schedule.id, "failed", Some(err_msg))
@@ -250,12 +650,18 @@ pub async fn process_schedule(
                 schedule.id, e
             );
             log::error!("{}", err_msg);
+            app_state
+                .metrics
+                .transfer_failed(schedule.group_id, "database");
+            app_state
+                .events
+                .schedule(schedule, "failed", Some(&err_msg));
             return app_state
                 .db
                 .update_schedule_status(schedule.id, "failed", Some(err_msg))
                 .await;
         }
-    };
+    }
 
     //Load Buyer
     let buyer = match app_state
@@ -267,6 +673,12 @@ pub async fn process_schedule(
         Ok(None) => {
             let err_msg = format!("Buyer not found for schedule id={}", schedule.id);
             log::error!("{}", err_msg);
+            app_state
+                .metrics
+                .transfer_failed(schedule.group_id, "buyer_not_found");
+            app_state
+                .events
+                .schedule(schedule, "failed", Some(&err_msg));
             return app_state
                 .db
                 .update_schedule_status(schedule.id, "failed", Some(err_msg))
@@ -278,6 +690,12 @@ pub async fn process_schedule(
                 schedule.id, e
             );
             log::error!("{}", err_msg);
+            app_state
+                .metrics
+                .transfer_failed(schedule.group_id, "database");
+            app_state
+                .events
+                .schedule(schedule, "failed", Some(&err_msg));
             return app_state
                 .db
                 .update_schedule_status(schedule.id, "failed", Some(err_msg))
@@ -293,14 +711,29 @@ pub async fn process_schedule(
         schedule.percent,
         "success".to_string(),
     );
+    tx_record.schedule_id = Some(schedule.id);
 
     //Attempt token transfer
-    match transfer_tokens_for_schedule(app_state, schedule, &buyer, token_decimals).await {
-        Ok(_) => {
+    let started = Instant::now();
+    let transfer = transfer_tokens_for_schedule(app_state, schedule, &buyer, token_decimals).await;
+    let outcome = if transfer.is_ok() {
+        "success"
+    } else {
+        "failed"
+    };
+    app_state
+        .metrics
+        .observe_transfer(schedule.group_id, outcome, started.elapsed().as_secs_f64());
+
+    match transfer {
+        Ok(signature) => {
             log::info!("Tokens transferred for schedule id={}", schedule.id);
+            app_state.metrics.transfer_succeeded(schedule.group_id);
 
             //Save transaction
+            tx_record.signature = Some(signature.to_string());
             tx_record.sent_at = Some(Utc::now().naive_utc());
+            app_state.events.transaction(&tx_record);
             if let Err(e) = app_state.db.save_transaction(tx_record.clone()).await {
                 log::error!(
                     "Failed to save transaction for schedule id={}: {}",
@@ -315,41 +748,59 @@ pub async fn process_schedule(
                 }
             }
 
-            //Update buyer balances
-            let total_spl = buyer.paid_lamports / group.spl_price_lamports;
-            let new_received_spl = buyer.received_spl_lamports + schedule.amount_lamports;
-            let new_pending_spl = total_spl - new_received_spl;
-            let percent = schedule.percent;
+            //Record the unlock in the ledger, which updates the buyer balances
+            let (new_received_spl, new_pending_spl) =
+                match app_state.db.record_unlock(schedule).await {
+                    Ok(updated) => (updated.received_spl_lamports, updated.pending_spl_lamports),
+                    Err(e) => {
+                        log::error!(
+                            "Failed to update buyer after transfer for schedule id={}: {}",
+                            schedule.id,
+                            e
+                        );
+                        if let Err(e) = retry_queue
+                            .push_and_persist(PendingOp::RecordUnlock(schedule.clone()))
+                            .await
+                        {
+                            log::error!("Failed to enqueue RecordUnlock: {}", e);
+                        }
+                        (
+                            buyer.received_spl_lamports + schedule.amount_lamports,
+                            buyer
+                                .pending_spl_lamports
+                                .saturating_sub(schedule.amount_lamports),
+                        )
+                    }
+                };
 
-            if let Err(e) = app_state
-                .db
-                .update_buyer(
-                    &buyer.wallet.to_string(),
-                    new_received_spl,
-                    percent,
-                    new_pending_spl,
+            webhooks::notify(
+                app_state,
+                WebhookEvent::ScheduleSucceeded,
+                json!({
+                    "schedule_id": schedule.id,
+                    "buyer_wallet": schedule.buyer_wallet,
+                    "group_id": schedule.group_id,
+                    "amount_lamports": schedule.amount_lamports,
+                    "percent": schedule.percent,
+                    "signature": signature.to_string(),
+                }),
+            )
+            .await;
+            if new_pending_spl == 0 {
+                webhooks::notify(
+                    app_state,
+                    WebhookEvent::BuyerVested,
+                    json!({
+                        "buyer_wallet": schedule.buyer_wallet,
+                        "group_id": schedule.group_id,
+                        "received_spl_lamports": new_received_spl,
+                    }),
                 )
-                .await
-            {
-                log::error!(
-                    "Failed to update buyer after transfer for schedule id={}: {}",
-                    schedule.id,
-                    e
-                );
-                if let Err(e) = retry_queue
-                    .push_and_persist(PendingOp::UpdateBuyer {
-                        wallet: buyer.wallet.to_string(),
-                        received_spl: new_received_spl,
-                        received_percent: percent,
-                        pending_spl: new_pending_spl,
-                    })
-                    .await
-                {
-                    log::error!("Failed to enqueue UpdateBuyer: {}", e);
-                }
+                .await;
             }
 
             //Mark schedule as success
+            app_state.events.schedule(schedule, "success", None);
             match app_state
                 .db
                 .update_schedule_status(schedule.id, "success", None)
@@ -389,6 +840,7 @@ pub async fn process_schedule(
             tx_record.status = "failed".to_string();
             tx_record.error_message = Some(err_msg.clone());
             tx_record.sent_at = Some(Utc::now().naive_utc());
+            app_state.events.transaction(&tx_record);
 
             if let Err(e) = app_state.db.save_transaction(tx_record.clone()).await {
                 log::error!(
@@ -404,7 +856,23 @@ pub async fn process_schedule(
                 }
             }
 
+            webhooks::notify(
+                app_state,
+                WebhookEvent::ScheduleFailed,
+                json!({
+                    "schedule_id": schedule.id,
+                    "buyer_wallet": schedule.buyer_wallet,
+                    "group_id": schedule.group_id,
+                    "amount_lamports": schedule.amount_lamports,
+                    "error": err_msg,
+                }),
+            )
+            .await;
+
             //Mark schedule as failed
+            app_state
+                .events
+                .schedule(schedule, "failed", Some(&err_msg));
             match app_state
                 .db
                 .update_schedule_status(schedule.id, "failed", Some(err_msg.clone()))
@@ -435,3 +903,275 @@ pub async fn process_schedule(
         }
     }
 }
+
+/// Sends a manual transfer that is already `processing` through the same transfer, retry and
+/// transaction recording path as the schedules. On success the amount is added to the
+/// buyer's `received_spl_lamports`.
+pub async fn process_manual_transfer(
+    app_state: &AppState,
+    adjustment: &Adjustment,
+) -> anyhow::Result<Adjustment> {
+    //Flush any pending DB operations from previous runs
+    let retry_queue = &app_state.retry_queue;
+    if let Err(e) = retry_queue.flush(&app_state.db).await {
+        log::error!("Found pending DB operations. Failed save them to DB: {e}");
+    }
+
+    app_state.metrics.transfer_attempted(adjustment.group_id);
+    let amount_lamports = adjustment.amount_lamports.unsigned_abs();
+
+    let buyer = match app_state
+        .db
+        .get_buyer_by_wallet(&adjustment.buyer_wallet)
+        .await
+    {
+        Ok(Some(b)) => b,
+        Ok(None) => {
+            let err_msg = format!("Buyer not found for transfer id={}", adjustment.id);
+            log::error!("{}", err_msg);
+            app_state
+                .metrics
+                .transfer_failed(adjustment.group_id, "buyer_not_found");
+            app_state
+                .db
+                .fail_adjustment(adjustment.id, None, &err_msg)
+                .await?;
+            return fetch_adjustment(app_state, adjustment.id).await;
+        }
+        Err(e) => {
+            let err_msg = format!(
+                "Database error retrieving buyer for transfer id={}: {}",
+                adjustment.id, e
+            );
+            log::error!("{}", err_msg);
+            app_state
+                .metrics
+                .transfer_failed(adjustment.group_id, "database");
+            app_state
+                .db
+                .fail_adjustment(adjustment.id, None, &err_msg)
+                .await?;
+            return fetch_adjustment(app_state, adjustment.id).await;
+        }
+    };
+
+    //Prepare transaction record
+    let mut tx_record = Transaction::new(
+        adjustment.buyer_wallet.clone(),
+        adjustment.group_id,
+        amount_lamports,
+        0.0,
+        "success".to_string(),
+    );
+
+    //Attempt token transfer
+    let started = Instant::now();
+    let transfer = transfer_tokens(
+        app_state,
+        &buyer.wallet,
+        amount_lamports,
+        adjustment.group_id,
+        app_state.spl_token.decimals,
+    )
+    .await;
+    let outcome = if transfer.is_ok() {
+        "success"
+    } else {
+        "failed"
+    };
+    app_state.metrics.observe_transfer(
+        adjustment.group_id,
+        outcome,
+        started.elapsed().as_secs_f64(),
+    );
+
+    let err_msg = match &transfer {
+        Ok(signature) => {
+            log::info!(
+                "Transferred {} token lamports to {} for manual transfer id={}, signature {}",
+                amount_lamports,
+                buyer.wallet,
+                adjustment.id,
+                signature
+            );
+            app_state.metrics.transfer_succeeded(adjustment.group_id);
+            tx_record.signature = Some(signature.to_string());
+            None
+        }
+        Err(e) => {
+            let err_msg = format!(
+                "Token transfer failed for manual transfer id={} buyer={} amount={}: {}",
+                adjustment.id, adjustment.buyer_wallet, amount_lamports, e
+            );
+            log::error!("{}", err_msg);
+            tx_record.status = "failed".to_string();
+            tx_record.error_message = Some(err_msg.clone());
+            Some(err_msg)
+        }
+    };
+
+    //Save transaction
+    tx_record.sent_at = Some(Utc::now().naive_utc());
+    app_state.events.transaction(&tx_record);
+    let transaction_id = match app_state.db.save_transaction(tx_record.clone()).await {
+        Ok(id) => Some(id),
+        Err(e) => {
+            log::error!(
+                "Failed to save transaction for manual transfer id={}: {}",
+                adjustment.id,
+                e
+            );
+            if let Err(e) = retry_queue
+                .push_and_persist(PendingOp::SaveTransaction(tx_record))
+                .await
+            {
+                log::error!("Failed to enqueue SaveTransaction: {}", e);
+            }
+            None
+        }
+    };
+
+    //Update buyer balance and the transfer
+    let (updated, op) = match err_msg {
+        None => (
+            app_state
+                .db
+                .apply_adjustment(adjustment.id, transaction_id)
+                .await,
+            PendingOp::ApplyAdjustment {
+                adjustment_id: adjustment.id,
+                transaction_id,
+            },
+        ),
+        Some(err_msg) => (
+            app_state
+                .db
+                .fail_adjustment(adjustment.id, transaction_id, &err_msg)
+                .await,
+            PendingOp::FailAdjustment {
+                adjustment_id: adjustment.id,
+                transaction_id,
+                error_message: err_msg,
+            },
+        ),
+    };
+    if let Err(e) = updated {
+        if let Err(e) = retry_queue.push_and_persist(op).await {
+            log::error!("Failed to enqueue adjustment update: {}", e);
+        }
+        anyhow::bail!(
+            "Failed to update manual transfer id={} after sending: {}",
+            adjustment.id,
+            e
+        );
+    }
+
+    fetch_adjustment(app_state, adjustment.id).await
+}
+
+async fn fetch_adjustment(app_state: &AppState, adjustment_id: i64) -> anyhow::Result<Adjustment> {
+    app_state
+        .db
+        .get_adjustment(adjustment_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Adjustment {} not found", adjustment_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group() -> Group {
+        Group {
+            id: 1,
+            spl_share_percent: 0.5,
+            spl_total_lamports: 1_000_000,
+            spl_price_lamports: 10,
+            initial_unlock_percent: 0.1,
+            unlock_interval_seconds: 3600,
+            unlock_percent_per_interval: 0.2,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn buyer(paid_lamports: u64) -> Buyer {
+        Buyer {
+            wallet: Pubkey::new_unique(),
+            paid_lamports,
+            group_id: 1,
+            received_spl_lamports: 0,
+            received_percent: 0.0,
+            pending_spl_lamports: 0,
+            error: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn start() -> NaiveDateTime {
+        NaiveDateTime::default()
+    }
+
+    fn percent_keys(schedules: &[Schedule]) -> Vec<u64> {
+        schedules
+            .iter()
+            .map(|s| (s.percent * 1_000_000.0).round() as u64)
+            .collect()
+    }
+
+    #[test]
+    fn plan_covers_the_allocation() {
+        let (group, buyer) = (group(), buyer(12_345));
+        let planned = plan_buyer_schedules(&group, &buyer, &[], start());
+
+        assert_eq!(planned.len(), 6);
+        assert_eq!(planned[0].scheduled_at, start());
+        assert_eq!(planned[0].amount_lamports, 123);
+        let total: u64 = planned.iter().map(|s| s.amount_lamports).sum();
+        assert_eq!(total, buyer_allocation(&group, &buyer));
+        assert!((planned.last().unwrap().percent - 1.0).abs() < 1e-9);
+        assert!(planned.iter().all(|s| s.status == "pending"));
+    }
+
+    #[test]
+    fn plan_continues_after_kept_schedules() {
+        let (group, buyer) = (group(), buyer(10_000));
+        let mut first = plan_buyer_schedules(&group, &buyer, &[], start());
+        first.truncate(2);
+        first[0].status = "success".to_string();
+        first[1].status = "failed".to_string();
+
+        let planned = plan_buyer_schedules(&group, &buyer, &first, start());
+
+        let mut keys = percent_keys(&first);
+        keys.extend(percent_keys(&planned));
+        let unique: std::collections::BTreeSet<u64> = keys.iter().copied().collect();
+        assert_eq!(unique.len(), keys.len());
+        // A failed schedule may still be on chain, so its tranche is not planned again
+        let total: u64 = first
+            .iter()
+            .chain(&planned)
+            .map(|s| s.amount_lamports)
+            .sum();
+        assert_eq!(total, buyer_allocation(&group, &buyer));
+        assert!(planned.iter().all(|s| s.percent > first[1].percent));
+    }
+
+    #[test]
+    fn plan_is_empty_when_everything_is_scheduled() {
+        let (group, buyer) = (group(), buyer(10_000));
+        let planned = plan_buyer_schedules(&group, &buyer, &[], start());
+        assert!(plan_buyer_schedules(&group, &buyer, &planned, start()).is_empty());
+    }
+
+    #[test]
+    fn vested_counts_only_successful_schedules() {
+        let (group, buyer) = (group(), buyer(10_000));
+        let mut planned = plan_buyer_schedules(&group, &buyer, &[], start());
+        planned[0].status = "success".to_string();
+        planned[1].status = "processing".to_string();
+        planned[2].status = "failed".to_string();
+        assert_eq!(vested_lamports(&planned), planned[0].amount_lamports);
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use chrono::{NaiveDateTime, Utc};
+use common::{Schedule, Transaction};
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::broadcast;
+
+/// Events kept in memory for clients resuming with `Last-Event-ID`.
+const EVENT_HISTORY: usize = 1000;
+/// Events buffered per subscriber before it starts to lag.
+const EVENT_CHANNEL: usize = 256;
+
+/// Something that happened in the distribution, pushed to `/events` subscribers.
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveEvent {
+    pub id: u64,
+    /// "schedule", "transaction" or "runner"
+    pub kind: &'static str,
+    pub group_id: Option<i64>,
+    pub wallet: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub data: serde_json::Value,
+}
+
+/// In-memory fan-out of live events with a short history.
+pub struct EventBus {
+    sender: broadcast::Sender<LiveEvent>,
+    // (next event id, last events)
+    history: Mutex<(u64, VecDeque<LiveEvent>)>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL);
+        // Ids start at the current time so they keep growing across restarts
+        let first_id = Utc::now().timestamp_millis() as u64;
+        EventBus {
+            sender,
+            history: Mutex::new((first_id, VecDeque::with_capacity(EVENT_HISTORY))),
+        }
+    }
+
+    pub fn publish(
+        &self,
+        kind: &'static str,
+        group_id: Option<i64>,
+        wallet: Option<String>,
+        data: serde_json::Value,
+    ) {
+        let mut history = self.history.lock().expect("event history lock poisoned");
+        let event = LiveEvent {
+            id: history.0,
+            kind,
+            group_id,
+            wallet,
+            created_at: Utc::now().naive_utc(),
+            data,
+        };
+        history.0 += 1;
+        if history.1.len() == EVENT_HISTORY {
+            history.1.pop_front();
+        }
+        history.1.push_back(event.clone());
+        // Sending only fails when nobody is subscribed
+        let _ = self.sender.send(event);
+    }
+
+    /// A schedule changed its status.
+    pub fn schedule(&self, schedule: &Schedule, status: &str, error_message: Option<&str>) {
+        self.publish(
+            "schedule",
+            Some(schedule.group_id),
+            Some(schedule.buyer_wallet.clone()),
+            json!({
+                "schedule_id": schedule.id,
+                "status": status,
+                "error_message": error_message,
+                "amount_lamports": schedule.amount_lamports,
+                "percent": schedule.percent,
+                "scheduled_at": schedule.scheduled_at,
+            }),
+        );
+    }
+
+    pub fn transaction(&self, transaction: &Transaction) {
+        self.publish(
+            "transaction",
+            Some(transaction.group_id),
+            Some(transaction.buyer_wallet.clone()),
+            json!(transaction),
+        );
+    }
+
+    /// `state` is "tick_started", "tick_finished", "paused", "resumed" or "restarted".
+    pub fn runner(&self, state: &str, data: serde_json::Value) {
+        self.publish(
+            "runner",
+            None,
+            None,
+            json!({ "state": state, "details": data }),
+        );
+    }
+
+    /// Subscribes to new events and returns the kept events after `last_event_id`.
+    /// Subscribing under the history lock makes sure no event is missed or sent twice.
+    pub fn subscribe(
+        &self,
+        last_event_id: Option<u64>,
+    ) -> (Vec<LiveEvent>, broadcast::Receiver<LiveEvent>) {
+        let history = self.history.lock().expect("event history lock poisoned");
+        let receiver = self.sender.subscribe();
+        let missed = match last_event_id {
+            Some(last_id) => history
+                .1
+                .iter()
+                .filter(|e| e.id > last_id)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        (missed, receiver)
+    }
+}
@@ -0,0 +1,360 @@
+use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, post, web};
+use common::{Adjustment, ApprovalAction, Buyer, Permission, Transaction, User};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::approvals::request_approval;
+use super::buyers::fetch_buyer;
+use super::require;
+use super::schedule::ResolvePayload;
+use crate::distribution::process_manual_transfer;
+use crate::state::AppState;
+
+/// Manual transfers and balance adjustments of a buyer.
+#[get("/buyers/{wallet}/adjustments")]
+pub async fn get_buyer_adjustments(
+    path: web::Path<String>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let wallet = path.into_inner();
+    fetch_buyer(&app_state, &wallet).await?;
+
+    let adjustments = app_state
+        .db
+        .get_adjustments_by_wallet(&wallet)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get adjustments of buyer {}: {}", wallet, e);
+            InternalError::new(
+                "Failed to fetch adjustments. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    Ok(HttpResponse::Ok().json(adjustments))
+}
+
+/// Sends tokens to a buyer outside their schedules.
+#[post("/buyers/{wallet}/transfers")]
+pub async fn create_transfer(
+    path: web::Path<String>,
+    payload: web::Json<TransferPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::SendTokens)?;
+    let wallet = path.into_inner();
+    let payload = payload.into_inner();
+    let buyer = fetch_buyer(&app_state, &wallet).await?;
+    // Checks the amount and reason before anything is held for approval
+    Adjustment::transfer(
+        &wallet,
+        buyer.group_id,
+        payload.amount_lamports,
+        &payload.reason,
+        None,
+    )
+    .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+
+    let approval = request_approval(
+        &app_state,
+        &user,
+        ApprovalAction::ManualTransfer,
+        payload.amount_lamports,
+        json!({
+            "wallet": wallet,
+            "amount_lamports": payload.amount_lamports,
+            "reason": payload.reason,
+        }),
+    )
+    .await?;
+    if let Some(approval) = approval {
+        return Ok(HttpResponse::Accepted().json(approval));
+    }
+
+    let adjustment = send_transfer(
+        &app_state,
+        &buyer,
+        payload.amount_lamports,
+        &payload.reason,
+        &user,
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(adjustment))
+}
+
+/// Settles a manual transfer that was interrupted while it was being sent, after it was
+/// checked on chain. With the signature of the transfer it is added to the buyer's balance,
+/// without one it is marked failed.
+#[post("/buyers/{wallet}/transfers/{id}/resolve")]
+pub async fn resolve_interrupted_transfer(
+    path: web::Path<(String, i64)>,
+    payload: web::Json<ResolvePayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::SendTokens)?;
+    let (wallet, adjustment_id) = path.into_inner();
+    let signature = payload.into_inner().signature()?;
+
+    let db_error = |e: anyhow::Error| {
+        log::error!("Failed to resolve transfer {}: {}", adjustment_id, e);
+        InternalError::new(
+            "Failed to resolve transfer. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    };
+    let adjustment = app_state
+        .db
+        .get_adjustment(adjustment_id)
+        .await
+        .map_err(db_error)?
+        .filter(|a| a.buyer_wallet == wallet && a.is_transfer())
+        .ok_or_else(|| InternalError::new("Transfer not found.", StatusCode::NOT_FOUND))?;
+    if !app_state
+        .db
+        .claim_adjustment(adjustment.id, "interrupted")
+        .await
+        .map_err(db_error)?
+    {
+        return Err(
+            InternalError::new("Transfer is not interrupted.", StatusCode::CONFLICT).into(),
+        );
+    }
+
+    match signature {
+        Some(signature) => {
+            let mut tx_record = Transaction::new(
+                wallet.clone(),
+                adjustment.group_id,
+                adjustment.amount_lamports.unsigned_abs(),
+                0.0,
+                "success".to_string(),
+            );
+            tx_record.signature = Some(signature.to_string());
+            let transaction_id = app_state
+                .db
+                .save_transaction(tx_record)
+                .await
+                .map_err(db_error)?;
+            app_state
+                .db
+                .apply_adjustment(adjustment.id, Some(transaction_id))
+                .await
+                .map_err(db_error)?;
+        }
+        None => app_state
+            .db
+            .fail_adjustment(
+                adjustment.id,
+                None,
+                &format!("Not sent, checked on chain by `{}`.", user.username),
+            )
+            .await
+            .map_err(db_error)?,
+    }
+
+    let adjustment = app_state
+        .db
+        .get_adjustment(adjustment.id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| InternalError::new("Transfer not found.", StatusCode::NOT_FOUND))?;
+    log::info!(
+        "Interrupted transfer id={} resolved as {} by `{}`",
+        adjustment.id,
+        adjustment.status,
+        user.username
+    );
+    Ok(HttpResponse::Ok().json(adjustment))
+}
+
+/// Corrects the received balance of a buyer without sending anything.
+#[post("/buyers/{wallet}/adjustments")]
+pub async fn create_adjustment(
+    path: web::Path<String>,
+    payload: web::Json<AdjustmentPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageBuyers)?;
+    let wallet = path.into_inner();
+    let payload = payload.into_inner();
+    let buyer = fetch_buyer(&app_state, &wallet).await?;
+    check_adjustment(&buyer, payload.amount_lamports, &payload.reason)?;
+
+    let approval = request_approval(
+        &app_state,
+        &user,
+        ApprovalAction::AdjustBalance,
+        payload.amount_lamports.unsigned_abs(),
+        json!({
+            "wallet": wallet,
+            "amount_lamports": payload.amount_lamports,
+            "reason": payload.reason,
+        }),
+    )
+    .await?;
+    if let Some(approval) = approval {
+        return Ok(HttpResponse::Accepted().json(approval));
+    }
+
+    let adjustment = adjust_balance(
+        &app_state,
+        &buyer,
+        payload.amount_lamports,
+        &payload.reason,
+        &user,
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(adjustment))
+}
+
+/// Records a manual transfer and sends it right away. The response holds its outcome,
+/// a failed transfer is not retried automatically.
+pub(super) async fn send_transfer(
+    app_state: &AppState,
+    buyer: &Buyer,
+    amount_lamports: u64,
+    reason: &str,
+    user: &User,
+) -> Result<Adjustment, Error> {
+    let wallet = buyer.wallet.to_string();
+    let mut adjustment = Adjustment::transfer(
+        &wallet,
+        buyer.group_id,
+        amount_lamports,
+        reason,
+        Some(user.username.clone()),
+    )
+    .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+    // Saved as processing, so the runner doesn't pick it up as well
+    adjustment.status = "processing".to_string();
+    adjustment.id = app_state
+        .db
+        .save_adjustment(&adjustment)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to save transfer to {}: {}", wallet, e);
+            InternalError::new(
+                "Failed to save transfer. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    log::info!(
+        "Manual transfer {} of {} lamports to {} started by `{}`",
+        adjustment.id,
+        amount_lamports,
+        wallet,
+        user.username
+    );
+
+    process_manual_transfer(app_state, &adjustment)
+        .await
+        .map_err(|e| {
+            log::error!(
+                "Failed to process manual transfer {}: {:#}",
+                adjustment.id,
+                e
+            );
+            InternalError::new(
+                "Failed to record the transfer. Check it before sending again.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into()
+        })
+}
+
+/// Records a signed adjustment and adds it to the buyer's received balance.
+pub(super) async fn adjust_balance(
+    app_state: &AppState,
+    buyer: &Buyer,
+    amount_lamports: i64,
+    reason: &str,
+    user: &User,
+) -> Result<Adjustment, Error> {
+    let wallet = buyer.wallet.to_string();
+    let mut adjustment = check_adjustment(buyer, amount_lamports, reason)?;
+    adjustment.created_by = Some(user.username.clone());
+
+    let db_error = |e: anyhow::Error| {
+        log::error!("Failed to adjust balance of buyer {}: {}", wallet, e);
+        InternalError::new(
+            "Failed to adjust balance. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    };
+    adjustment.id = app_state
+        .db
+        .save_adjustment(&adjustment)
+        .await
+        .map_err(db_error)?;
+    if let Err(e) = app_state.db.apply_adjustment(adjustment.id, None).await {
+        let message = e.to_string();
+        if let Err(e) = app_state
+            .db
+            .fail_adjustment(adjustment.id, None, &message)
+            .await
+        {
+            log::error!("Failed to mark adjustment {} failed: {}", adjustment.id, e);
+        }
+        return Err(db_error(e).into());
+    }
+    log::info!(
+        "Balance of buyer {} adjusted by {} lamports by `{}`: {}",
+        wallet,
+        amount_lamports,
+        user.username,
+        adjustment.reason
+    );
+
+    app_state
+        .db
+        .get_adjustment(adjustment.id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| InternalError::new("Adjustment not found.", StatusCode::NOT_FOUND).into())
+}
+
+/// Builds the adjustment if it is valid and leaves the balance at zero or above.
+fn check_adjustment(
+    buyer: &Buyer,
+    amount_lamports: i64,
+    reason: &str,
+) -> Result<Adjustment, Error> {
+    let adjustment = Adjustment::correction(
+        &buyer.wallet.to_string(),
+        buyer.group_id,
+        amount_lamports,
+        reason,
+        None,
+    )
+    .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+
+    if amount_lamports < 0 && amount_lamports.unsigned_abs() > buyer.received_spl_lamports {
+        return Err(InternalError::new(
+            format!(
+                "Adjustment of {} would make the received balance {} negative.",
+                amount_lamports, buyer.received_spl_lamports
+            ),
+            StatusCode::BAD_REQUEST,
+        )
+        .into());
+    }
+    Ok(adjustment)
+}
+
+#[derive(Debug, Deserialize)]
+struct TransferPayload {
+    amount_lamports: u64,
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdjustmentPayload {
+    /// Negative to lower the balance.
+    amount_lamports: i64,
+    reason: String,
+}
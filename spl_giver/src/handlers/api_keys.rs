@@ -0,0 +1,128 @@
+use actix_web::{
+    Error, HttpResponse, delete, error::InternalError, get, http::StatusCode, post, web,
+};
+use chrono::{Duration, Utc};
+use common::{ApiKey, Permission, Role, User};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::require;
+use crate::state::AppState;
+
+#[get("/api-keys")]
+pub async fn get_api_keys(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let api_keys = app_state.db.get_all_api_keys().await.map_err(|e| {
+        log::error!("Failed to get API keys: {}", e);
+        InternalError::new(
+            "Failed to fetch API keys. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    Ok(HttpResponse::Ok().json(api_keys))
+}
+
+#[post("/api-keys")]
+pub async fn create_api_key(
+    payload: web::Json<ApiKeyPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let payload = payload.into_inner();
+    let expires_at = match payload.expires_in_days {
+        Some(days) if days <= 0 => {
+            return Err(InternalError::new(
+                "expires_in_days must be positive",
+                StatusCode::BAD_REQUEST,
+            )
+            .into());
+        }
+        Some(days) => Some(Utc::now().naive_utc() + Duration::days(days)),
+        None => None,
+    };
+
+    let (mut api_key, key) = ApiKey::generate(&payload.name, payload.role, expires_at, &user)
+        .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+    api_key.id = app_state.db.save_api_key(&api_key).await.map_err(|e| {
+        log::error!("Failed to create API key: {}", e);
+        InternalError::new(
+            "Failed to create API key. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    let api_key = fetch_api_key(&app_state, api_key.id).await?;
+
+    log::info!(
+        "API key {} `{}` with role `{}` created by `{}`",
+        api_key.id,
+        api_key.name,
+        api_key.role,
+        user.username
+    );
+
+    // Only the hash is stored, the key can't be shown again
+    Ok(HttpResponse::Created().json(CreatedApiKeyResponse { api_key, key }))
+}
+
+/// Revokes a key; it is kept for the audit trail.
+#[delete("/api-keys/{api_key_id}")]
+pub async fn revoke_api_key(
+    path: web::Path<i64>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let api_key_id = path.into_inner();
+    fetch_api_key(&app_state, api_key_id).await?;
+
+    app_state.db.revoke_api_key(api_key_id).await.map_err(|e| {
+        log::error!("Failed to revoke API key {}: {}", api_key_id, e);
+        InternalError::new(
+            "Failed to revoke API key. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    log::info!("API key {} revoked by `{}`", api_key_id, user.username);
+
+    Ok(HttpResponse::Ok().json(json!({ "revoked": api_key_id })))
+}
+
+async fn fetch_api_key(app_state: &AppState, api_key_id: i64) -> Result<ApiKey, Error> {
+    let maybe_api_key = app_state.db.get_api_key(api_key_id).await.map_err(|e| {
+        log::error!("Database error fetching API key {}: {}", api_key_id, e);
+        InternalError::new(
+            "Internal server error while fetching API key.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    maybe_api_key.ok_or_else(|| {
+        InternalError::new("API key with provided ID not found.", StatusCode::NOT_FOUND).into()
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiKeyPayload {
+    name: String,
+    #[serde(default = "default_role")]
+    role: Role,
+    /// Days until the key expires, never if missing.
+    expires_in_days: Option<i64>,
+}
+
+fn default_role() -> Role {
+    Role::Viewer
+}
+
+#[derive(Debug, Serialize)]
+struct CreatedApiKeyResponse {
+    #[serde(flatten)]
+    api_key: ApiKey,
+    key: String,
+}
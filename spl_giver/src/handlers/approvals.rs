@@ -0,0 +1,372 @@
+use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, post, web};
+use common::{Approval, ApprovalAction, Permission, User};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::adjustments::{adjust_balance, send_transfer};
+use super::buyers::{fetch_buyer, remove_buyer};
+use super::groups::{GroupPayload, change_group, check_no_buyers, fetch_group, remove_group};
+use super::require;
+use super::schedule::retry_schedules;
+use crate::state::AppState;
+
+#[get("/approvals")]
+pub async fn get_approvals(
+    query: web::Query<ApprovalQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let approvals = app_state
+        .db
+        .get_approvals(query.status.as_deref())
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get approvals: {}", e);
+            InternalError::new(
+                "Failed to fetch approvals. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    Ok(HttpResponse::Ok().json(approvals))
+}
+
+#[get("/approvals/{approval_id}")]
+pub async fn get_approval(
+    path: web::Path<i64>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let approval = fetch_approval(&app_state, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(approval))
+}
+
+/// Accepts a pending request and runs the operation. The requester can't approve their own,
+/// neither made with one of their API keys.
+#[post("/approvals/{approval_id}/approve")]
+pub async fn approve_request(
+    path: web::Path<i64>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Approve)?;
+    let approval_id = path.into_inner();
+    let approval = fetch_approval(&app_state, approval_id).await?;
+    if approval.is_requested_by(&user) {
+        return Err(InternalError::new(
+            "A request must be approved by a different user than the one who made it.",
+            StatusCode::FORBIDDEN,
+        )
+        .into());
+    }
+    decide(&app_state, &approval, "approved", &user, None).await?;
+    log::info!(
+        "Approval {} `{}` of `{}` approved by `{}`",
+        approval_id,
+        approval.action,
+        approval.requested_by,
+        user.username
+    );
+
+    let (status, result) = match execute(&app_state, &approval).await {
+        Ok(result) => ("executed", result),
+        Err(e) => {
+            log::error!("Approved request {} failed: {}", approval_id, e);
+            ("failed", json!({ "error": e.to_string() }))
+        }
+    };
+    app_state
+        .db
+        .finish_approval(approval_id, status, &result)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to save result of approval {}: {}", approval_id, e);
+            InternalError::new(
+                "The request ran, but its result couldn't be saved.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    let approval = fetch_approval(&app_state, approval_id).await?;
+    Ok(HttpResponse::Ok().json(approval))
+}
+
+/// Rejects a pending request. The requester can also withdraw their own.
+#[post("/approvals/{approval_id}/reject")]
+pub async fn reject_request(
+    path: web::Path<i64>,
+    payload: Option<web::Json<RejectPayload>>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let approval_id = path.into_inner();
+    let approval = fetch_approval(&app_state, approval_id).await?;
+    if !approval.is_requested_by(&user) {
+        require(&user, Permission::Approve)?;
+    }
+    let reason = payload.and_then(|p| p.into_inner().reason);
+    decide(&app_state, &approval, "rejected", &user, reason.as_deref()).await?;
+    log::info!(
+        "Approval {} `{}` of `{}` rejected by `{}`",
+        approval_id,
+        approval.action,
+        approval.requested_by,
+        user.username
+    );
+
+    let approval = fetch_approval(&app_state, approval_id).await?;
+    Ok(HttpResponse::Ok().json(approval))
+}
+
+/// Holds `action` for a second user's approval if the policy asks for it. Returns the
+/// created request, or `None` if the operation can run right away.
+pub(super) async fn request_approval(
+    app_state: &AppState,
+    user: &User,
+    action: ApprovalAction,
+    amount_lamports: u64,
+    parameters: serde_json::Value,
+) -> Result<Option<Approval>, Error> {
+    if !app_state
+        .approval_policy
+        .requires_approval(action, amount_lamports)
+    {
+        return Ok(None);
+    }
+
+    let mut approval = Approval::new(action, parameters, amount_lamports, user);
+    approval.id = app_state.db.save_approval(&approval).await.map_err(|e| {
+        log::error!("Failed to save approval request `{}`: {}", action, e);
+        InternalError::new(
+            "Failed to create approval request. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    log::info!(
+        "`{}` of {} lamports by `{}` waits for approval {}",
+        action,
+        amount_lamports,
+        user.username,
+        approval.id
+    );
+
+    fetch_approval(app_state, approval.id).await.map(Some)
+}
+
+/// Runs an approved operation as its requester and returns its response.
+async fn execute(app_state: &AppState, approval: &Approval) -> Result<serde_json::Value, Error> {
+    let invalid = |e: serde_json::Error| {
+        log::error!("Invalid parameters of approval {}: {}", approval.id, e);
+        InternalError::new(
+            "Invalid parameters of approval request.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    };
+    let parameters: serde_json::Value =
+        serde_json::from_str(&approval.parameters).map_err(invalid)?;
+    let action = approval.action().ok_or_else(|| {
+        InternalError::new(
+            format!("Unknown approval action `{}`.", approval.action),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    // Requests of API keys run as the key's owner
+    let requester = match approval.requested_by_id {
+        Some(user_id) => app_state.db.get_user_by_id(user_id).await,
+        None => app_state.db.get_user(&approval.requested_by).await,
+    };
+    let requester = requester
+        .map_err(|e| {
+            log::error!("Failed to get user `{}`: {}", approval.requested_by, e);
+            InternalError::new(
+                "Internal server error while fetching user.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?
+        .filter(|u| u.active)
+        .ok_or_else(|| {
+            InternalError::new(
+                "The user who made the request no longer exists or is deactivated.",
+                StatusCode::CONFLICT,
+            )
+        })?;
+    // The role of the requester may have changed since
+    if !requester.can(action.permission()) {
+        return Err(InternalError::new(
+            format!(
+                "`{}` is no longer allowed to {}.",
+                requester.username, approval.action
+            ),
+            StatusCode::CONFLICT,
+        )
+        .into());
+    }
+
+    match action {
+        ApprovalAction::RetrySchedules => {
+            let params: RetryParameters = serde_json::from_value(parameters).map_err(invalid)?;
+            let mut schedules = Vec::new();
+            for schedule_id in params.schedule_ids {
+                let schedule = app_state
+                    .db
+                    .get_schedule_by_id(schedule_id)
+                    .await
+                    .map_err(|e| {
+                        log::error!("Failed to get schedule {}: {}", schedule_id, e);
+                        InternalError::new(
+                            "Failed to get schedules. Please try again later.",
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    })?;
+                // Schedules sent since the request was made are skipped
+                schedules.extend(schedule.filter(|s| s.status == "failed"));
+            }
+            Ok(retry_schedules(app_state, schedules).await)
+        }
+        ApprovalAction::UpdateGroup => {
+            let params: UpdateGroupParameters =
+                serde_json::from_value(parameters).map_err(invalid)?;
+            let response =
+                change_group(app_state, params.group_id, params.group, false, &requester).await?;
+            Ok(json!(response))
+        }
+        ApprovalAction::DeleteGroup => {
+            let params: GroupParameters = serde_json::from_value(parameters).map_err(invalid)?;
+            let group = fetch_group(app_state, params.group_id).await?;
+            check_no_buyers(app_state, group.id).await?;
+            remove_group(app_state, &group, &requester).await
+        }
+        ApprovalAction::DeleteBuyer => {
+            let params: BuyerParameters = serde_json::from_value(parameters).map_err(invalid)?;
+            let buyer = fetch_buyer(app_state, &params.wallet).await?;
+            remove_buyer(app_state, &buyer).await?;
+            log::info!(
+                "Buyer {} deleted by `{}`",
+                params.wallet,
+                requester.username
+            );
+            Ok(json!({ "deleted": params.wallet }))
+        }
+        ApprovalAction::ManualTransfer => {
+            let params: TransferParameters = serde_json::from_value(parameters).map_err(invalid)?;
+            let buyer = fetch_buyer(app_state, &params.wallet).await?;
+            let adjustment = send_transfer(
+                app_state,
+                &buyer,
+                params.amount_lamports,
+                &params.reason,
+                &requester,
+            )
+            .await?;
+            Ok(json!(adjustment))
+        }
+        ApprovalAction::AdjustBalance => {
+            let params: AdjustmentParameters =
+                serde_json::from_value(parameters).map_err(invalid)?;
+            let buyer = fetch_buyer(app_state, &params.wallet).await?;
+            let adjustment = adjust_balance(
+                app_state,
+                &buyer,
+                params.amount_lamports,
+                &params.reason,
+                &requester,
+            )
+            .await?;
+            Ok(json!(adjustment))
+        }
+    }
+}
+
+async fn decide(
+    app_state: &AppState,
+    approval: &Approval,
+    status: &str,
+    user: &User,
+    reason: Option<&str>,
+) -> Result<(), Error> {
+    let decided = app_state
+        .db
+        .decide_approval(approval.id, status, &user.username, reason)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to decide approval {}: {}", approval.id, e);
+            InternalError::new(
+                "Failed to update approval request. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    if !decided {
+        return Err(InternalError::new(
+            format!("Approval request is already {}.", approval.status),
+            StatusCode::CONFLICT,
+        )
+        .into());
+    }
+    Ok(())
+}
+
+async fn fetch_approval(app_state: &AppState, approval_id: i64) -> Result<Approval, Error> {
+    let maybe_approval = app_state.db.get_approval(approval_id).await.map_err(|e| {
+        log::error!("Database error fetching approval {}: {}", approval_id, e);
+        InternalError::new(
+            "Internal server error while fetching approval.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    maybe_approval.ok_or_else(|| {
+        InternalError::new(
+            "Approval request with provided ID not found.",
+            StatusCode::NOT_FOUND,
+        )
+        .into()
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct ApprovalQuery {
+    /// `pending`, `approved`, `rejected`, `executed` or `failed`
+    status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RejectPayload {
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RetryParameters {
+    schedule_ids: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateGroupParameters {
+    group_id: i64,
+    group: GroupPayload,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupParameters {
+    group_id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuyerParameters {
+    wallet: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransferParameters {
+    wallet: String,
+    amount_lamports: u64,
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdjustmentParameters {
+    wallet: String,
+    amount_lamports: i64,
+    reason: String,
+}
@@ -0,0 +1,43 @@
+use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, web};
+use common::{AuditFilter, PageQuery, Permission, User};
+
+use super::require;
+use crate::state::AppState;
+
+#[get("/audit")]
+pub async fn get_audit_log(
+    filter: web::Query<AuditFilter>,
+    page: web::Query<PageQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ViewAudit)?;
+    if filter
+        .result
+        .as_deref()
+        .is_some_and(|result| !["success", "failure"].contains(&result))
+    {
+        return Err(InternalError::new(
+            "Audit result must be either 'success' or 'failure'.",
+            StatusCode::BAD_REQUEST,
+        )
+        .into());
+    }
+    let cursor = page
+        .validate()
+        .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+
+    let entries = app_state
+        .db
+        .list_audit_log(&filter, &page, cursor.as_ref())
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get audit log: {}", e);
+            InternalError::new(
+                "Failed to get audit log. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
@@ -1,9 +1,29 @@
+use super::sessions::revoke_session;
+use crate::jwt::{ACCESS_TOKEN_COOKIE, REFRESH_TOKEN_COOKIE, RotatingEd25519, SESSION_LIFETIME};
 use crate::state::AppState;
 use actix_jwt_auth_middleware::TokenSigner;
 use actix_web::Error;
-use actix_web::{HttpResponse, error::InternalError, http::StatusCode, post, web};
-use common::User;
-use jwt_compact::alg::Ed25519;
+use actix_web::cookie::Cookie;
+use actix_web::http::header::{RETRY_AFTER, USER_AGENT};
+use actix_web::{HttpRequest, HttpResponse, error::InternalError, http::StatusCode, post, web};
+use chrono::Utc;
+use common::{AuditEntry, Role, Session, Totp, User, hash_recovery_code};
+use serde_json::json;
+use std::sync::LazyLock;
+
+/// Same message for unknown users and wrong passwords, so accounts can't be enumerated.
+const INVALID_CREDENTIALS: &str = "Invalid username or password.";
+const TOO_MANY_ATTEMPTS: &str = "Too many login attempts. Try again later.";
+const INVALID_CHALLENGE: &str = "Login challenge is invalid or expired. Log in again.";
+const INVALID_CODE: &str = "Invalid authentication code.";
+/// Time to enter the TOTP code after the password.
+const TOTP_CHALLENGE_MINUTES: i64 = 5;
+
+/// Password hash checked for unknown users.
+static DUMMY_USER: LazyLock<User> = LazyLock::new(|| {
+    User::with_role("dummy_user", "dummy@example.com", "Dummy1234", Role::Viewer)
+        .expect("dummy user is valid")
+});
 
 #[derive(Debug, serde::Deserialize)]
 pub struct LoginData {
@@ -11,61 +31,439 @@ pub struct LoginData {
     pub password: String,
 }
 
+/// Second login step, returned instead of the cookies to users with TOTP.
+#[derive(Debug, serde::Serialize)]
+struct TotpChallenge {
+    totp_required: bool,
+    /// Sent back with the code to `/login/totp`.
+    challenge: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TotpLoginData {
+    pub challenge: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
 #[post("/login")]
 pub async fn login(
+    req: HttpRequest,
     login_data: web::Json<LoginData>,
     app_state: web::Data<AppState>,
-    cookie_signer: web::Data<TokenSigner<User, Ed25519>>,
+    cookie_signer: web::Data<TokenSigner<User, RotatingEd25519>>,
 ) -> Result<HttpResponse, Error> {
-    let user = match app_state
+    let ip_address = app_state.trusted_proxies.client_ip(&req);
+    let username = login_data.username.trim();
+
+    if let Err(retry_after) = app_state.login_guard.check(ip_address.as_deref(), username) {
+        log::warn!(
+            "Login rate limit hit for `{}` from {}",
+            username,
+            ip_address.as_deref().unwrap_or("-")
+        );
+        record_failed_login(&app_state, username, ip_address.as_deref(), "rate_limited").await;
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, retry_after.to_string()))
+            .body(TOO_MANY_ATTEMPTS));
+    }
+
+    let maybe_user = app_state.db.get_user(username).await.map_err(|e| {
+        log::error!("Database error while looking up user `{}`: {}", username, e);
+        InternalError::new(
+            "Internal error looking up user",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    let Some(mut user) = maybe_user else {
+        // Hash anyway, so unknown users can't be told apart by the response time
+        let _ = DUMMY_USER.verify_password(&login_data.password);
+        log::warn!("Login attempt for non-existent user `{}`", username);
+        record_failed_login(&app_state, username, ip_address.as_deref(), "unknown_user").await;
+        return Err(InternalError::new(INVALID_CREDENTIALS, StatusCode::UNAUTHORIZED).into());
+    };
+
+    let now = Utc::now().naive_utc();
+    if let Some(retry_after) = locked_for(&app_state, &user, now).await? {
+        log::warn!("Login attempt for locked user `{}`", user.username);
+        record_failed_login(&app_state, &user.username, ip_address.as_deref(), "locked").await;
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, retry_after.to_string()))
+            .body(TOO_MANY_ATTEMPTS));
+    }
+
+    if let Err(err) = user.verify_password(&login_data.password) {
+        log::warn!("Invalid password for user {}: {:?}", user.username, err);
+        count_failed_login(&app_state, &user, now).await;
+        record_failed_login(
+            &app_state,
+            &user.username,
+            ip_address.as_deref(),
+            "wrong_password",
+        )
+        .await;
+        return Err(InternalError::new(INVALID_CREDENTIALS, StatusCode::UNAUTHORIZED).into());
+    }
+
+    if !user.active {
+        // Answered like a wrong password, so the state of an account isn't revealed
+        log::warn!("Login attempt for deactivated user `{}`", user.username);
+        record_failed_login(
+            &app_state,
+            &user.username,
+            ip_address.as_deref(),
+            "deactivated",
+        )
+        .await;
+        return Err(InternalError::new(INVALID_CREDENTIALS, StatusCode::UNAUTHORIZED).into());
+    }
+
+    let totp = app_state.db.get_user_totp(user.id).await.map_err(|e| {
+        log::error!("Failed to get TOTP of `{}`: {}", user.username, e);
+        InternalError::new(
+            "Internal error looking up user",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    if user.is_superuser && !totp.enabled {
+        // The secret is handed over by an admin, never to whoever knows the password
+        log::warn!(
+            "Login of superuser `{}` without a second factor",
+            user.username
+        );
+        record_failed_login(
+            &app_state,
+            &user.username,
+            ip_address.as_deref(),
+            "totp_not_enrolled",
+        )
+        .await;
+        return Err(InternalError::new(
+            "Two-factor authentication is required for superusers. Ask an admin to enroll it.",
+            StatusCode::FORBIDDEN,
+        )
+        .into());
+    }
+    if totp.enabled {
+        // Failed logins are reset only once the second factor is checked too
+        let session = start_session(
+            &req,
+            &app_state,
+            &user,
+            chrono::Duration::minutes(TOTP_CHALLENGE_MINUTES),
+            true,
+        )
+        .await?;
+        return Ok(HttpResponse::Ok().json(TotpChallenge {
+            totp_required: true,
+            challenge: session.id,
+        }));
+    }
+
+    reset_failed_logins(&app_state, &user).await;
+
+    let session = start_session(
+        &req,
+        &app_state,
+        &user,
+        chrono::Duration::from_std(SESSION_LIFETIME).expect("session lifetime fits"),
+        false,
+    )
+    .await?;
+    user.session_id = Some(session.id);
+
+    let (access_cookie, refresh_cookie) = token_cookies(&cookie_signer, &user)?;
+    Ok(HttpResponse::Ok()
+        .cookie(access_cookie)
+        .cookie(refresh_cookie)
+        .body("Login successful."))
+}
+
+/// Second login step of users with TOTP: exchanges the challenge of `/login` and a TOTP or
+/// recovery code for the token cookies.
+#[post("/login/totp")]
+pub async fn login_totp(
+    req: HttpRequest,
+    payload: web::Json<TotpLoginData>,
+    app_state: web::Data<AppState>,
+    cookie_signer: web::Data<TokenSigner<User, RotatingEd25519>>,
+) -> Result<HttpResponse, Error> {
+    let ip_address = app_state.trusted_proxies.client_ip(&req);
+    let payload = payload.into_inner();
+
+    let session = app_state
         .db
-        .get_user(&login_data.username)
+        .get_session(&payload.challenge)
         .await
         .map_err(|e| {
-            log::error!(
-                "Database error while looking up user `{}`: {}",
-                login_data.username,
-                e
-            );
+            log::error!("Failed to get session {}: {}", payload.challenge, e);
             InternalError::new(
-                "Internal error looking up user",
+                "Internal error looking up session",
                 StatusCode::INTERNAL_SERVER_ERROR,
             )
-        })? {
-        Some(u) => u,
-        None => {
-            log::warn!(
-                "Login attempt for non-existent user `{}`",
-                login_data.username
-            );
-            return Err(InternalError::new(
-                "User with provided username not found!",
-                StatusCode::UNAUTHORIZED,
+        })?
+        .filter(|s| s.pending_totp && s.is_open())
+        .ok_or_else(|| InternalError::new(INVALID_CHALLENGE, StatusCode::UNAUTHORIZED))?;
+    let mut user = app_state
+        .db
+        .get_user_by_id(session.user_id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get user id={}: {}", session.user_id, e);
+            InternalError::new(
+                "Internal error looking up user",
+                StatusCode::INTERNAL_SERVER_ERROR,
             )
-            .into());
-        }
+        })?
+        .filter(|u| u.active)
+        .ok_or_else(|| InternalError::new(INVALID_CHALLENGE, StatusCode::UNAUTHORIZED))?;
+
+    if let Err(retry_after) = app_state
+        .login_guard
+        .check(ip_address.as_deref(), &user.username)
+    {
+        log::warn!(
+            "Login rate limit hit for `{}` from {}",
+            user.username,
+            ip_address.as_deref().unwrap_or("-")
+        );
+        record_failed_login(
+            &app_state,
+            &user.username,
+            ip_address.as_deref(),
+            "rate_limited",
+        )
+        .await;
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, retry_after.to_string()))
+            .body(TOO_MANY_ATTEMPTS));
+    }
+
+    let now = Utc::now();
+    if let Some(retry_after) = locked_for(&app_state, &user, now.naive_utc()).await? {
+        log::warn!("TOTP attempt for locked user `{}`", user.username);
+        record_failed_login(&app_state, &user.username, ip_address.as_deref(), "locked").await;
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, retry_after.to_string()))
+            .body(TOO_MANY_ATTEMPTS));
+    }
+
+    let totp = app_state.db.get_user_totp(user.id).await.map_err(|e| {
+        log::error!("Failed to get TOTP of `{}`: {}", user.username, e);
+        InternalError::new(
+            "Internal error looking up user",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    let Some(secret) = totp.secret.as_deref().filter(|_| totp.enabled) else {
+        // The second factor was reset since the password step
+        return Err(InternalError::new(INVALID_CHALLENGE, StatusCode::UNAUTHORIZED).into());
     };
+    let secret = Totp::from_secret(secret).map_err(|e| {
+        log::error!("Invalid TOTP secret of `{}`: {}", user.username, e);
+        InternalError::new(
+            "Internal error checking code",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
 
-    if let Err(err) = user.verify_password(&login_data.password) {
+    let verified = match (&payload.code, &payload.recovery_code) {
+        (Some(code), _) => match secret.verify(code, now.timestamp()) {
+            Some(step) => app_state.db.use_totp_step(user.id, step).await,
+            None => Ok(false),
+        },
+        (None, Some(recovery_code)) => {
+            app_state
+                .db
+                .use_recovery_code(user.id, &hash_recovery_code(recovery_code))
+                .await
+        }
+        _ => Ok(false),
+    }
+    .map_err(|e| {
+        log::error!("Failed to check TOTP of `{}`: {}", user.username, e);
+        InternalError::new(
+            "Internal error checking code",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    if !verified {
+        log::warn!("Invalid second factor for user {}", user.username);
+        count_failed_login(&app_state, &user, now.naive_utc()).await;
+        let reason = if payload.code.is_some() {
+            "wrong_totp"
+        } else {
+            "wrong_recovery_code"
+        };
+        record_failed_login(&app_state, &user.username, ip_address.as_deref(), reason).await;
+        return Err(InternalError::new(INVALID_CODE, StatusCode::UNAUTHORIZED).into());
+    }
+
+    reset_failed_logins(&app_state, &user).await;
+    if payload.code.is_none() {
         log::warn!(
-            "Invalid password for user {}: {:?}",
-            login_data.username,
-            err
+            "User `{}` logged in with a recovery code, {} left",
+            user.username,
+            totp.recovery_codes_left - 1
+        );
+    }
+
+    let expires_at = now.naive_utc()
+        + chrono::Duration::from_std(SESSION_LIFETIME).expect("session lifetime fits");
+    app_state
+        .db
+        .confirm_session(&session.id, expires_at)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to confirm session of `{}`: {}", user.username, e);
+            InternalError::new(
+                "Internal error creating session",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    user.session_id = Some(session.id);
+
+    let (access_cookie, refresh_cookie) = token_cookies(&cookie_signer, &user)?;
+    Ok(HttpResponse::Ok()
+        .cookie(access_cookie)
+        .cookie(refresh_cookie)
+        .json(json!({ "message": "Login successful." })))
+}
+
+/// Ends the session of the request and removes the token cookies.
+#[post("/logout")]
+pub async fn logout(user: User, app_state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    if let Some(session_id) = &user.session_id {
+        revoke_session(&app_state, session_id).await?;
+    }
+    log::info!("User `{}` logged out", user.username);
+
+    let mut access_cookie = Cookie::new(ACCESS_TOKEN_COOKIE, "");
+    access_cookie.make_removal();
+    let mut refresh_cookie = Cookie::new(REFRESH_TOKEN_COOKIE, "");
+    refresh_cookie.make_removal();
+    Ok(HttpResponse::Ok()
+        .cookie(access_cookie)
+        .cookie(refresh_cookie)
+        .body("Logout successful."))
+}
+
+/// Writes a failed login to the audit log. Login has no logged in user, so the attempted
+/// username is the actor.
+async fn record_failed_login(
+    app_state: &AppState,
+    username: &str,
+    ip_address: Option<&str>,
+    reason: &str,
+) {
+    let mut entry = AuditEntry::new(
+        "api",
+        Some(username.to_string()),
+        "login_failed",
+        json!({ "reason": reason }),
+    )
+    .failed(reason);
+    entry.ip_address = ip_address.map(str::to_string);
+    if let Err(e) = app_state.db.save_audit_entry(&entry).await {
+        log::error!("Failed to write failed login of `{}`: {:#}", username, e);
+    }
+}
+
+/// Seconds until the lockout of `user` ends, if locked.
+async fn locked_for(
+    app_state: &AppState,
+    user: &User,
+    now: chrono::NaiveDateTime,
+) -> Result<Option<i64>, Error> {
+    let locked_until = app_state
+        .db
+        .get_user_locked_until(user.id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get lockout of `{}`: {}", user.username, e);
+            InternalError::new(
+                "Internal error looking up user",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    Ok(locked_until
+        .filter(|t| *t > now)
+        .map(|t| (t - now).num_seconds()))
+}
+
+/// Clears the failed logins of `user` after both factors were checked.
+async fn reset_failed_logins(app_state: &AppState, user: &User) {
+    if let Err(e) = app_state.db.reset_failed_logins(user.id).await {
+        log::error!(
+            "Failed to reset failed logins of `{}`: {}",
+            user.username,
+            e
         );
-        return Ok(HttpResponse::Unauthorized().body("Invalid username or password"));
     }
+}
 
-    let access_cookie = cookie_signer.create_access_cookie(&user).map_err(|err| {
+/// Counts a wrong password or code towards the lockout of `user`.
+async fn count_failed_login(app_state: &AppState, user: &User, now: chrono::NaiveDateTime) {
+    let policy = &app_state.login_guard.policy;
+    match app_state
+        .db
+        .record_failed_login(user.id, policy.max_failures, now + policy.lockout)
+        .await
+    {
+        Ok(true) => log::warn!(
+            "User `{}` locked for {} minutes after {} failed logins",
+            user.username,
+            policy.lockout.num_minutes(),
+            policy.max_failures
+        ),
+        Ok(false) => {}
+        Err(e) => log::error!(
+            "Failed to record failed login of `{}`: {}",
+            user.username,
+            e
+        ),
+    }
+}
+
+async fn start_session(
+    req: &HttpRequest,
+    app_state: &AppState,
+    user: &User,
+    lifetime: chrono::Duration,
+    pending_totp: bool,
+) -> Result<Session, Error> {
+    let mut session = Session::new(
+        user.id,
+        lifetime,
+        app_state.trusted_proxies.client_ip(req),
+        req.headers()
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.chars().take(255).collect()),
+    );
+    session.pending_totp = pending_totp;
+    app_state.db.save_session(&session).await.map_err(|e| {
+        log::error!("Failed to save session of `{}`: {}", user.username, e);
+        InternalError::new(
+            "Internal error creating session",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    Ok(session)
+}
+
+fn token_cookies(
+    cookie_signer: &TokenSigner<User, RotatingEd25519>,
+    user: &User,
+) -> Result<(Cookie<'static>, Cookie<'static>), Error> {
+    let access_cookie = cookie_signer.create_access_cookie(user).map_err(|err| {
         log::error!("Failed to create access token: {:?}", err);
         InternalError::new("Token error", StatusCode::INTERNAL_SERVER_ERROR)
     })?;
 
-    let refresh_cookie = cookie_signer.create_refresh_cookie(&user).map_err(|err| {
+    let refresh_cookie = cookie_signer.create_refresh_cookie(user).map_err(|err| {
         log::error!("Failed to create refresh token: {:?}", err);
         InternalError::new("Token error", StatusCode::INTERNAL_SERVER_ERROR)
     })?;
-    Ok(HttpResponse::Ok()
-        .cookie(access_cookie)
-        .cookie(refresh_cookie)
-        .body("Login successful."))
+    Ok((access_cookie, refresh_cookie))
 }
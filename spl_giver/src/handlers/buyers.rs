@@ -1,28 +1,47 @@
-use std::collections::HashMap;
+use std::str::FromStr;
 
+use super::approvals::request_approval;
+use super::require;
+use crate::distribution::{
+    ScheduleDiff, buyer_allocation, group_allocation, initialize_buyer_schedules, replan,
+    vested_lamports,
+};
 use crate::state::AppState;
-use actix_multipart::form::{MultipartForm, tempfile::TempFile};
-use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, post, web};
-use common::{Buyer, Schedule};
+use actix_web::{
+    Error, HttpResponse, delete, error::InternalError, get, http::StatusCode, post, put, web,
+};
+use chrono::Utc;
+use common::{
+    ApprovalAction, Buyer, BuyerFilter, Group, PageQuery, Permission, Schedule, User,
+    VestingSummary,
+};
 use serde::Deserialize;
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
 
 #[get("/buyers")]
 pub async fn get_buyers(
-    query: web::Query<BuyerQuery>,
+    filter: web::Query<BuyerFilter>,
+    page: web::Query<PageQuery>,
+    user: User,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    let buyers_result = match query.group_id {
-        Some(group_id) => app_state.db.get_buyers_by_group(group_id).await,
-        None => app_state.db.get_all_buyers().await,
-    };
+    require(&user, Permission::Read)?;
+    let cursor = page
+        .validate()
+        .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
 
-    let buyers = buyers_result.map_err(|e| {
-        log::error!("Failed to fetch buyers: {}", e);
-        InternalError::new(
-            "Failed to get buyers. Please try again later.",
-            StatusCode::INTERNAL_SERVER_ERROR,
-        )
-    })?;
+    let buyers = app_state
+        .db
+        .list_buyers(&filter, &page, cursor.as_ref())
+        .await
+        .map_err(|e| {
+            log::error!("Failed to fetch buyers: {}", e);
+            InternalError::new(
+                "Failed to get buyers. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
 
     Ok(HttpResponse::Ok().json(buyers))
 }
@@ -30,8 +49,10 @@ pub async fn get_buyers(
 #[get("/buyers/{wallet}")]
 pub async fn get_buyer_by_wallet(
     path: web::Path<String>,
+    user: User,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
     let wallet = path.into_inner();
 
     let maybe_buyer = app_state
@@ -61,108 +82,399 @@ pub async fn get_buyer_by_wallet(
     Ok(HttpResponse::Ok().json(buyer))
 }
 
-#[post("/buyers/upload")]
-pub async fn upload_buyers_csv(
-    MultipartForm(form): MultipartForm<CsvUploadForm>,
+#[get("/buyers/{wallet}/vesting")]
+pub async fn get_buyer_vesting(
+    path: web::Path<String>,
+    user: User,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    let filename = form.file.file_name.as_deref().unwrap_or("");
-    if !filename.to_lowercase().ends_with(".csv") {
-        return Err(InternalError::new("Only CSV files allowed", StatusCode::BAD_REQUEST).into());
-    }
+    require(&user, Permission::Read)?;
+    let wallet = path.into_inner();
+    let buyer = fetch_buyer(&app_state, &wallet).await?;
+    let group = fetch_buyer_group(&app_state, buyer.group_id).await?;
 
-    let groups = app_state.db.get_all_groups().await.map_err(|e| {
-        log::error!("Failed to fetch groups: {}", e);
+    let db_error = |e: anyhow::Error| {
+        log::error!("Failed to load vesting of buyer {}: {}", wallet, e);
         InternalError::new(
-            "Failed to fetch groups from database",
+            "Failed to get buyer vesting. Please try again later.",
             StatusCode::INTERNAL_SERVER_ERROR,
         )
-    })?;
+    };
+    let schedules = app_state
+        .db
+        .get_schedules_by_buyer_and_group(&wallet, buyer.group_id)
+        .await
+        .map_err(db_error)?;
+    let transactions = app_state
+        .db
+        .get_transactions_by_wallet(&wallet)
+        .await
+        .map_err(db_error)?;
 
-    let path = form.file.file.path().to_string_lossy().to_string();
+    let summary = VestingSummary::new(
+        &buyer,
+        buyer_allocation(&group, &buyer),
+        schedules,
+        &transactions,
+        Utc::now().naive_utc(),
+    );
+
+    Ok(HttpResponse::Ok().json(summary))
+}
 
-    let buyers = Buyer::load_from_csv(&path, &groups).await.map_err(|e| {
-        log::error!("Failed to parse CSV: {}", e);
+#[post("/buyers")]
+pub async fn create_buyer(
+    payload: web::Json<BuyerPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageBuyers)?;
+    let payload = payload.into_inner();
+    let wallet = Pubkey::from_str(&payload.wallet).map_err(|e| {
         InternalError::new(
-            format!("Failed to parse CSV: {}", e),
+            format!("Invalid wallet `{}`: {}", payload.wallet, e),
             StatusCode::BAD_REQUEST,
         )
     })?;
 
-    let mut imported = Vec::new();
-    let mut skipped = Vec::new();
-
-    for b in &buyers {
-        let wallet = b.wallet.to_string();
-        match app_state.db.save_buyer(b).await {
-            Ok(true) => {
-                // newly inserted
-                if let Ok(Some(saved)) = app_state.db.get_buyer_by_wallet(&wallet).await {
-                    imported.push(saved);
-                }
-            }
-            Ok(false) => {
-                // already existed
-                if let Ok(Some(existing)) = app_state.db.get_buyer_by_wallet(&wallet).await {
-                    skipped.push(existing);
-                }
-            }
-            Err(e) => {
-                log::error!("Failed to save buyer {}: {}", wallet, e);
-                // treat as skipped
-                if let Ok(Some(existing)) = app_state.db.get_buyer_by_wallet(&wallet).await {
-                    skipped.push(existing);
-                }
-            }
-        }
+    let buyer = Buyer {
+        wallet,
+        paid_lamports: payload.paid_lamports,
+        group_id: payload.group_id,
+        received_spl_lamports: 0,
+        received_percent: 0.0,
+        pending_spl_lamports: 0,
+        error: None,
+        created_at: None,
+        updated_at: None,
+    };
+    let (buyer, created) = add_buyer(&app_state, buyer).await?;
+
+    log::info!(
+        "Buyer {} added to group {} by `{}`",
+        wallet,
+        buyer.group_id,
+        user.username
+    );
+
+    Ok(HttpResponse::Created().json(BuyerChangeResponse {
+        buyer,
+        schedules: ScheduleDiff {
+            removed: Vec::new(),
+            created,
+        },
+    }))
+}
+
+#[put("/buyers/{wallet}")]
+pub async fn update_buyer(
+    path: web::Path<String>,
+    payload: web::Json<BuyerUpdatePayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageBuyers)?;
+    let wallet = path.into_inner();
+    let current = fetch_buyer(&app_state, &wallet).await?;
+
+    let (buyer, schedules) = change_buyer(
+        &app_state,
+        &current,
+        payload.paid_lamports.unwrap_or(current.paid_lamports),
+        payload.group_id.unwrap_or(current.group_id),
+    )
+    .await?;
+
+    log::info!("Buyer {} updated by `{}`", wallet, user.username);
+
+    Ok(HttpResponse::Ok().json(BuyerChangeResponse { buyer, schedules }))
+}
+
+#[delete("/buyers/{wallet}")]
+pub async fn delete_buyer(
+    path: web::Path<String>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageBuyers)?;
+    let wallet = path.into_inner();
+    let buyer = fetch_buyer(&app_state, &wallet).await?;
+
+    check_removable(&app_state, &buyer).await?;
+    // Revokes the unsent allocation of the buyer
+    let approval = request_approval(
+        &app_state,
+        &user,
+        ApprovalAction::DeleteBuyer,
+        buyer.pending_spl_lamports,
+        json!({ "wallet": wallet }),
+    )
+    .await?;
+    if let Some(approval) = approval {
+        return Ok(HttpResponse::Accepted().json(approval));
     }
 
-    if !imported.is_empty() {
-        if let Err(e) = crate::distribution::initialize_schedules(&app_state).await {
-            log::error!("Failed to initialize schedules for new buyers: {}", e);
-        }
+    remove_buyer(&app_state, &buyer).await?;
+
+    log::info!("Buyer {} deleted by `{}`", wallet, user.username);
+
+    Ok(HttpResponse::Ok().json(json!({ "deleted": wallet })))
+}
+
+/// Saves a new buyer and creates their schedules, if the group can cover the allocation.
+pub(super) async fn add_buyer(
+    app_state: &AppState,
+    mut buyer: Buyer,
+) -> Result<(Buyer, Vec<Schedule>), Error> {
+    let wallet = buyer.wallet.to_string();
+    let group = fetch_buyer_group(app_state, buyer.group_id).await?;
+    buyer.pending_spl_lamports = buyer_allocation(&group, &buyer);
+    check_group_capacity(app_state, &group, &buyer).await?;
+
+    let inserted = app_state.db.save_buyer(&buyer).await.map_err(|e| {
+        log::error!("Failed to save buyer {}: {}", wallet, e);
+        InternalError::new(
+            "Failed to save buyer. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    if !inserted {
+        return Err(InternalError::new(
+            "Buyer with provided wallet already exists.",
+            StatusCode::CONFLICT,
+        )
+        .into());
     }
 
-    let mut schedules_map = HashMap::new();
-    for buyer in imported.iter().chain(skipped.iter()) {
-        let w = buyer.wallet.to_string();
-        if let Ok(list) = app_state
+    let created = initialize_buyer_schedules(app_state, &group, &buyer)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to initialize schedules for buyer {}: {}", wallet, e);
+            InternalError::new(
+                "Buyer saved, but failed to create schedules. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    let buyer = fetch_buyer(app_state, &wallet).await?;
+    Ok((buyer, created))
+}
+
+/// Changes the purchase of a buyer and recomputes their `pending` schedules.
+pub(super) async fn change_buyer(
+    app_state: &AppState,
+    current: &Buyer,
+    paid_lamports: u64,
+    group_id: i64,
+) -> Result<(Buyer, ScheduleDiff), Error> {
+    let wallet = current.wallet.to_string();
+    let mut buyer = current.clone();
+    buyer.paid_lamports = paid_lamports;
+    buyer.group_id = group_id;
+
+    let group_changed = buyer.group_id != current.group_id;
+    if group_changed && !nothing_sent(app_state, current).await? {
+        return Err(moved_after_transfer());
+    }
+
+    let db_error = |e: anyhow::Error| {
+        log::error!("Failed to update buyer {}: {}", wallet, e);
+        InternalError::new(
+            "Failed to update buyer. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    };
+
+    let group = fetch_buyer_group(app_state, buyer.group_id).await?;
+    let allocation = buyer_allocation(&group, &buyer);
+    let vested = vested_lamports(
+        &app_state
             .db
-            .get_schedules_by_buyer_and_group(&w, buyer.group_id)
+            .get_schedules_by_buyer_and_group(&wallet, current.group_id)
             .await
-        {
-            schedules_map.insert(w, list);
-        }
+            .map_err(db_error)?,
+    );
+    if allocation < vested {
+        return Err(InternalError::new(
+            format!(
+                "New allocation {} is lower than already vested {} SPL lamports.",
+                allocation, vested
+            ),
+            StatusCode::BAD_REQUEST,
+        )
+        .into());
     }
+    check_group_capacity(app_state, &group, &buyer).await?;
 
-    log::info!(
-        "Uploaded buyers from CSV. Imported {} buyers, skipped {} buyers",
-        imported.len(),
-        skipped.len()
-    );
+    // Pending schedules of the previous group are dropped, the new group gets a fresh plan
+    let (removed, created) = app_state
+        .db
+        .update_buyer_details(
+            &wallet,
+            buyer.paid_lamports,
+            buyer.group_id,
+            replan(&group, Utc::now().naive_utc()),
+        )
+        .await
+        .map_err(db_error)?
+        .ok_or_else(moved_after_transfer)?;
+    let schedules = ScheduleDiff { removed, created };
 
-    let response = UploadBuyersResponse {
-        imported,
-        skipped,
-        schedules: schedules_map,
-    };
-    Ok(HttpResponse::Ok().json(response))
+    let buyer = fetch_buyer(app_state, &wallet).await?;
+    Ok((buyer, schedules))
+}
+
+fn moved_after_transfer() -> Error {
+    InternalError::new(
+        "Buyer already received tokens and can't be moved to another group.",
+        StatusCode::CONFLICT,
+    )
+    .into()
+}
+
+/// Deletes a buyer once it's confirmed that nothing was sent to them.
+pub(super) async fn remove_buyer(app_state: &AppState, buyer: &Buyer) -> Result<(), Error> {
+    let wallet = buyer.wallet.to_string();
+    check_removable(app_state, buyer).await?;
+
+    let deleted = app_state.db.delete_buyer(&wallet).await.map_err(|e| {
+        log::error!("Failed to delete buyer {}: {}", wallet, e);
+        InternalError::new(
+            "Failed to delete buyer. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    if !deleted {
+        return Err(not_removable());
+    }
+    Ok(())
+}
+
+/// Rejects deleting a buyer that already received tokens.
+pub(super) async fn check_removable(app_state: &AppState, buyer: &Buyer) -> Result<(), Error> {
+    if !nothing_sent(app_state, buyer).await? {
+        return Err(not_removable());
+    }
+    Ok(())
+}
+
+fn not_removable() -> Error {
+    InternalError::new(
+        "Buyer already received tokens or has transfers that may have been sent, and can't be deleted.",
+        StatusCode::CONFLICT,
+    )
+    .into()
+}
+
+pub(super) async fn fetch_buyer(app_state: &AppState, wallet: &str) -> Result<Buyer, Error> {
+    let maybe_buyer = app_state
+        .db
+        .get_buyer_by_wallet(wallet)
+        .await
+        .map_err(|e| {
+            log::error!("DB error fetching buyer `{}`: {}", wallet, e);
+            InternalError::new(
+                "Internal server error while fetching buyer.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    maybe_buyer.ok_or_else(|| {
+        log::warn!("Buyer not found: {}", wallet);
+        InternalError::new(
+            "Buyer with provided wallet not found.",
+            StatusCode::NOT_FOUND,
+        )
+        .into()
+    })
+}
+
+async fn fetch_buyer_group(app_state: &AppState, group_id: i64) -> Result<Group, Error> {
+    let maybe_group = app_state.db.get_group(group_id).await.map_err(|e| {
+        log::error!("Database error fetching group {}: {}", group_id, e);
+        InternalError::new(
+            "Internal server error while fetching group.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    maybe_group.ok_or_else(|| {
+        InternalError::new(
+            format!("Group {} not found.", group_id),
+            StatusCode::BAD_REQUEST,
+        )
+        .into()
+    })
+}
+
+/// Rejects the buyer if the group can't cover its allocation next to the other buyers.
+async fn check_group_capacity(
+    app_state: &AppState,
+    group: &Group,
+    buyer: &Buyer,
+) -> Result<(), Error> {
+    let allocated = group_allocation(app_state, group, Some(&buyer.wallet.to_string()))
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get allocation of group {}: {}", group.id, e);
+            InternalError::new(
+                "Failed to check group allocation. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    let requested = buyer_allocation(group, buyer);
+    if allocated + requested > group.spl_total_lamports {
+        return Err(InternalError::new(
+            format!(
+                "Group {} does not have enough SPL tokens: spl_total_lamports = {}, allocated = {}, requested = {}",
+                group.id, group.spl_total_lamports, allocated, requested
+            ),
+            StatusCode::BAD_REQUEST,
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Confirms that no tokens were sent to the buyer and that no transfer may be on its way:
+/// no balance, no schedule other than `pending`, no transaction and no manual transfer or
+/// adjustment. The database checks this again when it deletes or moves the buyer.
+async fn nothing_sent(app_state: &AppState, buyer: &Buyer) -> Result<bool, Error> {
+    if buyer.received_spl_lamports > 0 {
+        return Ok(false);
+    }
+
+    let wallet = buyer.wallet.to_string();
+    let has_transfers = app_state
+        .db
+        .buyer_has_transfers(&wallet)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to check transfers of buyer {}: {}", wallet, e);
+            InternalError::new(
+                "Failed to check buyer transfers. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    Ok(!has_transfers)
 }
 
 #[derive(Debug, Deserialize)]
-struct BuyerQuery {
-    group_id: Option<i64>,
+struct BuyerPayload {
+    wallet: String,
+    paid_lamports: u64,
+    group_id: i64,
 }
 
-#[derive(Debug, MultipartForm)]
-struct CsvUploadForm {
-    #[multipart(limit = "10MB")]
-    file: TempFile,
+#[derive(Debug, Deserialize)]
+struct BuyerUpdatePayload {
+    paid_lamports: Option<u64>,
+    group_id: Option<i64>,
 }
 
 #[derive(serde::Serialize)]
-struct UploadBuyersResponse {
-    imported: Vec<Buyer>,
-    skipped: Vec<Buyer>,
-    schedules: HashMap<String, Vec<Schedule>>,
+struct BuyerChangeResponse {
+    buyer: Buyer,
+    schedules: ScheduleDiff,
 }
@@ -0,0 +1,116 @@
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse, get, web, web::Bytes};
+use common::{ApiKey, Permission, User};
+use serde::Deserialize;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio::time::{Duration, interval};
+use tokio_stream::wrappers::ReceiverStream;
+
+use super::require;
+use crate::events::LiveEvent;
+use crate::jwt::still_authenticated;
+use crate::state::AppState;
+
+/// Comment sent to idle connections so proxies don't close them. The session is checked
+/// again at the same pace, the stream ends once it was revoked or expired.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+const STREAM_BUFFER: usize = 64;
+
+/// Server-Sent Events stream of schedule, transaction and runner events.
+#[get("/events")]
+pub async fn stream_events(
+    req: HttpRequest,
+    query: web::Query<EventsQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let query = query.into_inner();
+    // Browsers send the header when they reconnect, other clients may use the query parameter
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .or(query.last_event_id);
+
+    let api_key = req.extensions().get::<ApiKey>().cloned();
+
+    let (missed, mut events) = app_state.events.subscribe(last_event_id);
+    let (tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(STREAM_BUFFER);
+
+    tokio::spawn(async move {
+        for event in missed.iter().filter(|e| query.matches(e)) {
+            if tx.send(Ok(encode_event(event))).await.is_err() {
+                return;
+            }
+        }
+
+        let mut keep_alive = interval(KEEP_ALIVE);
+        loop {
+            let frame = tokio::select! {
+                received = events.recv() => match received {
+                    Ok(event) if query.matches(&event) => encode_event(&event),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("Event stream client lagged, {} events skipped", skipped);
+                        Bytes::from(format!(": {} events skipped\n\n", skipped))
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+                _ = keep_alive.tick() => {
+                    if !still_authenticated(&app_state, &user, api_key.as_ref()).await {
+                        log::info!("Closing event stream of `{}`, session ended", user.username);
+                        return;
+                    }
+                    Bytes::from_static(b": keep-alive\n\n")
+                }
+            };
+            if tx.send(Ok(frame)).await.is_err() {
+                // Client disconnected
+                return;
+            }
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(ReceiverStream::new(rx)))
+}
+
+fn encode_event(event: &LiveEvent) -> Bytes {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        event.id, event.kind, data
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct EventsQuery {
+    group_id: Option<i64>,
+    wallet: Option<String>,
+    /// Comma separated event kinds
+    kinds: Option<String>,
+    last_event_id: Option<u64>,
+}
+
+impl EventsQuery {
+    /// Runner events don't belong to a group or wallet, so only `kinds` filters them out.
+    fn matches(&self, event: &LiveEvent) -> bool {
+        let kind_matches = self
+            .kinds
+            .as_ref()
+            .is_none_or(|kinds| kinds.split(',').any(|k| k.trim() == event.kind));
+        let group_matches = self
+            .group_id
+            .zip(event.group_id)
+            .is_none_or(|(group_id, event_group)| group_id == event_group);
+        let wallet_matches = match (&self.wallet, &event.wallet) {
+            (Some(wallet), Some(event_wallet)) => wallet == event_wallet,
+            _ => true,
+        };
+        kind_matches && group_matches && wallet_matches
+    }
+}
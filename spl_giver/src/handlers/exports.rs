@@ -0,0 +1,180 @@
+use super::require;
+use crate::state::AppState;
+use actix_web::{
+    Error, HttpResponse, get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web::{self, Bytes},
+};
+use common::{Permission, User};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt, wrappers::ReceiverStream};
+
+/// Number of encoded rows buffered between the database stream and the client.
+const EXPORT_BUFFER: usize = 64;
+
+#[get("/buyers/export")]
+pub async fn export_buyers(
+    query: web::Query<ExportQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let format = query.format;
+    let (tx, rx) = mpsc::channel(EXPORT_BUFFER);
+    tokio::spawn(async move {
+        write_export(app_state.db.stream_buyers(), format, tx).await;
+    });
+    Ok(export_response("buyers", format, rx))
+}
+
+#[get("/schedule/export")]
+pub async fn export_schedules(
+    query: web::Query<ExportQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let format = query.format;
+    let (tx, rx) = mpsc::channel(EXPORT_BUFFER);
+    tokio::spawn(async move {
+        write_export(app_state.db.stream_schedules(), format, tx).await;
+    });
+    Ok(export_response("schedule", format, rx))
+}
+
+#[get("/transactions/export")]
+pub async fn export_transactions(
+    query: web::Query<ExportQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let format = query.format;
+    let (tx, rx) = mpsc::channel(EXPORT_BUFFER);
+    tokio::spawn(async move {
+        write_export(app_state.db.stream_transactions(), format, tx).await;
+    });
+    Ok(export_response("transactions", format, rx))
+}
+
+fn export_response(
+    name: &str,
+    format: ExportFormat,
+    rx: mpsc::Receiver<Result<Bytes, std::io::Error>>,
+) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "{}.{}",
+                name,
+                format.extension()
+            ))],
+        })
+        .streaming(ReceiverStream::new(rx))
+}
+
+/// Encodes every row of `rows` and sends it to the response body.
+/// Stops early if the client disconnects; a database error aborts the response.
+async fn write_export<T, S>(
+    rows: S,
+    format: ExportFormat,
+    tx: mpsc::Sender<Result<Bytes, std::io::Error>>,
+) where
+    T: Serialize,
+    S: Stream<Item = anyhow::Result<T>>,
+{
+    tokio::pin!(rows);
+
+    if format == ExportFormat::Json && tx.send(Ok(Bytes::from_static(b"["))).await.is_err() {
+        return;
+    }
+
+    let mut first = true;
+    while let Some(row) = rows.next().await {
+        let chunk = match row {
+            Ok(item) => encode_row(&item, format, first).await,
+            Err(e) => Err(e),
+        };
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                log::error!("Failed to export rows: {:?}", e);
+                let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+                return;
+            }
+        };
+        if tx.send(Ok(Bytes::from(chunk))).await.is_err() {
+            log::debug!("Export client disconnected");
+            return;
+        }
+        first = false;
+    }
+
+    if format == ExportFormat::Json {
+        let _ = tx.send(Ok(Bytes::from_static(b"]"))).await;
+    }
+}
+
+async fn encode_row<T: Serialize>(
+    item: &T,
+    format: ExportFormat,
+    first: bool,
+) -> anyhow::Result<Vec<u8>> {
+    match format {
+        ExportFormat::Csv => {
+            let mut wtr = csv_async::AsyncWriterBuilder::new()
+                .has_headers(first)
+                .create_serializer(Vec::new());
+            wtr.serialize(item).await?;
+            wtr.into_inner()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to encode CSV row: {}", e.error()))
+        }
+        ExportFormat::Json => {
+            let mut chunk = if first { Vec::new() } else { b",".to_vec() };
+            serde_json::to_writer(&mut chunk, item)?;
+            Ok(chunk)
+        }
+        ExportFormat::Ndjson => {
+            let mut chunk = serde_json::to_vec(item)?;
+            chunk.push(b'\n');
+            Ok(chunk)
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+    Ndjson,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
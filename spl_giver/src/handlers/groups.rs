@@ -1,8 +1,21 @@
+use super::approvals::request_approval;
+use super::require;
+use crate::distribution::{ScheduleDiff, buyer_allocation, preview_group_schedules, replan};
 use crate::state::AppState;
-use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, web};
+use actix_web::{
+    Error, HttpResponse, delete, error::InternalError, get, http::StatusCode, post, put, web,
+};
+use chrono::Utc;
+use common::{ApprovalAction, Group, GroupUpdate, Permission, User};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 #[get("/groups")]
-pub async fn get_all_groups(app_state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+pub async fn get_all_groups(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
     let groups_result = app_state.db.get_all_groups().await;
 
     let groups = groups_result.map_err(|e| {
@@ -18,8 +31,10 @@ pub async fn get_all_groups(app_state: web::Data<AppState>) -> Result<HttpRespon
 #[get("/groups/{group_id}")]
 pub async fn get_group_by_id(
     path: web::Path<i64>,
+    user: User,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
     let group_id = path.into_inner();
 
     let maybe_group = app_state.db.get_group(group_id).await.map_err(|e| {
@@ -44,3 +59,401 @@ pub async fn get_group_by_id(
 
     Ok(HttpResponse::Ok().json(group))
 }
+
+#[get("/groups/{group_id}/versions")]
+pub async fn get_group_versions(
+    path: web::Path<i64>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let group_id = path.into_inner();
+
+    let versions = app_state
+        .db
+        .get_group_versions(group_id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get versions of group {}: {}", group_id, e);
+            InternalError::new(
+                "Failed to get group versions. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    Ok(HttpResponse::Ok().json(versions))
+}
+
+#[post("/groups")]
+pub async fn create_group(
+    payload: web::Json<GroupPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageGroups)?;
+    let mut group = payload.into_inner().into_group(0);
+    group.spl_total_lamports =
+        (group.spl_share_percent * app_state.spl_token.balance as f64).round() as u64;
+
+    group
+        .validate()
+        .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+    check_share_available(&app_state, &group).await?;
+
+    group.id = app_state.db.create_group(&group).await.map_err(|e| {
+        log::error!("Failed to create group: {}", e);
+        InternalError::new(
+            "Failed to create group. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    let version = save_version(&app_state, &group, "create", &user).await?;
+    let group = fetch_group(&app_state, group.id).await?;
+
+    log::info!("Group {} created by `{}`", group.id, user.username);
+
+    Ok(HttpResponse::Created().json(GroupChangeResponse {
+        group,
+        version: Some(version),
+        dry_run: false,
+        schedules: ScheduleDiff::default(),
+    }))
+}
+
+/// Changes a group and re-plans its schedules. Held for approval if the policy asks for it,
+/// after checking that the change applies.
+#[put("/groups/{group_id}")]
+pub async fn update_group(
+    path: web::Path<i64>,
+    query: web::Query<GroupUpdateQuery>,
+    payload: web::Json<GroupPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageGroups)?;
+    let group_id = path.into_inner();
+    let payload = payload.into_inner();
+
+    if !query.dry_run
+        && app_state
+            .approval_policy
+            .applies_to(ApprovalAction::UpdateGroup)
+    {
+        let preview = change_group(&app_state, group_id, payload.clone(), true, &user).await?;
+        let approval = request_approval(
+            &app_state,
+            &user,
+            ApprovalAction::UpdateGroup,
+            preview.group.spl_total_lamports,
+            json!({ "group_id": group_id, "group": payload }),
+        )
+        .await?;
+        if let Some(approval) = approval {
+            return Ok(HttpResponse::Accepted().json(approval));
+        }
+    }
+
+    let response = change_group(&app_state, group_id, payload, query.dry_run, &user).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[delete("/groups/{group_id}")]
+pub async fn delete_group(
+    path: web::Path<i64>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageGroups)?;
+    let group_id = path.into_inner();
+    let group = fetch_group(&app_state, group_id).await?;
+    check_no_buyers(&app_state, group_id).await?;
+
+    let approval = request_approval(
+        &app_state,
+        &user,
+        ApprovalAction::DeleteGroup,
+        group.spl_total_lamports,
+        json!({ "group_id": group_id }),
+    )
+    .await?;
+    if let Some(approval) = approval {
+        return Ok(HttpResponse::Accepted().json(approval));
+    }
+
+    Ok(HttpResponse::Ok().json(remove_group(&app_state, &group, &user).await?))
+}
+
+/// Validates and saves a change of a group, or only previews it with `dry_run`.
+/// `user` is the author of the new version.
+pub(super) async fn change_group(
+    app_state: &AppState,
+    group_id: i64,
+    payload: GroupPayload,
+    dry_run: bool,
+    user: &User,
+) -> Result<GroupChangeResponse, Error> {
+    let current = fetch_group(app_state, group_id).await?;
+
+    let mut group = payload.into_group(group_id);
+    group.spl_total_lamports = if group.spl_share_percent == current.spl_share_percent {
+        current.spl_total_lamports
+    } else {
+        (group.spl_share_percent * app_state.spl_token.balance as f64).round() as u64
+    };
+
+    group
+        .validate()
+        .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+    check_share_available(app_state, &group).await?;
+
+    if dry_run {
+        // The new price changes every buyer's allocation, so the group must still cover them all
+        let buyers = app_state
+            .db
+            .get_buyers_by_group(group_id)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to get buyers of group {}: {}", group_id, e);
+                InternalError::new(
+                    "Failed to get group buyers. Please try again later.",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?;
+        let total_allocation: u64 = buyers.iter().map(|b| buyer_allocation(&group, b)).sum();
+        if total_allocation > group.spl_total_lamports {
+            return Err(over_allocated(&group, total_allocation));
+        }
+        let schedules = if group.vesting_changed(&current) {
+            preview_group_schedules(app_state, &group)
+                .await
+                .map_err(|e| {
+                    log::error!("Failed to re-plan schedules of group {}: {}", group_id, e);
+                    InternalError::new(
+                        "Failed to re-plan group schedules. Please try again later.",
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                })?
+        } else {
+            ScheduleDiff::default()
+        };
+        return Ok(GroupChangeResponse {
+            group,
+            version: None,
+            dry_run,
+            schedules,
+        });
+    }
+
+    // The group and its buyers are locked while the allocations are checked, the group is
+    // saved and the schedules are re-planned, so a buyer added meanwhile isn't missed
+    let now = Utc::now().naive_utc();
+    let update = app_state
+        .db
+        .update_group(
+            &group,
+            Some(&user.username),
+            |buyer| buyer_allocation(&group, buyer),
+            group.vesting_changed(&current).then(|| replan(&group, now)),
+        )
+        .await
+        .map_err(|e| {
+            log::error!("Failed to update group {}: {}", group_id, e);
+            InternalError::new(
+                "Failed to update group. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    let (group, version, schedules) = match update {
+        GroupUpdate::Updated {
+            group,
+            version,
+            removed,
+            created,
+        } => (group, version, ScheduleDiff { removed, created }),
+        GroupUpdate::OverAllocated { allocation } => {
+            return Err(over_allocated(&group, allocation));
+        }
+    };
+
+    log::info!(
+        "Group {} updated by `{}` to version {}: {} schedules removed, {} created",
+        group_id,
+        user.username,
+        version,
+        schedules.removed.len(),
+        schedules.created.len()
+    );
+
+    Ok(GroupChangeResponse {
+        group,
+        version: Some(version),
+        dry_run,
+        schedules,
+    })
+}
+
+/// Rejects a change that leaves the group fewer tokens than its buyers are owed.
+fn over_allocated(group: &Group, allocation: u64) -> Error {
+    InternalError::new(
+        format!(
+            "Group {} would not have enough SPL tokens: spl_total_lamports = {}, buyers allocation = {}",
+            group.id, group.spl_total_lamports, allocation
+        ),
+        StatusCode::BAD_REQUEST,
+    )
+    .into()
+}
+
+/// Deletes a group without buyers; `user` is the author of the last version.
+pub(super) async fn remove_group(
+    app_state: &AppState,
+    group: &Group,
+    user: &User,
+) -> Result<serde_json::Value, Error> {
+    app_state.db.delete_group(group.id).await.map_err(|e| {
+        log::error!("Failed to delete group {}: {}", group.id, e);
+        InternalError::new(
+            "Failed to delete group. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    let version = save_version(app_state, group, "delete", user).await?;
+
+    log::info!("Group {} deleted by `{}`", group.id, user.username);
+
+    Ok(json!({
+        "deleted": group.id,
+        "version": version,
+    }))
+}
+
+/// Rejects deleting a group that still has buyers.
+pub(super) async fn check_no_buyers(app_state: &AppState, group_id: i64) -> Result<(), Error> {
+    let buyers = app_state
+        .db
+        .get_buyers_by_group(group_id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get buyers of group {}: {}", group_id, e);
+            InternalError::new(
+                "Failed to get group buyers. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    if !buyers.is_empty() {
+        return Err(InternalError::new(
+            format!(
+                "Group {} still has {} buyers and can't be deleted.",
+                group_id,
+                buyers.len()
+            ),
+            StatusCode::CONFLICT,
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub(super) async fn fetch_group(app_state: &AppState, group_id: i64) -> Result<Group, Error> {
+    let maybe_group = app_state.db.get_group(group_id).await.map_err(|e| {
+        log::error!("Database error fetching group {}: {}", group_id, e);
+        InternalError::new(
+            "Internal server error while fetching group.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    maybe_group.ok_or_else(|| {
+        log::warn!("Group not found: {}", group_id);
+        InternalError::new("Group with provided ID not found.", StatusCode::NOT_FOUND).into()
+    })
+}
+
+/// Rejects the change if the shares of all groups would exceed the whole treasury.
+async fn check_share_available(app_state: &AppState, group: &Group) -> Result<(), Error> {
+    let groups = app_state.db.get_all_groups().await.map_err(|e| {
+        log::error!("Failed to get groups: {}", e);
+        InternalError::new(
+            "Failed to fetch groups. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    let total_share: f64 = groups
+        .iter()
+        .filter(|g| g.id != group.id)
+        .map(|g| g.spl_share_percent)
+        .sum::<f64>()
+        + group.spl_share_percent;
+    if total_share > 1.0 + f64::EPSILON {
+        return Err(InternalError::new(
+            format!(
+                "Total spl_share_percent of all groups would be {:.4}, which exceeds 1.0",
+                total_share
+            ),
+            StatusCode::BAD_REQUEST,
+        )
+        .into());
+    }
+    Ok(())
+}
+
+async fn save_version(
+    app_state: &AppState,
+    group: &Group,
+    change_type: &str,
+    user: &User,
+) -> Result<i64, Error> {
+    app_state
+        .db
+        .save_group_version(group, change_type, Some(&user.username))
+        .await
+        .map_err(|e| {
+            log::error!("Failed to save version of group {}: {}", group.id, e);
+            InternalError::new(
+                "Failed to save group version. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into()
+        })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct GroupPayload {
+    spl_share_percent: f64,
+    spl_price_lamports: u64,
+    initial_unlock_percent: f64,
+    unlock_interval_seconds: i64,
+    unlock_percent_per_interval: f64,
+}
+
+impl GroupPayload {
+    fn into_group(self, id: i64) -> Group {
+        Group {
+            id,
+            spl_share_percent: self.spl_share_percent,
+            spl_total_lamports: 0,
+            spl_price_lamports: self.spl_price_lamports,
+            initial_unlock_percent: self.initial_unlock_percent,
+            unlock_interval_seconds: self.unlock_interval_seconds,
+            unlock_percent_per_interval: self.unlock_percent_per_interval,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupUpdateQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub(super) struct GroupChangeResponse {
+    group: Group,
+    version: Option<i64>,
+    dry_run: bool,
+    schedules: ScheduleDiff,
+}
@@ -0,0 +1,177 @@
+use std::time::Instant;
+
+use actix_web::{HttpResponse, Responder, get, web};
+use chrono::Utc;
+use common::SplToken;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{distribution::RUNNER_STALL_SECONDS, state::AppState};
+
+/// RPC calls slower than this mark the service as degraded.
+const RPC_SLOW_MS: u128 = 2000;
+
+#[get("/healthz")]
+pub async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+#[get("/readyz")]
+pub async fn readyz(app_state: web::Data<AppState>) -> impl Responder {
+    let (database, rpc, mint, treasury, runner, retry_queue) = tokio::join!(
+        check_database(&app_state),
+        check_rpc(&app_state),
+        check_mint(&app_state),
+        check_treasury(&app_state),
+        check_runner(&app_state),
+        check_retry_queue(&app_state),
+    );
+    let checks = vec![database, rpc, mint, treasury, runner, retry_queue];
+    let status = checks
+        .iter()
+        .map(|c| c.status)
+        .max()
+        .unwrap_or(CheckStatus::Ok);
+
+    let response = ReadinessResponse { status, checks };
+    match status {
+        CheckStatus::Failing => HttpResponse::ServiceUnavailable().json(response),
+        _ => HttpResponse::Ok().json(response),
+    }
+}
+
+async fn check_database(app_state: &AppState) -> HealthCheck {
+    let started = Instant::now();
+    match app_state.db.ping().await {
+        Ok(()) => HealthCheck::ok("database").latency(started),
+        Err(e) => HealthCheck::failing("database", format!("{:#}", e)).latency(started),
+    }
+}
+
+async fn check_rpc(app_state: &AppState) -> HealthCheck {
+    let started = Instant::now();
+    match app_state.spl_token.client.get_latest_blockhash().await {
+        Ok(_) if started.elapsed().as_millis() > RPC_SLOW_MS => {
+            HealthCheck::degraded("rpc", "RPC responds slowly").latency(started)
+        }
+        Ok(_) => HealthCheck::ok("rpc").latency(started),
+        Err(e) => {
+            app_state.metrics.rpc_error("get_latest_blockhash");
+            HealthCheck::failing("rpc", e.to_string()).latency(started)
+        }
+    }
+}
+
+async fn check_mint(app_state: &AppState) -> HealthCheck {
+    let started = Instant::now();
+    match app_state
+        .spl_token
+        .client
+        .get_account(&app_state.spl_token.mint)
+        .await
+    {
+        Ok(_) => HealthCheck::ok("mint").latency(started),
+        Err(e) => {
+            HealthCheck::failing("mint", format!("Mint account not found: {}", e)).latency(started)
+        }
+    }
+}
+
+async fn check_treasury(app_state: &AppState) -> HealthCheck {
+    let started = Instant::now();
+    let spl_token = &app_state.spl_token;
+    match SplToken::get_token_account_balance(&spl_token.client, &spl_token.token_account).await {
+        Ok(0) => {
+            HealthCheck::degraded("treasury", "Treasury token account is empty").latency(started)
+        }
+        Ok(_) => HealthCheck::ok("treasury").latency(started),
+        Err(e) => HealthCheck::failing("treasury", format!("{:#}", e)).latency(started),
+    }
+}
+
+async fn check_runner(app_state: &AppState) -> HealthCheck {
+    let status = app_state.runner.lock().await.clone();
+    let Some(last_heartbeat) = status.last_heartbeat else {
+        return HealthCheck::degraded("runner", "Schedule runner hasn't started yet");
+    };
+
+    let age = (Utc::now().naive_utc() - last_heartbeat).num_seconds();
+    if age > RUNNER_STALL_SECONDS {
+        HealthCheck::failing(
+            "runner",
+            format!("Schedule runner made no progress for {} seconds", age),
+        )
+    } else if status.paused {
+        HealthCheck::degraded("runner", "Schedule runner is paused")
+    } else {
+        HealthCheck::ok("runner")
+    }
+}
+
+async fn check_retry_queue(app_state: &AppState) -> HealthCheck {
+    let depth = app_state.retry_queue.ops.lock().await.len();
+    if depth > 0 {
+        HealthCheck::degraded(
+            "retry_queue",
+            format!("{} database operations are waiting to be retried", depth),
+        )
+    } else {
+        HealthCheck::ok("retry_queue")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Ok,
+    Degraded,
+    Failing,
+}
+
+#[derive(Debug, Serialize)]
+struct HealthCheck {
+    name: &'static str,
+    status: CheckStatus,
+    latency_ms: Option<u128>,
+    message: Option<String>,
+}
+
+impl HealthCheck {
+    fn ok(name: &'static str) -> Self {
+        HealthCheck {
+            name,
+            status: CheckStatus::Ok,
+            latency_ms: None,
+            message: None,
+        }
+    }
+
+    fn degraded(name: &'static str, message: impl Into<String>) -> Self {
+        HealthCheck {
+            name,
+            status: CheckStatus::Degraded,
+            latency_ms: None,
+            message: Some(message.into()),
+        }
+    }
+
+    fn failing(name: &'static str, message: impl Into<String>) -> Self {
+        HealthCheck {
+            name,
+            status: CheckStatus::Failing,
+            latency_ms: None,
+            message: Some(message.into()),
+        }
+    }
+
+    fn latency(mut self, started: Instant) -> Self {
+        self.latency_ms = Some(started.elapsed().as_millis());
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ReadinessResponse {
+    status: CheckStatus,
+    checks: Vec<HealthCheck>,
+}
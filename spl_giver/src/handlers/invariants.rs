@@ -0,0 +1,33 @@
+use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, web};
+use common::{Permission, User};
+
+use super::require;
+use crate::state::AppState;
+
+/// Checks the consistency of buyers, schedules, transactions and the ledger, and lists the
+/// violations by wallet and by group.
+#[get("/invariants")]
+pub async fn get_invariants(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+
+    let report = app_state.db.check_invariants().await.map_err(|e| {
+        log::error!("Failed to check invariants: {:#}", e);
+        InternalError::new(
+            "Failed to check invariants. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    if !report.is_consistent() {
+        log::warn!(
+            "Invariant check found {} violations in {} wallets and {} groups",
+            report.violations,
+            report.wallets.len(),
+            report.groups.len()
+        );
+    }
+
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,48 @@
+use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, web};
+use common::{LedgerFilter, Movement, PageQuery, Permission, User};
+
+use super::require;
+use crate::state::AppState;
+
+/// Token movements, filtered by buyer, group or kind of movement.
+#[get("/ledger")]
+pub async fn get_ledger(
+    filter: web::Query<LedgerFilter>,
+    page: web::Query<PageQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    if let Some(movement) = filter
+        .movement
+        .as_deref()
+        .filter(|m| Movement::parse(m).is_none())
+    {
+        return Err(InternalError::new(
+            format!(
+                "Unknown movement '{}'. Use {}.",
+                movement,
+                Movement::ALL.map(|m| m.as_str()).join(", ")
+            ),
+            StatusCode::BAD_REQUEST,
+        )
+        .into());
+    }
+    let cursor = page
+        .validate()
+        .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+
+    let entries = app_state
+        .db
+        .list_ledger(&filter, &page, cursor.as_ref())
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get ledger: {}", e);
+            InternalError::new(
+                "Failed to get ledger. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
@@ -0,0 +1,25 @@
+use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, web};
+use common::{Permission, User};
+
+use super::require;
+use crate::state::AppState;
+
+/// Prometheus metrics, scraped with an API key that can read.
+#[get("/metrics")]
+pub async fn get_metrics(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let body = app_state.metrics.render(&app_state).await.map_err(|e| {
+        log::error!("Failed to render metrics: {:#}", e);
+        InternalError::new(
+            "Failed to collect metrics.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
@@ -1,17 +1,73 @@
+mod adjustments;
+mod api_keys;
+mod approvals;
+mod audit;
 mod auth;
 mod buyers;
+mod events;
+mod exports;
 mod groups;
+mod health;
+mod invariants;
+mod ledger;
+mod metrics;
+mod runner;
 mod schedule;
+mod sessions;
+mod stats;
+mod totp;
 mod transactions;
+mod uploads;
+mod users;
+mod webhooks;
 
-use actix_web::{HttpResponse, Responder, get};
+use actix_web::{Error, HttpResponse, Responder, error::InternalError, get, http::StatusCode};
+pub use adjustments::*;
+pub use api_keys::*;
+pub use approvals::*;
+pub use audit::*;
 pub use auth::*;
 pub use buyers::*;
+use common::{Permission, User};
+pub use events::*;
+pub use exports::*;
 pub use groups::*;
+pub use health::*;
+pub use invariants::*;
+pub use ledger::*;
+pub use metrics::*;
+pub use runner::*;
 pub use schedule::*;
+pub use sessions::*;
+pub use stats::*;
+pub use totp::*;
 pub use transactions::*;
+pub use uploads::*;
+pub use users::*;
+pub use webhooks::*;
 
 #[get("/")]
 pub async fn index() -> impl Responder {
     HttpResponse::Ok().body("Welcome to Spl Token Service!")
 }
+
+/// Rejects the request unless the user's role has `permission`.
+fn require(user: &User, permission: Permission) -> Result<(), Error> {
+    if user.can(permission) {
+        return Ok(());
+    }
+    log::warn!(
+        "User `{}` with role `{}` was denied {:?}",
+        user.username,
+        user.role,
+        permission
+    );
+    Err(InternalError::new(
+        format!(
+            "Your role `{}` doesn't allow this action.",
+            user.role().as_str()
+        ),
+        StatusCode::FORBIDDEN,
+    )
+    .into())
+}
@@ -0,0 +1,52 @@
+use actix_web::{Error, HttpResponse, get, post, web};
+use common::{Permission, User};
+use serde_json::json;
+
+use super::require;
+use crate::state::AppState;
+
+#[get("/runner/status")]
+pub async fn get_runner_status(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let status = app_state.runner.lock().await.clone();
+    Ok(HttpResponse::Ok().json(status))
+}
+
+#[post("/runner/pause")]
+pub async fn pause_runner(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ControlRunner)?;
+    let status = {
+        let mut status = app_state.runner.lock().await;
+        status.paused = true;
+        status.clone()
+    };
+    log::warn!("Schedule runner paused by `{}`", user.username);
+    app_state
+        .events
+        .runner("paused", json!({ "by": user.username }));
+    Ok(HttpResponse::Ok().json(status))
+}
+
+#[post("/runner/resume")]
+pub async fn resume_runner(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ControlRunner)?;
+    let status = {
+        let mut status = app_state.runner.lock().await;
+        status.paused = false;
+        status.clone()
+    };
+    log::warn!("Schedule runner resumed by `{}`", user.username);
+    app_state
+        .events
+        .runner("resumed", json!({ "by": user.username }));
+    Ok(HttpResponse::Ok().json(status))
+}
@@ -1,56 +1,62 @@
+use std::str::FromStr;
+
 use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, post, web};
+use common::{ApprovalAction, PageQuery, Permission, Schedule, ScheduleFilter, Transaction, User};
 use serde::Deserialize;
 use serde_json::json;
+use solana_sdk::signature::Signature;
 
+use super::approvals::request_approval;
+use super::require;
 use crate::{distribution::process_schedule, state::AppState};
-#[derive(Debug, Deserialize)]
-struct ScheduleQuery {
-    #[serde(default)]
-    status: Option<String>,
-}
 
 #[get("/schedule")]
 pub async fn get_schedule(
-    query: web::Query<ScheduleQuery>,
+    filter: web::Query<ScheduleFilter>,
+    page: web::Query<PageQuery>,
+    user: User,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
     // Validate status if provided
-    if let Some(ref status) = query.status {
-        let valid_statuses = ["pending", "success", "failed"];
+    if let Some(ref status) = filter.status {
+        let valid_statuses = ["pending", "processing", "success", "failed", "interrupted"];
         if !valid_statuses.contains(&status.as_str()) {
             return Err(InternalError::new(
-                "Schedule status must be either 'pending', 'success', or 'failed'.",
+                "Schedule status must be either 'pending', 'processing', 'success', 'failed', \
+                or 'interrupted'.",
                 StatusCode::BAD_REQUEST,
             )
             .into());
         }
     }
+    let cursor = page
+        .validate()
+        .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
 
-    // Fetch data based on presence of status
-    let schedule_result = match query.status.as_deref() {
-        Some("pending") | Some("success") | Some("failed") => {
-            app_state
-                .db
-                .get_schedules_by_status(query.status.as_ref().unwrap())
-                .await
-        }
-        None => app_state.db.get_all_schedules().await,
-        _ => unreachable!(),
-    };
-
-    let schedules = schedule_result.map_err(|e| {
-        log::error!("Failed to get schedule: {}", e);
-        InternalError::new(
-            "Failed to get schedule. Please try again later.",
-            StatusCode::INTERNAL_SERVER_ERROR,
-        )
-    })?;
+    let schedules = app_state
+        .db
+        .list_schedules(&filter, &page, cursor.as_ref())
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get schedule: {}", e);
+            InternalError::new(
+                "Failed to get schedule. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
 
     Ok(HttpResponse::Ok().json(schedules))
 }
 
+/// Resends all failed schedules. Held for approval if the policy asks for it; the approved
+/// request retries the same schedules, if they are still failed.
 #[post("/schedule/retry")]
-pub async fn retry_failed_schedule(app_state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+pub async fn retry_failed_schedule(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::SendTokens)?;
     let schedules = app_state
         .db
         .get_schedules_by_status("failed")
@@ -63,19 +69,60 @@ pub async fn retry_failed_schedule(app_state: web::Data<AppState>) -> Result<Htt
             )
         })?;
 
+    if !schedules.is_empty() {
+        let schedule_ids: Vec<i64> = schedules.iter().map(|s| s.id).collect();
+        let approval = request_approval(
+            &app_state,
+            &user,
+            ApprovalAction::RetrySchedules,
+            schedules.iter().map(|s| s.amount_lamports).sum(),
+            json!({ "schedule_ids": schedule_ids }),
+        )
+        .await?;
+        if let Some(approval) = approval {
+            return Ok(HttpResponse::Accepted().json(approval));
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(retry_schedules(&app_state, schedules).await))
+}
+
+/// Sends the schedules right away and reports which were retried and which failed again.
+pub(super) async fn retry_schedules(
+    app_state: &AppState,
+    schedules: Vec<Schedule>,
+) -> serde_json::Value {
     if schedules.is_empty() {
-        return Ok(HttpResponse::Ok().json(json!({
+        return json!({
             "retried": [],
             "failed": [],
             "message": "Nothing to retry — all schedules already processed successfully."
-        })));
+        });
     }
 
     let mut retried = Vec::new();
     let mut failed = Vec::new();
 
     for schedule in schedules {
-        match process_schedule(&app_state, &schedule, app_state.spl_token.decimals).await {
+        match app_state.db.claim_schedule(schedule.id, "failed").await {
+            Ok(true) => {}
+            Ok(false) => {
+                failed.push(FailedRetry {
+                    schedule_id: schedule.id,
+                    error: "Schedule is no longer failed".to_string(),
+                });
+                continue;
+            }
+            Err(e) => {
+                log::error!("Failed to claim schedule {}: {}", schedule.id, e);
+                failed.push(FailedRetry {
+                    schedule_id: schedule.id,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        }
+        match process_schedule(app_state, &schedule, app_state.spl_token.decimals).await {
             Ok(updated) => retried.push(updated),
             Err(e) => {
                 log::error!("Failed to retry schedule {}: {}", schedule.id, e);
@@ -87,7 +134,7 @@ pub async fn retry_failed_schedule(app_state: web::Data<AppState>) -> Result<Htt
         }
     }
 
-    Ok(HttpResponse::Ok().json(json!({
+    json!({
         "retried": retried,
         "failed": failed,
         "message": format!(
@@ -95,7 +142,115 @@ pub async fn retry_failed_schedule(app_state: web::Data<AppState>) -> Result<Htt
             retried.len(),
             failed.len()
         )
-    })))
+    })
+}
+
+/// Settles a schedule that was interrupted while it was being sent, after it was checked on
+/// chain. With the signature of its transfer it is recorded as sent, without one it is
+/// marked failed, so it can be retried.
+#[post("/schedule/{id}/resolve")]
+pub async fn resolve_interrupted_schedule(
+    path: web::Path<i64>,
+    payload: web::Json<ResolvePayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::SendTokens)?;
+    let schedule_id = path.into_inner();
+    let signature = payload.into_inner().signature()?;
+
+    let db_error = |e: anyhow::Error| {
+        log::error!("Failed to resolve schedule {}: {}", schedule_id, e);
+        InternalError::new(
+            "Failed to resolve schedule. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    };
+    let schedule = app_state
+        .db
+        .get_schedule_by_id(schedule_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| InternalError::new("Schedule not found.", StatusCode::NOT_FOUND))?;
+    if !app_state
+        .db
+        .claim_schedule(schedule.id, "interrupted")
+        .await
+        .map_err(db_error)?
+    {
+        return Err(
+            InternalError::new("Schedule is not interrupted.", StatusCode::CONFLICT).into(),
+        );
+    }
+
+    let updated = match signature {
+        Some(signature) => {
+            let mut tx_record = Transaction::new(
+                schedule.buyer_wallet.clone(),
+                schedule.group_id,
+                schedule.amount_lamports,
+                schedule.percent,
+                "success".to_string(),
+            );
+            tx_record.schedule_id = Some(schedule.id);
+            tx_record.signature = Some(signature.to_string());
+            app_state
+                .db
+                .save_transaction(tx_record)
+                .await
+                .map_err(db_error)?;
+            app_state
+                .db
+                .record_unlock(&schedule)
+                .await
+                .map_err(db_error)?;
+            app_state
+                .db
+                .update_schedule_status(schedule.id, "success", None)
+                .await
+                .map_err(db_error)?
+        }
+        None => app_state
+            .db
+            .update_schedule_status(
+                schedule.id,
+                "failed",
+                Some(format!(
+                    "Not sent, checked on chain by `{}`.",
+                    user.username
+                )),
+            )
+            .await
+            .map_err(db_error)?,
+    };
+    log::info!(
+        "Interrupted schedule id={} resolved as {} by `{}`",
+        schedule.id,
+        updated.status,
+        user.username
+    );
+
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+/// Outcome of an interrupted transfer, as found on chain.
+#[derive(Debug, Deserialize)]
+pub(super) struct ResolvePayload {
+    /// Signature of the transfer if it reached the chain.
+    signature: Option<String>,
+}
+
+impl ResolvePayload {
+    pub(super) fn signature(self) -> Result<Option<Signature>, Error> {
+        self.signature
+            .map(|signature| {
+                Signature::from_str(&signature).map_err(|_| {
+                    InternalError::new("Invalid transaction signature.", StatusCode::BAD_REQUEST)
+                        .into()
+                })
+            })
+            .transpose()
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -0,0 +1,144 @@
+use actix_web::{Error, HttpResponse, delete, error::InternalError, get, http::StatusCode, web};
+use common::{Permission, Session, User};
+use serde::Serialize;
+use serde_json::json;
+
+use super::require;
+use crate::state::AppState;
+
+/// Active sessions of the logged in user.
+#[get("/me/sessions")]
+pub async fn get_own_sessions(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let sessions = app_state
+        .db
+        .get_active_sessions(user.id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get sessions of `{}`: {}", user.username, e);
+            InternalError::new(
+                "Failed to fetch sessions. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    let sessions: Vec<SessionView> = sessions
+        .into_iter()
+        .map(|session| SessionView {
+            current: user.session_id.as_deref() == Some(session.id.as_str()),
+            session,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+/// Ends one of the logged in user's sessions, e.g. a login on a lost device.
+#[delete("/me/sessions/{session_id}")]
+pub async fn revoke_own_session(
+    path: web::Path<String>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let session_id = path.into_inner();
+    let session = app_state.db.get_session(&session_id).await.map_err(|e| {
+        log::error!("Failed to get session {}: {}", session_id, e);
+        InternalError::new(
+            "Failed to fetch session. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    // Sessions of other users are reported as missing
+    if !session.is_some_and(|s| s.user_id == user.id && s.is_active()) {
+        return Err(InternalError::new("Session not found.", StatusCode::NOT_FOUND).into());
+    }
+
+    revoke_session(&app_state, &session_id).await?;
+    log::info!("User `{}` revoked session {}", user.username, session_id);
+
+    Ok(HttpResponse::Ok().json(json!({ "revoked": session_id })))
+}
+
+/// Logs a user out everywhere.
+#[delete("/users/{username}/sessions")]
+pub async fn revoke_user_sessions(
+    path: web::Path<String>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let username = path.into_inner();
+    let target = app_state
+        .db
+        .get_user(&username)
+        .await
+        .map_err(|e| {
+            log::error!("Database error fetching user `{}`: {}", username, e);
+            InternalError::new(
+                "Internal server error while fetching user.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?
+        .ok_or_else(|| {
+            InternalError::new(
+                "User with provided username not found.",
+                StatusCode::NOT_FOUND,
+            )
+        })?;
+
+    let revoked = revoke_sessions(&app_state, &target, None).await?;
+    log::info!(
+        "{} sessions of `{}` revoked by `{}`",
+        revoked,
+        username,
+        user.username
+    );
+
+    Ok(HttpResponse::Ok().json(json!({ "username": username, "revoked": revoked })))
+}
+
+pub(super) async fn revoke_session(app_state: &AppState, session_id: &str) -> Result<(), Error> {
+    app_state
+        .db
+        .revoke_session(session_id)
+        .await
+        .map(|_| ())
+        .map_err(|e| {
+            log::error!("Failed to revoke session {}: {}", session_id, e);
+            InternalError::new(
+                "Failed to revoke session. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into()
+        })
+}
+
+/// Ends all sessions of `user` except `keep`. Their access tokens stop working once they
+/// expire, as they can't be refreshed anymore.
+pub(super) async fn revoke_sessions(
+    app_state: &AppState,
+    user: &User,
+    keep: Option<&str>,
+) -> Result<u64, Error> {
+    app_state
+        .db
+        .revoke_user_sessions(user.id, keep)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to revoke sessions of `{}`: {}", user.username, e);
+            InternalError::new(
+                "Failed to revoke sessions. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into()
+        })
+}
+
+#[derive(Debug, Serialize)]
+struct SessionView {
+    #[serde(flatten)]
+    session: Session,
+    /// The session of this request.
+    current: bool,
+}
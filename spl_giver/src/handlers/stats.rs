@@ -0,0 +1,125 @@
+use super::groups::fetch_group;
+use super::require;
+use crate::state::AppState;
+use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, web};
+use chrono::{Duration, Utc};
+use common::{DistributionStats, Permission, SplToken, StatsBucket, StatsWindow, User};
+use serde::{Deserialize, Serialize};
+
+/// Longest range of upcoming unlocks that can be requested.
+const MAX_STATS_DAYS: i64 = 365;
+
+#[get("/stats")]
+pub async fn get_stats(
+    query: web::Query<StatsQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let stats = load_stats(&app_state, None, &query).await?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+#[get("/groups/{group_id}/stats")]
+pub async fn get_group_stats(
+    path: web::Path<i64>,
+    query: web::Query<StatsQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let group = fetch_group(&app_state, path.into_inner()).await?;
+    let stats = load_stats(&app_state, Some(group.id), &query).await?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+async fn load_stats(
+    app_state: &AppState,
+    group_id: Option<i64>,
+    query: &StatsQuery,
+) -> Result<StatsResponse, Error> {
+    if !(1..=MAX_STATS_DAYS).contains(&query.days) {
+        return Err(InternalError::new(
+            format!("days must be in range [1, {}]", MAX_STATS_DAYS),
+            StatusCode::BAD_REQUEST,
+        )
+        .into());
+    }
+
+    let now = Utc::now().naive_utc();
+    let window = StatsWindow {
+        now,
+        until: now + Duration::days(query.days),
+        bucket: query.bucket,
+    };
+    let stats = app_state
+        .db
+        .get_distribution_stats(group_id, window)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get distribution stats: {:#}", e);
+            InternalError::new(
+                "Failed to get stats. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    // The treasury is shared by all groups, so for a group it shows if the whole balance covers it
+    let treasury = match SplToken::get_token_account_balance(
+        &app_state.spl_token.client,
+        &app_state.spl_token.token_account,
+    )
+    .await
+    {
+        Ok(balance) => Some(TreasuryCoverage::new(
+            balance,
+            stats.tokens.outstanding_lamports,
+        )),
+        Err(e) => {
+            log::error!("Failed to get treasury balance: {:#}", e);
+            None
+        }
+    };
+
+    Ok(StatsResponse { stats, treasury })
+}
+
+#[derive(Debug, Deserialize)]
+struct StatsQuery {
+    #[serde(default)]
+    bucket: StatsBucket,
+    #[serde(default = "default_stats_days")]
+    days: i64,
+}
+
+fn default_stats_days() -> i64 {
+    30
+}
+
+#[derive(Debug, Serialize)]
+struct StatsResponse {
+    #[serde(flatten)]
+    stats: DistributionStats,
+    treasury: Option<TreasuryCoverage>,
+}
+
+#[derive(Debug, Serialize)]
+struct TreasuryCoverage {
+    balance_lamports: u64,
+    outstanding_lamports: u64,
+    /// `balance / outstanding`, `null` when nothing is outstanding
+    coverage: Option<f64>,
+    shortfall_lamports: u64,
+}
+
+impl TreasuryCoverage {
+    fn new(balance_lamports: u64, outstanding_lamports: u64) -> Self {
+        TreasuryCoverage {
+            balance_lamports,
+            outstanding_lamports,
+            coverage: (outstanding_lamports > 0)
+                .then(|| balance_lamports as f64 / outstanding_lamports as f64),
+            shortfall_lamports: outstanding_lamports.saturating_sub(balance_lamports),
+        }
+    }
+}
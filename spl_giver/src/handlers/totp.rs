@@ -0,0 +1,286 @@
+use actix_web::{
+    Error, HttpResponse, delete, error::InternalError, get, http::StatusCode, post, web,
+};
+use chrono::Utc;
+use common::{Permission, Totp, User, UserTotp, generate_recovery_codes, hash_recovery_code};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::require;
+use crate::state::AppState;
+
+/// Second factor status of the logged in user.
+#[get("/me/totp")]
+pub async fn get_own_totp(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let totp = fetch_totp(&app_state, &user).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "enabled": totp.enabled,
+        "pending": totp.is_pending(),
+        "recovery_codes_left": totp.recovery_codes_left,
+        "required": user.is_superuser,
+    })))
+}
+
+/// Starts TOTP enrollment with a new secret, which is enabled by `/me/totp/confirm`.
+#[post("/me/totp")]
+pub async fn start_totp_enrollment(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if fetch_totp(&app_state, &user).await?.enabled {
+        return Err(InternalError::new(
+            "Two-factor authentication is already enabled.",
+            StatusCode::CONFLICT,
+        )
+        .into());
+    }
+
+    let secret = Totp::generate();
+    save_totp(&app_state, &user, Some(secret.secret())).await?;
+    log::info!("User `{}` started TOTP enrollment", user.username);
+
+    Ok(HttpResponse::Ok().json(TotpEnrollment {
+        otpauth_uri: secret.uri(&user.username),
+        secret: secret.secret().to_string(),
+    }))
+}
+
+/// Enables TOTP with a first code from the authenticator app and returns the recovery codes.
+#[post("/me/totp/confirm")]
+pub async fn confirm_totp_enrollment(
+    payload: web::Json<TotpCodePayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let totp = fetch_totp(&app_state, &user).await?;
+    if !totp.is_pending() {
+        return Err(InternalError::new(
+            "No two-factor enrollment is in progress.",
+            StatusCode::CONFLICT,
+        )
+        .into());
+    }
+
+    verify_code(&app_state, &user, &totp, &payload.code).await?;
+    let recovery_codes = issue_recovery_codes(&app_state, &user).await?;
+    log::info!("User `{}` enabled TOTP", user.username);
+
+    Ok(HttpResponse::Ok().json(json!({ "recovery_codes": recovery_codes })))
+}
+
+/// Replaces the recovery codes, e.g. when most are used up.
+#[post("/me/totp/recovery-codes")]
+pub async fn regenerate_recovery_codes(
+    payload: web::Json<TotpCodePayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let totp = enabled_totp(&app_state, &user).await?;
+    verify_code(&app_state, &user, &totp, &payload.code).await?;
+    let recovery_codes = issue_recovery_codes(&app_state, &user).await?;
+    log::info!("User `{}` regenerated recovery codes", user.username);
+
+    Ok(HttpResponse::Ok().json(json!({ "recovery_codes": recovery_codes })))
+}
+
+/// Turns off TOTP of the logged in user. Superusers must keep it.
+#[delete("/me/totp")]
+pub async fn disable_own_totp(
+    payload: web::Json<TotpCodePayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if user.is_superuser {
+        return Err(InternalError::new(
+            "Two-factor authentication is required for superusers.",
+            StatusCode::BAD_REQUEST,
+        )
+        .into());
+    }
+    let totp = enabled_totp(&app_state, &user).await?;
+    verify_code(&app_state, &user, &totp, &payload.code).await?;
+    save_totp(&app_state, &user, None).await?;
+    log::info!("User `{}` disabled TOTP", user.username);
+
+    Ok(HttpResponse::Ok().json(json!({ "enabled": false })))
+}
+
+/// Enables TOTP for a user with a new secret and returns it with the recovery codes, to be
+/// handed to the user. Superusers can't log in before this, they never get a secret at login.
+#[post("/users/{username}/totp")]
+pub async fn enroll_user_totp(
+    path: web::Path<String>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let username = path.into_inner();
+    let target = fetch_user(&app_state, &username).await?;
+    if fetch_totp(&app_state, &target).await?.enabled {
+        return Err(InternalError::new(
+            "User already has a second factor. Reset it first.",
+            StatusCode::CONFLICT,
+        )
+        .into());
+    }
+
+    let secret = Totp::generate();
+    save_totp(&app_state, &target, Some(secret.secret())).await?;
+    let recovery_codes = issue_recovery_codes(&app_state, &target).await?;
+    log::info!("TOTP of `{}` enrolled by `{}`", username, user.username);
+
+    Ok(HttpResponse::Ok().json(json!({
+        "secret": secret.secret(),
+        "otpauth_uri": secret.uri(&target.username),
+        "recovery_codes": recovery_codes,
+    })))
+}
+
+/// Removes the second factor of a user that lost their authenticator and recovery codes.
+/// Superusers can't log in until they are enrolled again with `/users/{username}/totp`.
+#[delete("/users/{username}/totp")]
+pub async fn reset_user_totp(
+    path: web::Path<String>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let username = path.into_inner();
+    let target = fetch_user(&app_state, &username).await?;
+
+    save_totp(&app_state, &target, None).await?;
+    log::info!("TOTP of `{}` reset by `{}`", username, user.username);
+
+    Ok(HttpResponse::Ok().json(json!({ "username": username, "enabled": false })))
+}
+
+/// Creates new recovery codes, completing the enrollment if it is pending. The codes are
+/// only stored hashed, so they are returned to be shown once.
+async fn issue_recovery_codes(app_state: &AppState, user: &User) -> Result<Vec<String>, Error> {
+    let codes = generate_recovery_codes();
+    let hashes: Vec<String> = codes.iter().map(|c| hash_recovery_code(c)).collect();
+    app_state
+        .db
+        .replace_recovery_codes(user.id, &hashes)
+        .await
+        .map_err(|e| {
+            log::error!(
+                "Failed to save recovery codes of `{}`: {}",
+                user.username,
+                e
+            );
+            InternalError::new(
+                "Failed to save recovery codes. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    Ok(codes)
+}
+
+async fn fetch_user(app_state: &AppState, username: &str) -> Result<User, Error> {
+    app_state
+        .db
+        .get_user(username)
+        .await
+        .map_err(|e| {
+            log::error!("Database error fetching user `{}`: {}", username, e);
+            InternalError::new(
+                "Internal server error while fetching user.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?
+        .ok_or_else(|| {
+            InternalError::new(
+                "User with provided username not found.",
+                StatusCode::NOT_FOUND,
+            )
+            .into()
+        })
+}
+
+async fn fetch_totp(app_state: &AppState, user: &User) -> Result<UserTotp, Error> {
+    app_state.db.get_user_totp(user.id).await.map_err(|e| {
+        log::error!("Failed to get TOTP of `{}`: {}", user.username, e);
+        InternalError::new(
+            "Failed to fetch two-factor status. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into()
+    })
+}
+
+async fn enabled_totp(app_state: &AppState, user: &User) -> Result<UserTotp, Error> {
+    let totp = fetch_totp(app_state, user).await?;
+    if !totp.enabled {
+        return Err(InternalError::new(
+            "Two-factor authentication is not enabled.",
+            StatusCode::CONFLICT,
+        )
+        .into());
+    }
+    Ok(totp)
+}
+
+async fn save_totp(app_state: &AppState, user: &User, secret: Option<&str>) -> Result<(), Error> {
+    app_state
+        .db
+        .set_user_totp(user.id, secret, false)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to update TOTP of `{}`: {}", user.username, e);
+            InternalError::new(
+                "Failed to update two-factor authentication. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into()
+        })
+}
+
+/// Checks a code of the user's authenticator; each code is accepted once.
+async fn verify_code(
+    app_state: &AppState,
+    user: &User,
+    totp: &UserTotp,
+    code: &str,
+) -> Result<(), Error> {
+    let step = totp
+        .secret
+        .as_deref()
+        .and_then(|secret| Totp::from_secret(secret).ok())
+        .and_then(|secret| secret.verify(code, Utc::now().timestamp()));
+    let verified = match step {
+        Some(step) => app_state
+            .db
+            .use_totp_step(user.id, step)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to update TOTP step of `{}`: {}", user.username, e);
+                InternalError::new(
+                    "Failed to check code. Please try again later.",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?,
+        None => false,
+    };
+    if !verified {
+        log::warn!("Invalid TOTP code from user `{}`", user.username);
+        return Err(
+            InternalError::new("Invalid authentication code.", StatusCode::BAD_REQUEST).into(),
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct TotpEnrollment {
+    secret: String,
+    otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TotpCodePayload {
+    code: String,
+}
@@ -1,20 +1,19 @@
 use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, web};
-use serde::Deserialize;
+use common::{PageQuery, Permission, TransactionFilter, User};
 
+use super::require;
 use crate::state::AppState;
-#[derive(Debug, Deserialize)]
-struct TransactionQuery {
-    #[serde(default)]
-    status: Option<String>,
-}
 
 #[get("/transactions")]
 pub async fn get_transactions(
-    query: web::Query<TransactionQuery>,
+    filter: web::Query<TransactionFilter>,
+    page: web::Query<PageQuery>,
+    user: User,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
     // Validate `status` if provided
-    if let Some(ref status) = query.status {
+    if let Some(ref status) = filter.status {
         let valid_statuses = ["success", "failed"];
         if !valid_statuses.contains(&status.as_str()) {
             return Err(InternalError::new(
@@ -24,26 +23,21 @@ pub async fn get_transactions(
             .into());
         }
     }
+    let cursor = page
+        .validate()
+        .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
 
-    // Fetch transactions depending on status filter
-    let transactions_result = match query.status.as_deref() {
-        Some("success") | Some("failed") => {
-            app_state
-                .db
-                .get_transactions_by_status(query.status.as_ref().unwrap())
-                .await
-        }
-        None => app_state.db.get_all_transactions().await,
-        _ => unreachable!(),
-    };
-
-    let transactions = transactions_result.map_err(|e| {
-        log::error!("Failed to get transactions: {}", e);
-        InternalError::new(
-            "Failed to get transactions. Please try again later.",
-            StatusCode::INTERNAL_SERVER_ERROR,
-        )
-    })?;
+    let transactions = app_state
+        .db
+        .list_transactions(&filter, &page, cursor.as_ref())
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get transactions: {}", e);
+            InternalError::new(
+                "Failed to get transactions. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
 
     Ok(HttpResponse::Ok().json(transactions))
 }
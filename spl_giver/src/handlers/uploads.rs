@@ -0,0 +1,609 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use super::approvals::request_approval;
+use super::buyers::{add_buyer, change_buyer, check_removable, fetch_buyer, remove_buyer};
+use super::require;
+use crate::distribution::{
+    buyer_allocation, group_allocation, initialize_buyer_schedules, plan_buyer_schedules,
+};
+use crate::state::AppState;
+use actix_multipart::form::{MultipartForm, tempfile::TempFile};
+use actix_web::{Error, HttpResponse, error::InternalError, get, http::StatusCode, post, web};
+use chrono::Utc;
+use common::{
+    Approval, ApprovalAction, Buyer, BuyerUpload, BuyersReport, FileFormat, Group, Permission,
+    RowIssue, RowIssueReason, Schedule, SyncDiff, User,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+#[post("/buyers/upload")]
+pub async fn upload_buyers(
+    MultipartForm(form): MultipartForm<BuyersUploadForm>,
+    query: web::Query<UploadQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageBuyers)?;
+    let filename = form.file.file_name.clone().unwrap_or_default();
+    let format = match FileFormat::from_path(&filename) {
+        Ok(format @ (FileFormat::Csv | FileFormat::Json | FileFormat::Ndjson)) => format,
+        _ => {
+            return Err(InternalError::new(
+                "Only CSV, JSON and NDJSON files allowed",
+                StatusCode::BAD_REQUEST,
+            )
+            .into());
+        }
+    };
+
+    let content = tokio::fs::read(form.file.file.path()).await.map_err(|e| {
+        log::error!("Failed to read uploaded file `{}`: {}", filename, e);
+        InternalError::new("Failed to read uploaded file", StatusCode::BAD_REQUEST)
+    })?;
+
+    // The same file can only be uploaded once
+    let sha256 = format!("{:x}", Sha256::digest(&content));
+    let previous = app_state
+        .db
+        .get_buyer_upload_by_sha256(&sha256)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to check upload history: {}", e);
+            InternalError::new(
+                "Failed to check upload history. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    if let Some(previous) = previous {
+        return Err(InternalError::new(
+            format!(
+                "This file was already uploaded by `{}` at {} (upload id {}, status `{}`).",
+                previous.uploaded_by.as_deref().unwrap_or("-"),
+                previous
+                    .created_at
+                    .map(|t| t.to_string())
+                    .unwrap_or_default(),
+                previous.id,
+                previous.status
+            ),
+            StatusCode::CONFLICT,
+        )
+        .into());
+    }
+
+    let content = String::from_utf8(content).map_err(|e| {
+        InternalError::new(
+            format!("Uploaded file is not valid UTF-8: {}", e),
+            StatusCode::BAD_REQUEST,
+        )
+    })?;
+
+    let groups = app_state.db.get_all_groups().await.map_err(|e| {
+        log::error!("Failed to fetch groups: {}", e);
+        InternalError::new(
+            "Failed to fetch groups from database",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    let report = Buyer::parse_report(&content, format, &groups)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to parse uploaded buyers file: {}", e);
+            InternalError::new(
+                format!("Failed to parse file: {}", e),
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+
+    let upload = BuyerUpload::new(
+        sha256,
+        Some(filename),
+        Some(user.username.clone()),
+        query.mode.as_str(),
+        "applied",
+    );
+
+    match query.mode {
+        UploadMode::Append => {
+            append_buyers(&app_state, report, &groups, upload, query.dry_run).await
+        }
+        UploadMode::Sync => sync_buyers(&app_state, report, &groups, upload, query.dry_run).await,
+    }
+}
+
+#[get("/uploads")]
+pub async fn get_buyer_uploads(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let uploads = app_state.db.get_all_buyer_uploads().await.map_err(|e| {
+        log::error!("Failed to get buyer uploads: {}", e);
+        InternalError::new(
+            "Failed to get uploads. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    Ok(HttpResponse::Ok().json(uploads))
+}
+
+#[get("/uploads/{upload_id}")]
+pub async fn get_buyer_upload(
+    path: web::Path<i64>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::Read)?;
+    let upload = fetch_upload(&app_state, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(upload))
+}
+
+/// Applies the diff of a pending sync upload: removals first to free group capacity,
+/// then changed amounts, then additions. Removals needing approval are held like
+/// `DELETE /buyers/{wallet}`. Changes that can't be applied are reported.
+#[post("/uploads/{upload_id}/confirm")]
+pub async fn confirm_buyer_upload(
+    path: web::Path<i64>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageBuyers)?;
+    let upload_id = path.into_inner();
+    let upload = fetch_upload(&app_state, upload_id).await?;
+    let not_pending = || {
+        InternalError::new(
+            format!("Upload {} is not a pending sync upload.", upload_id),
+            StatusCode::CONFLICT,
+        )
+    };
+    if upload.mode != "sync" || upload.status != "pending" {
+        return Err(not_pending().into());
+    }
+
+    let diff: SyncDiff =
+        serde_json::from_str(upload.changes.as_deref().unwrap_or("{}")).map_err(|e| {
+            log::error!("Invalid changes stored for upload {}: {}", upload_id, e);
+            InternalError::new(
+                "Stored upload changes are invalid.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    // Two confirmations of the same upload must not both apply it
+    let claimed = app_state
+        .db
+        .claim_buyer_upload(upload_id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to claim upload {}: {}", upload_id, e);
+            InternalError::new(
+                "Failed to confirm upload. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    if !claimed {
+        return Err(not_pending().into());
+    }
+
+    let mut removed = Vec::new();
+    let mut held = Vec::new();
+    let mut updated = Vec::new();
+    let mut added = Vec::new();
+    let mut failed = Vec::new();
+
+    for buyer in &diff.removals {
+        let wallet = buyer.wallet.to_string();
+        match remove_or_hold(&app_state, &user, &wallet).await {
+            Ok(None) => removed.push(wallet),
+            Ok(Some(approval)) => held.push(HeldRemoval {
+                wallet,
+                approval_id: approval.id,
+            }),
+            Err(e) => failed.push(FailedChange {
+                wallet,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    for change in &diff.changes {
+        let result = match fetch_buyer(&app_state, &change.wallet).await {
+            Ok(current) => {
+                change_buyer(
+                    &app_state,
+                    &current,
+                    change.new_paid_lamports,
+                    change.new_group_id,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok((buyer, _)) => updated.push(buyer),
+            Err(e) => failed.push(FailedChange {
+                wallet: change.wallet.clone(),
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    for buyer in &diff.additions {
+        match add_buyer(&app_state, buyer.clone()).await {
+            Ok((buyer, _)) => added.push(buyer),
+            Err(e) => failed.push(FailedChange {
+                wallet: buyer.wallet.to_string(),
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    let summary = json!({
+        "confirmed_by": user.username,
+        "removed": &removed,
+        "awaiting_approval": &held,
+        "updated": updated.iter().map(|b| b.wallet.to_string()).collect::<Vec<_>>(),
+        "added": added.iter().map(|b| b.wallet.to_string()).collect::<Vec<_>>(),
+        "failed": &failed,
+    });
+    let upload = app_state
+        .db
+        .update_buyer_upload_status(upload_id, "applied", Some(summary.to_string()))
+        .await
+        .map_err(|e| {
+            log::error!("Failed to update upload {}: {}", upload_id, e);
+            InternalError::new(
+                "Changes applied, but failed to update upload status.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    log::info!(
+        "Sync upload {} confirmed by `{}`: {} removed, {} awaiting approval, {} updated, {} added, {} failed",
+        upload_id,
+        user.username,
+        removed.len(),
+        held.len(),
+        updated.len(),
+        added.len(),
+        failed.len()
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "upload": upload,
+        "removed": removed,
+        "awaiting_approval": held,
+        "updated": updated,
+        "added": added,
+        "failed": failed,
+    })))
+}
+
+/// Removes a buyer of a sync upload, or holds the removal for approval. Returns the
+/// approval request if it is held.
+async fn remove_or_hold(
+    app_state: &AppState,
+    user: &User,
+    wallet: &str,
+) -> Result<Option<Approval>, Error> {
+    let buyer = fetch_buyer(app_state, wallet).await?;
+    check_removable(app_state, &buyer).await?;
+    // Revokes the unsent allocation of the buyer
+    let approval = request_approval(
+        app_state,
+        user,
+        ApprovalAction::DeleteBuyer,
+        buyer.pending_spl_lamports,
+        json!({ "wallet": wallet }),
+    )
+    .await?;
+    if approval.is_none() {
+        remove_buyer(app_state, &buyer).await?;
+    }
+    Ok(approval)
+}
+
+/// Imports new buyers of the file, existing wallets are skipped.
+async fn append_buyers(
+    app_state: &AppState,
+    report: BuyersReport,
+    groups: &[Group],
+    mut upload: BuyerUpload,
+    dry_run: bool,
+) -> Result<HttpResponse, Error> {
+    let db_error = |e: anyhow::Error| {
+        log::error!("Database error while uploading buyers: {}", e);
+        InternalError::new(
+            "Database error while uploading buyers. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    };
+
+    let mut rejected = report.issues;
+    let mut imported = Vec::new();
+    let mut skipped = Vec::new();
+    let mut schedules_map = HashMap::new();
+    // Allocation already taken in each group. Rows of the file are added on top in file order
+    let mut allocated: HashMap<i64, u64> = HashMap::new();
+
+    for (row, buyer) in report.buyers {
+        let wallet = buyer.wallet.to_string();
+        if let Some(existing) = app_state
+            .db
+            .get_buyer_by_wallet(&wallet)
+            .await
+            .map_err(db_error)?
+        {
+            skipped.push(existing);
+            continue;
+        }
+
+        // Rows with unknown groups are already rejected by the report
+        let Some(group) = groups.iter().find(|g| g.id == buyer.group_id) else {
+            continue;
+        };
+        let group_allocated = match allocated.entry(group.id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                group_allocation(app_state, group, None)
+                    .await
+                    .map_err(db_error)?,
+            ),
+        };
+        let requested = buyer_allocation(group, &buyer);
+        if *group_allocated + requested > group.spl_total_lamports {
+            rejected.push(RowIssue {
+                row,
+                wallet: Some(wallet),
+                reason: RowIssueReason::OverCap,
+                message: format!(
+                    "Group {} does not have enough SPL tokens: spl_total_lamports = {}, allocated = {}, requested = {}",
+                    group.id, group.spl_total_lamports, group_allocated, requested
+                ),
+            });
+            continue;
+        }
+        *group_allocated += requested;
+
+        if dry_run {
+            let planned = plan_buyer_schedules(group, &buyer, &[], Utc::now().naive_utc());
+            schedules_map.insert(wallet, planned);
+            imported.push(buyer);
+            continue;
+        }
+
+        if !app_state.db.save_buyer(&buyer).await.map_err(db_error)? {
+            // inserted by someone else since the check above
+            if let Some(existing) = app_state
+                .db
+                .get_buyer_by_wallet(&wallet)
+                .await
+                .map_err(db_error)?
+            {
+                skipped.push(existing);
+            }
+            continue;
+        }
+        let created = initialize_buyer_schedules(app_state, group, &buyer)
+            .await
+            .map_err(db_error)?;
+        schedules_map.insert(wallet.clone(), created);
+        imported.push(fetch_buyer(app_state, &wallet).await?);
+    }
+
+    for buyer in &skipped {
+        let w = buyer.wallet.to_string();
+        let list = app_state
+            .db
+            .get_schedules_by_buyer_and_group(&w, buyer.group_id)
+            .await
+            .map_err(db_error)?;
+        schedules_map.insert(w, list);
+    }
+    rejected.sort_by_key(|issue| issue.row);
+
+    log::info!(
+        "Uploaded buyers file (dry_run={}). Imported {} buyers, skipped {} buyers, rejected {} rows",
+        dry_run,
+        imported.len(),
+        skipped.len(),
+        rejected.len()
+    );
+
+    let mut upload_id = None;
+    if !dry_run {
+        upload.summary = Some(
+            json!({
+                "imported": imported.len(),
+                "skipped": skipped.len(),
+                "rejected": rejected.len(),
+            })
+            .to_string(),
+        );
+        upload_id = Some(
+            app_state
+                .db
+                .save_buyer_upload(&upload)
+                .await
+                .map_err(db_error)?,
+        );
+    }
+
+    let response = UploadBuyersResponse {
+        upload_id,
+        dry_run,
+        imported,
+        skipped,
+        rejected,
+        schedules: schedules_map,
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Treats the file as the full intended list of buyers and stores the differences with the
+/// database as a pending upload. Nothing changes until the upload is confirmed.
+async fn sync_buyers(
+    app_state: &AppState,
+    report: BuyersReport,
+    groups: &[Group],
+    mut upload: BuyerUpload,
+    dry_run: bool,
+) -> Result<HttpResponse, Error> {
+    let db_error = |e: anyhow::Error| {
+        log::error!("Database error while syncing buyers: {}", e);
+        InternalError::new(
+            "Database error while syncing buyers. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    };
+
+    // Each group must cover all rows of the file, since they replace the current buyers
+    let mut rejected = report.issues;
+    let mut allocated: HashMap<i64, u64> = HashMap::new();
+    let mut uploaded = Vec::with_capacity(report.buyers.len());
+    for (row, buyer) in report.buyers {
+        let Some(group) = groups.iter().find(|g| g.id == buyer.group_id) else {
+            continue;
+        };
+        let group_allocated = allocated.entry(group.id).or_default();
+        let requested = buyer_allocation(group, &buyer);
+        if *group_allocated + requested > group.spl_total_lamports {
+            rejected.push(RowIssue {
+                row,
+                wallet: Some(buyer.wallet.to_string()),
+                reason: RowIssueReason::OverCap,
+                message: format!(
+                    "Group {} does not have enough SPL tokens: spl_total_lamports = {}, allocated = {}, requested = {}",
+                    group.id, group.spl_total_lamports, group_allocated, requested
+                ),
+            });
+            continue;
+        }
+        *group_allocated += requested;
+        uploaded.push(buyer);
+    }
+
+    // A rejected row would otherwise show up as a removal of an existing buyer
+    if !rejected.is_empty() {
+        rejected.sort_by_key(|issue| issue.row);
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "message": "Sync requires a file without rejected rows. Nothing was changed.",
+            "rejected": rejected,
+        })));
+    }
+
+    let stored = app_state.db.get_all_buyers().await.map_err(db_error)?;
+    let diff = SyncDiff::between(&stored, &uploaded);
+
+    let mut upload_id = None;
+    if !dry_run {
+        if !diff.is_empty() {
+            upload.status = "pending".to_string();
+            upload.changes = Some(serde_json::to_string(&diff).map_err(|e| {
+                log::error!("Failed to serialize sync diff: {}", e);
+                InternalError::new(
+                    "Failed to save sync changes.",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            })?);
+        }
+        upload_id = Some(
+            app_state
+                .db
+                .save_buyer_upload(&upload)
+                .await
+                .map_err(db_error)?,
+        );
+    }
+
+    log::info!(
+        "Sync upload (dry_run={}): {} additions, {} removals, {} changes",
+        dry_run,
+        diff.additions.len(),
+        diff.removals.len(),
+        diff.changes.len()
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "upload_id": upload_id,
+        "dry_run": dry_run,
+        "status": upload.status,
+        "changes": diff,
+    })))
+}
+
+async fn fetch_upload(app_state: &AppState, upload_id: i64) -> Result<BuyerUpload, Error> {
+    let maybe_upload = app_state
+        .db
+        .get_buyer_upload(upload_id)
+        .await
+        .map_err(|e| {
+            log::error!("Database error fetching upload {}: {}", upload_id, e);
+            InternalError::new(
+                "Internal server error while fetching upload.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    maybe_upload.ok_or_else(|| {
+        InternalError::new("Upload with provided ID not found.", StatusCode::NOT_FOUND).into()
+    })
+}
+
+#[derive(Debug, MultipartForm)]
+struct BuyersUploadForm {
+    #[multipart(limit = "10MB")]
+    file: TempFile,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum UploadMode {
+    #[default]
+    Append,
+    Sync,
+}
+
+impl UploadMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            UploadMode::Append => "append",
+            UploadMode::Sync => "sync",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadQuery {
+    #[serde(default)]
+    mode: UploadMode,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(serde::Serialize)]
+struct UploadBuyersResponse {
+    upload_id: Option<i64>,
+    dry_run: bool,
+    imported: Vec<Buyer>,
+    skipped: Vec<Buyer>,
+    rejected: Vec<RowIssue>,
+    schedules: HashMap<String, Vec<Schedule>>,
+}
+
+#[derive(serde::Serialize)]
+struct HeldRemoval {
+    wallet: String,
+    approval_id: i64,
+}
+
+#[derive(serde::Serialize)]
+struct FailedChange {
+    wallet: String,
+    error: String,
+}
@@ -0,0 +1,377 @@
+use actix_web::{
+    Error, HttpResponse, delete, error::InternalError, get, http::StatusCode, post, put, web,
+};
+use common::{Permission, Role, User};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{require, sessions::revoke_sessions};
+use crate::state::AppState;
+
+#[get("/users")]
+pub async fn get_users(user: User, app_state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let users = app_state.db.get_all_users().await.map_err(|e| {
+        log::error!("Failed to get users: {}", e);
+        InternalError::new(
+            "Failed to fetch users. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    Ok(HttpResponse::Ok().json(users))
+}
+
+#[post("/users")]
+pub async fn create_user(
+    payload: web::Json<NewUserPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let payload = payload.into_inner();
+    let new_user = User::with_role(
+        &payload.username,
+        &payload.email,
+        &payload.password,
+        payload.role,
+    )
+    .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+
+    if find_user(&app_state, &new_user.username).await?.is_some() {
+        return Err(InternalError::new(
+            format!(
+                "A user with username '{}' already exists.",
+                new_user.username
+            ),
+            StatusCode::CONFLICT,
+        )
+        .into());
+    }
+
+    app_state.db.save_user(&new_user).await.map_err(|e| {
+        log::error!("Failed to create user `{}`: {}", new_user.username, e);
+        InternalError::new(
+            "Failed to create user. Is the email already used?",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    let created = fetch_user(&app_state, &new_user.username).await?;
+
+    log::info!(
+        "User `{}` with role `{}` created by `{}`",
+        created.username,
+        created.role,
+        user.username
+    );
+
+    Ok(HttpResponse::Created().json(created))
+}
+
+#[put("/users/{username}/email")]
+pub async fn update_user_email(
+    path: web::Path<String>,
+    payload: web::Json<EmailPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let mut target = fetch_user(&app_state, &path.into_inner()).await?;
+    target
+        .set_email(&payload.email)
+        .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+
+    app_state
+        .db
+        .update_user_email(&target.username, &target.email)
+        .await
+        .map_err(|e| {
+            log::error!(
+                "Failed to update email of user `{}`: {}",
+                target.username,
+                e
+            );
+            InternalError::new(
+                "Failed to update email. Is it already used?",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    log::info!(
+        "Email of user `{}` changed by `{}`",
+        target.username,
+        user.username
+    );
+
+    Ok(HttpResponse::Ok().json(fetch_user(&app_state, &target.username).await?))
+}
+
+/// Sets a new password for another user, e.g. when they forgot theirs.
+#[put("/users/{username}/password")]
+pub async fn reset_user_password(
+    path: web::Path<String>,
+    payload: web::Json<PasswordPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let mut target = fetch_user(&app_state, &path.into_inner()).await?;
+    save_password(&app_state, &mut target, &payload.password, None).await?;
+
+    log::info!(
+        "Password of user `{}` reset by `{}`",
+        target.username,
+        user.username
+    );
+
+    Ok(HttpResponse::Ok().json(json!({ "username": target.username })))
+}
+
+#[put("/users/{username}/role")]
+pub async fn update_user_role(
+    path: web::Path<String>,
+    payload: web::Json<RolePayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let username = path.into_inner();
+    let role = payload.role;
+
+    // An admin removing their own role could leave nobody able to manage users
+    if username == user.username && role != Role::Admin {
+        return Err(InternalError::new(
+            "You can't remove the admin role from yourself.",
+            StatusCode::BAD_REQUEST,
+        )
+        .into());
+    }
+    let target = fetch_user(&app_state, &username).await?;
+
+    app_state
+        .db
+        .update_user_role(&username, role)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to update role of user `{}`: {}", username, e);
+            InternalError::new(
+                "Failed to update user role. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    // Other logins end, so they start over with the new role
+    let keep = (target.id == user.id)
+        .then_some(user.session_id.as_deref())
+        .flatten();
+    revoke_sessions(&app_state, &target, keep).await?;
+
+    log::info!(
+        "Role of user `{}` set to `{}` by `{}`",
+        username,
+        role.as_str(),
+        user.username
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "username": username,
+        "role": role,
+        "permissions": role.permissions(),
+    })))
+}
+
+#[post("/users/{username}/deactivate")]
+pub async fn deactivate_user(
+    path: web::Path<String>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    set_active(path.into_inner(), false, user, app_state).await
+}
+
+#[post("/users/{username}/activate")]
+pub async fn activate_user(
+    path: web::Path<String>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    set_active(path.into_inner(), true, user, app_state).await
+}
+
+#[delete("/users/{username}")]
+pub async fn delete_user(
+    path: web::Path<String>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    let username = path.into_inner();
+    if username == user.username {
+        return Err(
+            InternalError::new("You can't delete yourself.", StatusCode::BAD_REQUEST).into(),
+        );
+    }
+    fetch_user(&app_state, &username).await?;
+
+    app_state.db.delete_user(&username).await.map_err(|e| {
+        log::error!("Failed to delete user `{}`: {}", username, e);
+        InternalError::new(
+            "Failed to delete user. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    log::info!("User `{}` deleted by `{}`", username, user.username);
+
+    Ok(HttpResponse::Ok().json(json!({ "deleted": username })))
+}
+
+/// Self-service password change of the logged in user.
+#[put("/me/password")]
+pub async fn change_own_password(
+    payload: web::Json<ChangePasswordPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    // The token doesn't carry the password hash, so the current password is checked against the database
+    let mut current = fetch_user(&app_state, &user.username).await?;
+    if current.verify_password(&payload.current_password).is_err() {
+        return Err(
+            InternalError::new("Current password is incorrect.", StatusCode::BAD_REQUEST).into(),
+        );
+    }
+    // Other logins end, the one changing the password stays logged in
+    save_password(
+        &app_state,
+        &mut current,
+        &payload.new_password,
+        user.session_id.as_deref(),
+    )
+    .await?;
+
+    log::info!("User `{}` changed their password", user.username);
+
+    Ok(HttpResponse::Ok().json(json!({ "username": user.username })))
+}
+
+async fn set_active(
+    username: String,
+    active: bool,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageUsers)?;
+    if username == user.username && !active {
+        return Err(
+            InternalError::new("You can't deactivate yourself.", StatusCode::BAD_REQUEST).into(),
+        );
+    }
+    let target = fetch_user(&app_state, &username).await?;
+
+    app_state
+        .db
+        .set_user_active(&username, active)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to update user `{}`: {}", username, e);
+            InternalError::new(
+                "Failed to update user. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    if !active {
+        revoke_sessions(&app_state, &target, None).await?;
+    }
+
+    log::info!(
+        "User `{}` {} by `{}`",
+        username,
+        if active { "activated" } else { "deactivated" },
+        user.username
+    );
+
+    Ok(HttpResponse::Ok().json(fetch_user(&app_state, &username).await?))
+}
+
+/// Stores the new password and ends all sessions of the user except `keep_session`.
+async fn save_password(
+    app_state: &AppState,
+    user: &mut User,
+    password: &str,
+    keep_session: Option<&str>,
+) -> Result<(), Error> {
+    user.set_password(password)
+        .map_err(|e| InternalError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+    app_state
+        .db
+        .update_user_password(&user.username, &user.password_hash)
+        .await
+        .map_err(|e| {
+            log::error!(
+                "Failed to update password of user `{}`: {}",
+                user.username,
+                e
+            );
+            InternalError::new(
+                "Failed to update password. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    revoke_sessions(app_state, user, keep_session)
+        .await
+        .map(|_| ())
+}
+
+async fn find_user(app_state: &AppState, username: &str) -> Result<Option<User>, Error> {
+    app_state.db.get_user(username).await.map_err(|e| {
+        log::error!("Database error fetching user `{}`: {}", username, e);
+        InternalError::new(
+            "Internal server error while fetching user.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into()
+    })
+}
+
+async fn fetch_user(app_state: &AppState, username: &str) -> Result<User, Error> {
+    find_user(app_state, username).await?.ok_or_else(|| {
+        InternalError::new(
+            "User with provided username not found.",
+            StatusCode::NOT_FOUND,
+        )
+        .into()
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct NewUserPayload {
+    username: String,
+    email: String,
+    password: String,
+    #[serde(default = "default_role")]
+    role: Role,
+}
+
+fn default_role() -> Role {
+    Role::Viewer
+}
+
+#[derive(Debug, Deserialize)]
+struct EmailPayload {
+    email: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PasswordPayload {
+    password: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangePasswordPayload {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RolePayload {
+    role: Role,
+}
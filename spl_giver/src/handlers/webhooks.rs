@@ -0,0 +1,276 @@
+use actix_web::{
+    Error, HttpResponse, delete, error::InternalError, get, http::StatusCode, post, put, web,
+};
+use chrono::Utc;
+use common::{Permission, User, Webhook, WebhookEvent};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::require;
+use crate::state::AppState;
+
+/// Deliveries returned by `/webhooks/{id}/deliveries` unless `limit` is given.
+const DEFAULT_DELIVERIES_LIMIT: u32 = 100;
+const MAX_DELIVERIES_LIMIT: u32 = 1000;
+
+#[get("/webhooks")]
+pub async fn get_webhooks(
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageWebhooks)?;
+    let webhooks = app_state.db.get_all_webhooks().await.map_err(|e| {
+        log::error!("Failed to get webhooks: {}", e);
+        InternalError::new(
+            "Failed to fetch webhooks. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+#[post("/webhooks")]
+pub async fn create_webhook(
+    payload: web::Json<WebhookPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageWebhooks)?;
+    let payload = payload.into_inner();
+    payload.validate()?;
+
+    let secret = match payload.secret {
+        Some(secret) if !secret.is_empty() => secret,
+        _ => generate_secret(),
+    };
+    let events = WebhookEvent::join(&payload.events);
+
+    let webhook_id = app_state
+        .db
+        .save_webhook(&payload.url, &secret, &events, Some(&user.username))
+        .await
+        .map_err(|e| {
+            log::error!("Failed to create webhook: {}", e);
+            InternalError::new(
+                "Failed to create webhook. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    let webhook = fetch_webhook(&app_state, webhook_id).await?;
+
+    log::info!("Webhook {} created by `{}`", webhook_id, user.username);
+
+    // The secret is only shown once, it is needed to verify delivery signatures
+    Ok(HttpResponse::Created().json(CreatedWebhookResponse { webhook, secret }))
+}
+
+#[put("/webhooks/{webhook_id}")]
+pub async fn update_webhook(
+    path: web::Path<i64>,
+    payload: web::Json<WebhookPayload>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageWebhooks)?;
+    let webhook_id = path.into_inner();
+    let payload = payload.into_inner();
+    payload.validate()?;
+    fetch_webhook(&app_state, webhook_id).await?;
+
+    app_state
+        .db
+        .update_webhook(
+            webhook_id,
+            &payload.url,
+            &WebhookEvent::join(&payload.events),
+            payload.active,
+        )
+        .await
+        .map_err(|e| {
+            log::error!("Failed to update webhook {}: {}", webhook_id, e);
+            InternalError::new(
+                "Failed to update webhook. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    let webhook = fetch_webhook(&app_state, webhook_id).await?;
+
+    log::info!("Webhook {} updated by `{}`", webhook_id, user.username);
+
+    Ok(HttpResponse::Ok().json(webhook))
+}
+
+#[delete("/webhooks/{webhook_id}")]
+pub async fn delete_webhook(
+    path: web::Path<i64>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageWebhooks)?;
+    let webhook_id = path.into_inner();
+    fetch_webhook(&app_state, webhook_id).await?;
+
+    app_state.db.delete_webhook(webhook_id).await.map_err(|e| {
+        log::error!("Failed to delete webhook {}: {}", webhook_id, e);
+        InternalError::new(
+            "Failed to delete webhook. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    log::info!("Webhook {} deleted by `{}`", webhook_id, user.username);
+
+    Ok(HttpResponse::Ok().json(json!({ "deleted": webhook_id })))
+}
+
+#[get("/webhooks/{webhook_id}/deliveries")]
+pub async fn get_webhook_deliveries(
+    path: web::Path<i64>,
+    query: web::Query<DeliveriesQuery>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageWebhooks)?;
+    let webhook_id = path.into_inner();
+    let limit = query.limit.unwrap_or(DEFAULT_DELIVERIES_LIMIT);
+    if limit == 0 || limit > MAX_DELIVERIES_LIMIT {
+        return Err(InternalError::new(
+            format!("limit must be between 1 and {}", MAX_DELIVERIES_LIMIT),
+            StatusCode::BAD_REQUEST,
+        )
+        .into());
+    }
+    fetch_webhook(&app_state, webhook_id).await?;
+
+    let deliveries = app_state
+        .db
+        .get_webhook_deliveries(webhook_id, limit)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get deliveries of webhook {}: {}", webhook_id, e);
+            InternalError::new(
+                "Failed to fetch webhook deliveries. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+/// Queues a delivery again, whatever its current status, with a fresh retry budget.
+#[post("/webhooks/deliveries/{delivery_id}/replay")]
+pub async fn replay_webhook_delivery(
+    path: web::Path<i64>,
+    user: User,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require(&user, Permission::ManageWebhooks)?;
+    let delivery_id = path.into_inner();
+    let maybe_delivery = app_state
+        .db
+        .get_webhook_delivery(delivery_id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get webhook delivery {}: {}", delivery_id, e);
+            InternalError::new(
+                "Internal server error while fetching webhook delivery.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    let mut delivery = maybe_delivery.ok_or_else(|| {
+        InternalError::new(
+            "Webhook delivery with provided ID not found.",
+            StatusCode::NOT_FOUND,
+        )
+    })?;
+
+    delivery.status = "pending".to_string();
+    delivery.attempts = 0;
+    delivery.next_attempt_at = Utc::now().naive_utc();
+    app_state
+        .db
+        .update_webhook_delivery(&delivery)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to replay webhook delivery {}: {}", delivery_id, e);
+            InternalError::new(
+                "Failed to replay webhook delivery. Please try again later.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    log::info!(
+        "Webhook delivery {} replayed by `{}`",
+        delivery_id,
+        user.username
+    );
+
+    Ok(HttpResponse::Accepted().json(delivery))
+}
+
+async fn fetch_webhook(app_state: &AppState, webhook_id: i64) -> Result<Webhook, Error> {
+    let maybe_webhook = app_state.db.get_webhook(webhook_id).await.map_err(|e| {
+        log::error!("Database error fetching webhook {}: {}", webhook_id, e);
+        InternalError::new(
+            "Internal server error while fetching webhook.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    maybe_webhook.ok_or_else(|| {
+        InternalError::new("Webhook with provided ID not found.", StatusCode::NOT_FOUND).into()
+    })
+}
+
+/// 32 random bytes, hex encoded.
+fn generate_secret() -> String {
+    let bytes: [u8; 32] = rand::rng().random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookPayload {
+    url: String,
+    events: Vec<WebhookEvent>,
+    secret: Option<String>,
+    #[serde(default = "default_active")]
+    active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+impl WebhookPayload {
+    fn validate(&self) -> Result<(), Error> {
+        if !self.url.starts_with("https://") && !self.url.starts_with("http://") {
+            return Err(InternalError::new(
+                "url must start with http:// or https://",
+                StatusCode::BAD_REQUEST,
+            )
+            .into());
+        }
+        if self.events.is_empty() {
+            return Err(InternalError::new(
+                "events must contain at least one event type",
+                StatusCode::BAD_REQUEST,
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DeliveriesQuery {
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreatedWebhookResponse {
+    #[serde(flatten)]
+    webhook: Webhook,
+    secret: String,
+}
@@ -0,0 +1,321 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use actix_web::{
+    Error, HttpMessage, HttpRequest,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::StatusCode,
+    middleware::Next,
+    web,
+};
+use common::{ApiKey, User};
+use ed25519_compact::{PublicKey, SecretKey, Signature};
+use jwt_compact::{Algorithm, UntrustedToken, alg::Ed25519};
+
+use crate::state::AppState;
+
+/// Lifetime of the refresh token, and so of a login session.
+pub const SESSION_LIFETIME: Duration = Duration::from_secs(30 * 60);
+/// Cookie names set by the `TokenSigner`.
+pub const ACCESS_TOKEN_COOKIE: &str = "access_token";
+pub const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+
+/// `EdDSA` that accepts a token signed by any of several keys, so signing keys can be rotated
+/// without invalidating the tokens issued with the previous ones.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RotatingEd25519;
+
+impl Algorithm for RotatingEd25519 {
+    type SigningKey = SecretKey;
+    type VerifyingKey = Vec<PublicKey>;
+    type Signature = Signature;
+
+    fn name(&self) -> Cow<'static, str> {
+        Ed25519.name()
+    }
+
+    fn sign(&self, signing_key: &Self::SigningKey, message: &[u8]) -> Self::Signature {
+        Ed25519.sign(signing_key, message)
+    }
+
+    fn verify_signature(
+        &self,
+        signature: &Self::Signature,
+        verifying_key: &Self::VerifyingKey,
+        message: &[u8],
+    ) -> bool {
+        verifying_key
+            .iter()
+            .any(|key| Ed25519.verify_signature(signature, key, message))
+    }
+}
+
+/// Refresh authorizer: a new access token is only issued while the session of the refresh
+/// token is active and its user still exists and is active.
+pub async fn authorize_refresh(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> Result<(), Error> {
+    // The middleware checks the refresh token's signature after this returns,
+    // here only the session id is needed
+    let session_id = req
+        .cookie(REFRESH_TOKEN_COOKIE)
+        .and_then(|cookie| {
+            UntrustedToken::new(cookie.value())
+                .ok()?
+                .deserialize_claims_unchecked::<User>()
+                .ok()
+        })
+        .and_then(|claims| claims.custom.session_id);
+    let Some(session_id) = session_id else {
+        return Err(InternalError::new("Not logged in.", StatusCode::UNAUTHORIZED).into());
+    };
+    session_user(&app_state, &session_id).await?;
+
+    if let Err(e) = app_state.db.touch_session(&session_id).await {
+        log::error!("Failed to update session {}: {}", session_id, e);
+    }
+    Ok(())
+}
+
+/// Middleware behind the JWT checks. A token only works while its session is active, and
+/// the request acts as the user is stored now, not as the token says, so revoking a
+/// session, deactivating a user or changing their role takes effect on the next request.
+pub async fn check_session(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let claims = req.extensions().get::<User>().cloned();
+    let Some(claims) = claims else {
+        return Err(InternalError::new("Not logged in.", StatusCode::UNAUTHORIZED).into());
+    };
+
+    let user = match claims.session_id {
+        Some(ref session_id) => {
+            let Some(app_state) = req.app_data::<web::Data<AppState>>().cloned() else {
+                log::error!("Session check is missing the app state");
+                return Err(InternalError::new(
+                    "Authentication is not configured.",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+                .into());
+            };
+            let user = session_user(&app_state, session_id).await?;
+            if user.id != claims.id {
+                return Err(session_ended());
+            }
+            user
+        }
+        // Tokens without a session are minted per request for an API key, only
+        // accepted on the request `authenticate_api_keys` checked the key of
+        None => {
+            let key_owner = req.extensions().get::<ApiKey>().and_then(|k| k.user_id);
+            if key_owner != Some(claims.id) {
+                return Err(session_ended());
+            }
+            claims
+        }
+    };
+    req.extensions_mut().insert(user);
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_boxed_body)
+}
+
+/// Whether a request that was let in by `check_session` would still be, for responses that
+/// outlive their request like event streams. Requests with an API key have no session,
+/// their key must still be valid and allowed for its owner.
+pub async fn still_authenticated(
+    app_state: &AppState,
+    user: &User,
+    api_key: Option<&ApiKey>,
+) -> bool {
+    if let Some(session_id) = &user.session_id {
+        return session_user(app_state, session_id)
+            .await
+            .is_ok_and(|current| current.id == user.id);
+    }
+    let Some(api_key) = api_key else {
+        return false;
+    };
+    let current = match app_state.db.get_api_key(api_key.id).await {
+        Ok(Some(current)) if current.is_valid() => current,
+        Ok(_) => return false,
+        Err(e) => {
+            log::error!("Failed to check API key {}: {}", api_key.id, e);
+            return false;
+        }
+    };
+    let Some(owner_id) = current.user_id else {
+        return false;
+    };
+    match app_state.db.get_user_by_id(owner_id).await {
+        Ok(owner) => owner.is_some_and(|owner| current.allowed_for(&owner)),
+        Err(e) => {
+            log::error!("Failed to check owner of API key {}: {}", api_key.id, e);
+            false
+        }
+    }
+}
+
+/// Loads the user of an active session, with the session id set like in a token.
+async fn session_user(app_state: &AppState, session_id: &str) -> Result<User, Error> {
+    let db_error = |e: anyhow::Error| {
+        log::error!("Failed to check session {}: {}", session_id, e);
+        InternalError::new(
+            "Failed to check session. Please try again later.",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    };
+    let session = app_state
+        .db
+        .get_session(session_id)
+        .await
+        .map_err(db_error)?
+        .filter(|s| s.is_active())
+        .ok_or_else(session_ended)?;
+    let mut user = app_state
+        .db
+        .get_user_by_id(session.user_id)
+        .await
+        .map_err(db_error)?
+        .filter(|u| u.active)
+        .ok_or_else(session_ended)?;
+    user.session_id = Some(session.id);
+    Ok(user)
+}
+
+fn session_ended() -> Error {
+    InternalError::new(
+        "Session has ended, please log in again.",
+        StatusCode::UNAUTHORIZED,
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpResponse, middleware::from_fn, test};
+    use common::Role;
+
+    /// What the JWT middleware and `authenticate_api_keys` left in the extensions.
+    struct Authenticated {
+        claims: User,
+        api_key: Option<ApiKey>,
+    }
+
+    async fn authenticated(
+        req: ServiceRequest,
+        next: Next<impl MessageBody + 'static>,
+    ) -> Result<ServiceResponse<BoxBody>, Error> {
+        if let Some(auth) = req.app_data::<web::Data<Authenticated>>().cloned() {
+            req.extensions_mut().insert(auth.claims.clone());
+            if let Some(api_key) = &auth.api_key {
+                req.extensions_mut().insert(api_key.clone());
+            }
+        }
+        next.call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body)
+    }
+
+    async fn whoami(user: User) -> HttpResponse {
+        HttpResponse::Ok().body(user.username)
+    }
+
+    fn user(id: i64, role: Role) -> User {
+        User {
+            id,
+            username: format!("user{}", id),
+            email: format!("user{}@example.com", id),
+            password_hash: String::new(),
+            role: role.as_str().to_string(),
+            is_superuser: role == Role::Admin,
+            active: true,
+            session_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    async fn call(auth: Authenticated) -> (StatusCode, String) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(auth))
+                .wrap(from_fn(check_session))
+                .wrap(from_fn(authenticated))
+                .route("/", web::get().to(whoami)),
+        )
+        .await;
+        // Middleware errors come back as errors, not responses
+        match test::try_call_service(&app, test::TestRequest::get().to_request()).await {
+            Ok(resp) => {
+                let status = resp.status();
+                let body = test::read_body(resp).await;
+                (status, String::from_utf8_lossy(&body).into_owned())
+            }
+            Err(e) => (e.as_response_error().status_code(), e.to_string()),
+        }
+    }
+
+    #[actix_web::test]
+    async fn key_token_acts_as_the_key_user() {
+        let owner = user(7, Role::Admin);
+        let (api_key, _) = ApiKey::generate("job", Role::Operator, None, &owner).unwrap();
+        let claims = api_key.user(&owner);
+        let (status, body) = call(Authenticated {
+            claims,
+            api_key: Some(api_key),
+        })
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "api-key:job");
+    }
+
+    #[actix_web::test]
+    async fn token_without_session_needs_a_checked_key() {
+        let owner = user(7, Role::Admin);
+        let (api_key, _) = ApiKey::generate("job", Role::Operator, None, &owner).unwrap();
+        let claims = api_key.user(&owner);
+        let (status, _) = call(Authenticated {
+            claims,
+            api_key: None,
+        })
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn key_of_another_owner_is_rejected() {
+        let owner = user(7, Role::Admin);
+        let (api_key, _) = ApiKey::generate("job", Role::Viewer, None, &owner).unwrap();
+        let claims = user(8, Role::Admin);
+        let (status, _) = call(Authenticated {
+            claims,
+            api_key: Some(api_key),
+        })
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn missing_claims_are_rejected() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(check_session))
+                .route("/", web::get().to(whoami)),
+        )
+        .await;
+        let err = test::try_call_service(&app, test::TestRequest::get().to_request())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+/// Window of the per-IP and per-username attempt limits.
+const ATTEMPT_WINDOW: Duration = Duration::from_secs(60);
+/// Counters kept before the expired ones are dropped.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// Limits of login attempts, read from the environment.
+#[derive(Debug, Clone)]
+pub struct LoginPolicy {
+    /// Wrong passwords in a row before an account is locked.
+    pub max_failures: u32,
+    pub lockout: chrono::Duration,
+    /// Login attempts per minute from one IP address.
+    pub attempts_per_ip: u32,
+    /// Login attempts per minute for one username.
+    pub attempts_per_user: u32,
+}
+
+impl LoginPolicy {
+    /// `LOGIN_MAX_FAILURES` (5), `LOGIN_LOCKOUT_MINUTES` (15), `LOGIN_ATTEMPTS_PER_IP` (20)
+    /// and `LOGIN_ATTEMPTS_PER_USER` (10), defaults in brackets.
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(LoginPolicy {
+            max_failures: env_or("LOGIN_MAX_FAILURES", 5)?,
+            lockout: chrono::Duration::minutes(env_or("LOGIN_LOCKOUT_MINUTES", 15)?.into()),
+            attempts_per_ip: env_or("LOGIN_ATTEMPTS_PER_IP", 20)?,
+            attempts_per_user: env_or("LOGIN_ATTEMPTS_PER_USER", 10)?,
+        })
+    }
+}
+
+fn env_or(name: &str, default: u32) -> anyhow::Result<u32> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse::<u32>()
+            .ok()
+            .filter(|v| *v > 0)
+            .with_context(|| format!("{} must be a positive number", name)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// In-memory rate limit of login attempts per IP address and per username.
+/// Account lockout is stored in the database instead, so it holds across restarts.
+pub struct LoginGuard {
+    pub policy: LoginPolicy,
+    // key -> (start of the window, attempts in it)
+    attempts: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl LoginGuard {
+    pub fn new(policy: LoginPolicy) -> Self {
+        LoginGuard {
+            policy,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a login attempt. Returns the seconds to wait if the IP address or the
+    /// username is over its limit.
+    pub fn check(&self, ip_address: Option<&str>, username: &str) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().expect("login attempts lock poisoned");
+        if attempts.len() >= MAX_TRACKED_KEYS {
+            attempts.retain(|_, (started, _)| now.duration_since(*started) < ATTEMPT_WINDOW);
+        }
+
+        let mut keys = vec![(
+            format!("user:{}", username.to_lowercase()),
+            self.policy.attempts_per_user,
+        )];
+        if let Some(ip_address) = ip_address {
+            keys.push((format!("ip:{}", ip_address), self.policy.attempts_per_ip));
+        }
+
+        let mut retry_after = None;
+        for (key, limit) in keys {
+            let (started, count) = attempts.entry(key).or_insert((now, 0));
+            if now.duration_since(*started) >= ATTEMPT_WINDOW {
+                *started = now;
+                *count = 0;
+            }
+            *count += 1;
+            if *count > limit {
+                let wait = ATTEMPT_WINDOW.saturating_sub(now.duration_since(*started));
+                retry_after = retry_after.max(Some(wait.as_secs().max(1)));
+            }
+        }
+        retry_after.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(attempts_per_ip: u32, attempts_per_user: u32) -> LoginGuard {
+        LoginGuard::new(LoginPolicy {
+            max_failures: 5,
+            lockout: chrono::Duration::minutes(15),
+            attempts_per_ip,
+            attempts_per_user,
+        })
+    }
+
+    #[test]
+    fn username_limit_ignores_case() {
+        let guard = guard(100, 2);
+        assert!(guard.check(Some("203.0.113.7"), "alice").is_ok());
+        assert!(guard.check(Some("198.51.100.1"), "Alice").is_ok());
+        let wait = guard.check(Some("198.51.100.2"), "ALICE").unwrap_err();
+        assert!((1..=ATTEMPT_WINDOW.as_secs()).contains(&wait));
+        // Other users aren't affected
+        assert!(guard.check(Some("203.0.113.7"), "bob").is_ok());
+    }
+
+    #[test]
+    fn ip_limit_spans_usernames() {
+        let guard = guard(2, 100);
+        assert!(guard.check(Some("203.0.113.7"), "alice").is_ok());
+        assert!(guard.check(Some("203.0.113.7"), "bob").is_ok());
+        assert!(guard.check(Some("203.0.113.7"), "carol").is_err());
+        assert!(guard.check(Some("198.51.100.1"), "carol").is_ok());
+        // Without an address only the username counts
+        assert!(guard.check(None, "dave").is_ok());
+    }
+
+    #[test]
+    fn expired_window_starts_over() {
+        let guard = guard(100, 1);
+        assert!(guard.check(None, "alice").is_ok());
+        assert!(guard.check(None, "alice").is_err());
+        guard
+            .attempts
+            .lock()
+            .unwrap()
+            .get_mut("user:alice")
+            .unwrap()
+            .0 -= ATTEMPT_WINDOW;
+        assert!(guard.check(None, "alice").is_ok());
+    }
+}
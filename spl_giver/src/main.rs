@@ -1,22 +1,33 @@
+mod api_keys;
+mod audit;
+mod client_ip;
 mod config;
 mod distribution;
+mod events;
 mod handlers;
+mod jwt;
+mod login_guard;
+mod metrics;
 mod state;
+mod webhooks;
 
 use actix_jwt_auth_middleware::{Authority, TokenSigner, use_jwt::UseJWTOnApp};
-use actix_state_guards::UseStateGuardOnScope;
 use actix_web::{
-    App, HttpServer, error::InternalError, http::StatusCode, middleware::Logger, rt::System, web,
+    App, HttpServer,
+    middleware::{Logger, from_fn},
+    web,
 };
 use common::User;
 use dotenv::dotenv;
-use ed25519_compact::KeyPair;
-use jwt_compact::alg::Ed25519;
 use pretty_env_logger::env_logger::{Builder, Env};
 
-use distribution::{check_group_token_funding, initialize_schedules};
+use distribution::{
+    check_group_token_funding, initialize_schedules, recover_interrupted_schedules,
+    recover_interrupted_transfers,
+};
 
 use crate::config::AppConfig;
+use crate::jwt::RotatingEd25519;
 
 //DONE: Check transaction send some times
 //DONE: Create database with transations history
@@ -70,6 +81,16 @@ async fn main() -> std::io::Result<()> {
 
     log::info!("Admin ATA balance is OK");
 
+    // Schedules left in the middle of a transfer must be checked before they are sent again
+    recover_interrupted_schedules(&state).await.map_err(|e| {
+        log::error!("Failed to recover interrupted schedules: {:#}", e);
+        std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+    })?;
+    recover_interrupted_transfers(&state).await.map_err(|e| {
+        log::error!("Failed to recover interrupted transfers: {:#}", e);
+        std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+    })?;
+
     // Initialize schedules
     initialize_schedules(&state).await.map_err(|e| {
         log::error!("Failed to initialize schedules: {:#}", e);
@@ -79,67 +100,128 @@ async fn main() -> std::io::Result<()> {
 
     let data = web::Data::new(state);
 
-    // Spawn the schedule runner
+    // Spawn the schedule runner under a watchdog that restarts it
     {
         let runner_state = data.clone();
-        tokio::spawn(async move {
-            if let Err(e) = distribution::start_schedule_runner(runner_state).await {
-                log::error!("Schedule runner encountered an error: {:#}", e);
-                // Gracefully stop the Actix system
-                System::current().stop();
-            }
-        });
+        tokio::spawn(distribution::supervise_schedule_runner(runner_state));
+    }
+
+    // Spawn the webhook delivery worker
+    {
+        let webhook_state = data.clone();
+        tokio::spawn(webhooks::start_webhook_worker(webhook_state));
     }
 
     //Authorization
-    let KeyPair {
-        pk: public_key,
-        sk: secret_key,
-    } = KeyPair::generate();
+    let (secret_key, public_keys) = config.jwt_keys().map_err(|e| {
+        log::error!("Failed to load JWT keys: {:#}", e);
+        std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+    })?;
 
     HttpServer::new(move || {
-        let authority = Authority::<User, Ed25519, _, _>::new()
-            .refresh_authorizer(|| async move { Ok(()) })
+        let authority = Authority::<User, RotatingEd25519, _, _>::new()
+            .refresh_authorizer(jwt::authorize_refresh)
             .token_signer(Some(
                 TokenSigner::new()
                     .signing_key(secret_key.clone())
-                    .algorithm(Ed25519)
+                    .algorithm(RotatingEd25519)
+                    .access_token_name(jwt::ACCESS_TOKEN_COOKIE)
+                    .refresh_token_name(jwt::REFRESH_TOKEN_COOKIE)
+                    .refresh_token_lifetime(jwt::SESSION_LIFETIME)
                     .build()
                     .expect("Failed to generate TokenSigner"),
             ))
-            .verifying_key(public_key)
+            .verifying_key(public_keys.clone())
             .build()
             .expect("Failed to create Authority");
 
         App::new()
             .app_data(data.clone())
+            .wrap(from_fn(api_keys::authenticate_api_keys))
+            .wrap(from_fn(audit::audit_requests))
             .wrap(Logger::new("%a %t %r %s  %{Referer}i %Dms"))
             .service(handlers::login)
+            .service(handlers::login_totp)
+            .service(handlers::healthz)
+            .service(handlers::readyz)
             .use_jwt(
                 authority,
-                web::scope("")
-                    .service(handlers::index)
-                    .service(handlers::get_transactions)
-                    .service(handlers::get_schedule)
-                    .service(handlers::retry_failed_schedule)
-                    .service(handlers::get_buyer_by_wallet)
-                    .service(handlers::get_buyers)
-                    .service(handlers::upload_buyers_csv)
-                    .service(handlers::get_all_groups)
-                    .service(handlers::get_group_by_id)
-                    .use_state_guard(
-                        |user: User| async move {
-                            if user.is_superuser {
-                                Ok(())
-                            } else {
-                                Err(InternalError::new(
-                                    "You are not an Admin",
-                                    StatusCode::UNAUTHORIZED,
-                                ))
-                            }
-                        },
-                        web::scope("").service(handlers::index),
-                    ),
+                // `use_jwt` takes a plain scope, the session check wraps the one inside
+                web::scope("").service(
+                    web::scope("")
+                        .wrap(from_fn(jwt::check_session))
+                        .service(handlers::index)
+                        .service(handlers::get_metrics)
+                        .service(handlers::get_transactions)
+                        .service(handlers::export_transactions)
+                        .service(handlers::get_schedule)
+                        .service(handlers::export_schedules)
+                        .service(handlers::retry_failed_schedule)
+                        .service(handlers::resolve_interrupted_schedule)
+                        .service(handlers::export_buyers)
+                        .service(handlers::get_buyer_by_wallet)
+                        .service(handlers::get_buyer_vesting)
+                        .service(handlers::get_buyers)
+                        .service(handlers::upload_buyers)
+                        .service(handlers::create_buyer)
+                        .service(handlers::update_buyer)
+                        .service(handlers::delete_buyer)
+                        .service(handlers::get_buyer_uploads)
+                        .service(handlers::get_buyer_upload)
+                        .service(handlers::confirm_buyer_upload)
+                        .service(handlers::get_all_groups)
+                        .service(handlers::get_group_by_id)
+                        .service(handlers::get_group_versions)
+                        .service(handlers::get_group_stats)
+                        .service(handlers::get_stats)
+                        .service(handlers::get_runner_status)
+                        .service(handlers::stream_events)
+                        .service(handlers::create_group)
+                        .service(handlers::update_group)
+                        .service(handlers::delete_group)
+                        .service(handlers::pause_runner)
+                        .service(handlers::resume_runner)
+                        .service(handlers::get_webhooks)
+                        .service(handlers::create_webhook)
+                        .service(handlers::get_webhook_deliveries)
+                        .service(handlers::replay_webhook_delivery)
+                        .service(handlers::update_webhook)
+                        .service(handlers::delete_webhook)
+                        .service(handlers::get_audit_log)
+                        .service(handlers::get_users)
+                        .service(handlers::create_user)
+                        .service(handlers::update_user_email)
+                        .service(handlers::reset_user_password)
+                        .service(handlers::update_user_role)
+                        .service(handlers::deactivate_user)
+                        .service(handlers::activate_user)
+                        .service(handlers::delete_user)
+                        .service(handlers::change_own_password)
+                        .service(handlers::logout)
+                        .service(handlers::get_own_sessions)
+                        .service(handlers::revoke_own_session)
+                        .service(handlers::revoke_user_sessions)
+                        .service(handlers::get_own_totp)
+                        .service(handlers::start_totp_enrollment)
+                        .service(handlers::confirm_totp_enrollment)
+                        .service(handlers::regenerate_recovery_codes)
+                        .service(handlers::disable_own_totp)
+                        .service(handlers::enroll_user_totp)
+                        .service(handlers::reset_user_totp)
+                        .service(handlers::get_api_keys)
+                        .service(handlers::create_api_key)
+                        .service(handlers::revoke_api_key)
+                        .service(handlers::get_approvals)
+                        .service(handlers::get_approval)
+                        .service(handlers::approve_request)
+                        .service(handlers::reject_request)
+                        .service(handlers::get_buyer_adjustments)
+                        .service(handlers::create_transfer)
+                        .service(handlers::resolve_interrupted_transfer)
+                        .service(handlers::create_adjustment)
+                        .service(handlers::get_ledger)
+                        .service(handlers::get_invariants),
+                ),
             )
     })
     .bind(("127.0.0.1", 8080))?
@@ -0,0 +1,185 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use common::SplToken;
+use prometheus::{
+    Encoder, Gauge, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use solana_sdk::signer::Signer;
+
+use crate::state::AppState;
+
+/// Prometheus metrics of the distribution service.
+///
+/// Counters and histograms are updated where transfers happen, gauges are refreshed on every scrape.
+pub struct Metrics {
+    registry: Registry,
+    transfers_attempted: IntCounterVec,
+    transfers_succeeded: IntCounterVec,
+    transfers_failed: IntCounterVec,
+    transfer_duration: HistogramVec,
+    rpc_errors: IntCounterVec,
+    schedule_backlog: IntGaugeVec,
+    schedule_lag: Gauge,
+    retry_queue_depth: IntGauge,
+    treasury_balance: GaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("spl_giver".to_string()), None)
+            .context("Failed to create metrics registry")?;
+
+        let transfers_attempted = IntCounterVec::new(
+            Opts::new(
+                "transfers_attempted_total",
+                "Schedules picked up for transfer",
+            ),
+            &["group"],
+        )?;
+        let transfers_succeeded = IntCounterVec::new(
+            Opts::new("transfers_succeeded_total", "Successful token transfers"),
+            &["group"],
+        )?;
+        let transfers_failed = IntCounterVec::new(
+            Opts::new("transfers_failed_total", "Failed schedules by error class"),
+            &["group", "error_class"],
+        )?;
+        let transfer_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "transfer_duration_seconds",
+                "Time to send a schedule, including ATA creation and retries",
+            )
+            .buckets(vec![0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0]),
+            &["group", "outcome"],
+        )?;
+        let rpc_errors = IntCounterVec::new(
+            Opts::new("rpc_errors_total", "Failed Solana RPC calls"),
+            &["operation"],
+        )?;
+        let schedule_backlog = IntGaugeVec::new(
+            Opts::new(
+                "schedule_backlog",
+                "Unsent schedules with `scheduled_at` in the past",
+            ),
+            &["status"],
+        )?;
+        let schedule_lag = Gauge::new(
+            "schedule_lag_seconds",
+            "Age of the oldest overdue pending schedule",
+        )?;
+        let retry_queue_depth = IntGauge::new(
+            "retry_queue_depth",
+            "Database operations waiting in the retry queue",
+        )?;
+        let treasury_balance = GaugeVec::new(
+            Opts::new("treasury_balance_lamports", "Balance of the main wallet"),
+            &["asset"],
+        )?;
+
+        registry.register(Box::new(transfers_attempted.clone()))?;
+        registry.register(Box::new(transfers_succeeded.clone()))?;
+        registry.register(Box::new(transfers_failed.clone()))?;
+        registry.register(Box::new(transfer_duration.clone()))?;
+        registry.register(Box::new(rpc_errors.clone()))?;
+        registry.register(Box::new(schedule_backlog.clone()))?;
+        registry.register(Box::new(schedule_lag.clone()))?;
+        registry.register(Box::new(retry_queue_depth.clone()))?;
+        registry.register(Box::new(treasury_balance.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            transfers_attempted,
+            transfers_succeeded,
+            transfers_failed,
+            transfer_duration,
+            rpc_errors,
+            schedule_backlog,
+            schedule_lag,
+            retry_queue_depth,
+            treasury_balance,
+        })
+    }
+
+    pub fn transfer_attempted(&self, group_id: i64) {
+        self.transfers_attempted
+            .with_label_values(&[group_id.to_string().as_str()])
+            .inc();
+    }
+
+    pub fn transfer_succeeded(&self, group_id: i64) {
+        self.transfers_succeeded
+            .with_label_values(&[group_id.to_string().as_str()])
+            .inc();
+    }
+
+    /// Records how long sending took; `outcome` is "success" or "failed".
+    pub fn observe_transfer(&self, group_id: i64, outcome: &str, seconds: f64) {
+        self.transfer_duration
+            .with_label_values(&[group_id.to_string().as_str(), outcome])
+            .observe(seconds);
+    }
+
+    pub fn transfer_failed(&self, group_id: i64, error_class: &str) {
+        self.transfers_failed
+            .with_label_values(&[group_id.to_string().as_str(), error_class])
+            .inc();
+    }
+
+    pub fn rpc_error(&self, operation: &str) {
+        self.rpc_errors.with_label_values(&[operation]).inc();
+    }
+
+    /// Refreshes the gauges and encodes all metrics in the Prometheus text format.
+    pub async fn render(&self, app_state: &AppState) -> Result<String> {
+        let now = Utc::now().naive_utc();
+        let backlog = app_state.db.get_schedule_backlog(now).await?;
+        self.schedule_backlog.reset();
+        self.schedule_lag.set(0.0);
+        for entry in &backlog {
+            self.schedule_backlog
+                .with_label_values(&[entry.status.as_str()])
+                .set(entry.schedules);
+            if let ("pending", Some(oldest)) = (entry.status.as_str(), entry.oldest_scheduled_at) {
+                self.schedule_lag
+                    .set((now - oldest).num_milliseconds() as f64 / 1000.0);
+            }
+        }
+
+        let depth = app_state.retry_queue.ops.lock().await.len();
+        self.retry_queue_depth.set(depth as i64);
+
+        let spl_token = &app_state.spl_token;
+        match SplToken::get_token_account_balance(&spl_token.client, &spl_token.token_account).await
+        {
+            Ok(balance) => self
+                .treasury_balance
+                .with_label_values(&["token"])
+                .set(balance as f64),
+            Err(e) => {
+                log::error!("Failed to get treasury token balance: {:#}", e);
+                self.rpc_error("get_token_balance");
+            }
+        }
+        match spl_token
+            .client
+            .get_balance(&spl_token.main_wallet.pubkey())
+            .await
+        {
+            Ok(balance) => self
+                .treasury_balance
+                .with_label_values(&["sol"])
+                .set(balance as f64),
+            Err(e) => {
+                log::error!("Failed to get treasury SOL balance: {}", e);
+                self.rpc_error("get_balance");
+            }
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .context("Failed to encode metrics")?;
+        String::from_utf8(buffer).context("Metrics are not valid UTF-8")
+    }
+}
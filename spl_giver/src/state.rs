@@ -1,5 +1,12 @@
 use anyhow::{Context, Result};
-use common::{Buyer, Database, Group, SplToken, Transaction};
+use chrono::NaiveDateTime;
+use common::{ApprovalPolicy, Buyer, Database, Group, Schedule, SplToken, Transaction};
+
+use crate::client_ip::TrustedProxies;
+use crate::events::EventBus;
+use crate::login_guard::{LoginGuard, LoginPolicy};
+use crate::metrics::Metrics;
+use crate::webhooks::Webhooks;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use tokio::fs::OpenOptions;
@@ -10,6 +17,13 @@ pub struct AppState {
     pub spl_token: SplToken,
     pub db: Database,
     pub retry_queue: RetryQueue,
+    pub metrics: Metrics,
+    pub runner: Mutex<RunnerStatus>,
+    pub webhooks: Webhooks,
+    pub events: EventBus,
+    pub login_guard: LoginGuard,
+    pub approval_policy: ApprovalPolicy,
+    pub trusted_proxies: TrustedProxies,
 }
 impl AppState {
     pub async fn new<P: AsRef<Path>>(
@@ -18,38 +32,56 @@ impl AppState {
         wallet: &str,
         mint: &str,
         retry_queue_path: P,
+        login_policy: LoginPolicy,
+        approval_policy: ApprovalPolicy,
     ) -> Result<Self> {
         let spl_token_context = SplToken::new(client_url, wallet, mint).await?;
 
         let db = Database::new(database_url).await?;
         log::info!("Database initialized successfully!");
         let retry_queue = RetryQueue::load(retry_queue_path).await?;
+        let metrics = Metrics::new()?;
+        let webhooks = Webhooks::new()?;
 
         Ok(AppState {
             spl_token: spl_token_context,
             db,
             retry_queue,
+            metrics,
+            runner: Mutex::new(RunnerStatus::default()),
+            webhooks,
+            events: EventBus::new(),
+            login_guard: LoginGuard::new(login_policy),
+            approval_policy,
+            trusted_proxies: TrustedProxies::default(),
         })
     }
 
     pub async fn initialize_data_from_files(
         &self,
-        groups_yaml: &str,
-        buyers_csv: &str,
+        groups_file: &str,
+        buyers_file: &str,
     ) -> Result<()> {
-        let groups = Group::from_yaml_file(groups_yaml, self.spl_token.balance)
+        let groups = Group::from_file(groups_file, self.spl_token.balance)
             .await
-            .with_context(|| format!("Failed to load groups from `{}`", groups_yaml))?;
+            .with_context(|| format!("Failed to load groups from `{}`", groups_file))?;
 
-        let buyers = Buyer::load_from_csv(buyers_csv, &groups)
+        let buyers = Buyer::load_from_file(buyers_file, &groups)
             .await
-            .with_context(|| format!("Failed to load buyers from `{}`", buyers_csv))?;
+            .with_context(|| format!("Failed to load buyers from `{}`", buyers_file))?;
 
         for group in &groups {
-            self.db
+            let inserted = self
+                .db
                 .save_group(group)
                 .await
                 .with_context(|| format!("Failed to save group id={} to database", group.id))?;
+            if inserted {
+                self.db
+                    .save_group_version(group, "create", None)
+                    .await
+                    .with_context(|| format!("Failed to save version of group id={}", group.id))?;
+            }
         }
 
         for buyer in &buyers {
@@ -62,10 +94,37 @@ impl AppState {
     }
 }
 
+/// State published by the schedule runner and its watchdog.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunnerStatus {
+    /// Start of the last tick.
+    pub last_tick: Option<NaiveDateTime>,
+    /// Last time the runner showed progress: start of a tick or a processed schedule.
+    pub last_heartbeat: Option<NaiveDateTime>,
+    /// Schedules processed during the last finished tick.
+    pub last_tick_processed: usize,
+    pub in_flight: Option<InFlightTransfer>,
+    pub next_wake_up: Option<NaiveDateTime>,
+    pub paused: bool,
+    /// How many times the watchdog restarted the runner.
+    pub restarts: u32,
+    pub last_error: Option<String>,
+}
+
+/// Schedule or manual transfer the runner is sending right now.
+#[derive(Debug, Clone, Serialize)]
+pub struct InFlightTransfer {
+    pub schedule_id: Option<i64>,
+    /// Set for a manual transfer, see `adjustments`.
+    pub adjustment_id: Option<i64>,
+    pub started_at: NaiveDateTime,
+}
+
 /// An operation that must be applied to the database.
 #[derive(Serialize, Deserialize, Clone)]
 pub enum PendingOp {
     SaveTransaction(Transaction),
+    /// Written by older versions, replaced by `RecordUnlock`
     UpdateBuyer {
         wallet: String,
         received_spl: u64,
@@ -77,6 +136,20 @@ pub enum PendingOp {
         status: String,
         error_message: Option<String>,
     },
+    RecordUnlock(Schedule),
+    ApplyAdjustment {
+        adjustment_id: i64,
+        transaction_id: Option<i64>,
+    },
+    FailAdjustment {
+        adjustment_id: i64,
+        transaction_id: Option<i64>,
+        error_message: String,
+    },
+    InterruptAdjustment {
+        adjustment_id: i64,
+        error_message: String,
+    },
 }
 
 /// A persistent queue of operations to retry on DB failure
@@ -119,10 +192,10 @@ impl RetryQueue {
         })
     }
 
-    /// Asynchronously save the retry queue to disk atomically.
-    pub async fn save(&self) -> Result<()> {
-        let guard = self.ops.lock().await;
-        let data = serde_json::to_vec_pretty(&*guard).context("Failed to serialize retry queue")?;
+    /// Asynchronously save the retry queue to disk. The caller holds the `ops` lock and
+    /// passes its contents, so writes never interleave and the lock is not taken twice.
+    async fn save_locked(&self, ops: &[PendingOp]) -> Result<()> {
+        let data = serde_json::to_vec_pretty(ops).context("Failed to serialize retry queue")?;
 
         let mut file = OpenOptions::new()
             .create(true)
@@ -144,7 +217,9 @@ impl RetryQueue {
     pub async fn push_and_persist(&self, op: PendingOp) -> Result<()> {
         let mut guard = self.ops.lock().await;
         guard.push(op);
-        self.save().await.context("Failed to save retry queue")
+        self.save_locked(&guard)
+            .await
+            .context("Failed to save retry queue")
     }
 
     /// Attempt all pending operations, removing those that succeed.
@@ -174,6 +249,23 @@ impl RetryQueue {
                     .update_schedule_status(*schedule_id, status, error_message.clone())
                     .await
                     .map(|_| ()),
+                PendingOp::RecordUnlock(schedule) => db.record_unlock(schedule).await.map(|_| ()),
+                PendingOp::ApplyAdjustment {
+                    adjustment_id,
+                    transaction_id,
+                } => db.apply_adjustment(*adjustment_id, *transaction_id).await,
+                PendingOp::FailAdjustment {
+                    adjustment_id,
+                    transaction_id,
+                    error_message,
+                } => {
+                    db.fail_adjustment(*adjustment_id, *transaction_id, error_message)
+                        .await
+                }
+                PendingOp::InterruptAdjustment {
+                    adjustment_id,
+                    error_message,
+                } => db.interrupt_adjustment(*adjustment_id, error_message).await,
             };
 
             if let Err(e) = outcome {
@@ -182,13 +274,51 @@ impl RetryQueue {
             }
         }
 
-        // Save only the remaining failures back
-        {
-            let mut guard = self.ops.lock().await;
-            *guard = remaining;
-        }
-        self.save()
+        // Save only the remaining failures back, ahead of the operations queued meanwhile
+        let mut guard = self.ops.lock().await;
+        remaining.append(&mut guard);
+        *guard = remaining;
+        self.save_locked(&guard)
             .await
             .context("Failed to save updated retry queue")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update_schedule(schedule_id: i64) -> PendingOp {
+        PendingOp::UpdateSchedule {
+            schedule_id,
+            status: "failed".to_string(),
+            error_message: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn push_and_persist_saves_the_queue() {
+        let path = std::env::temp_dir().join(format!("retry_queue_{}.json", std::process::id()));
+        let queue = RetryQueue::load(&path).await.unwrap();
+
+        let push = async {
+            queue.push_and_persist(update_schedule(1)).await.unwrap();
+            queue.push_and_persist(update_schedule(2)).await.unwrap();
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(5), push)
+            .await
+            .expect("push_and_persist must not wait for its own lock");
+
+        let reloaded = RetryQueue::load(&path).await.unwrap();
+        let ops = reloaded.ops.lock().await;
+        let ids: Vec<i64> = ops
+            .iter()
+            .map(|op| match op {
+                PendingOp::UpdateSchedule { schedule_id, .. } => *schedule_id,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(ids, [1, 2]);
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use actix_web::web;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use common::{SplToken, WebhookDelivery, WebhookEvent};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::time::{Duration, sleep};
+
+use crate::state::AppState;
+
+/// How often the worker looks for due deliveries.
+const WORKER_INTERVAL: Duration = Duration::from_secs(5);
+/// Deliveries sent per worker tick.
+const WORKER_BATCH: u32 = 50;
+/// A delivery is marked failed after this many attempts.
+const MAX_ATTEMPTS: i32 = 10;
+/// Delay before the second attempt, doubled after every failed attempt.
+const FIRST_RETRY_SECONDS: i64 = 30;
+const MAX_RETRY_SECONDS: i64 = 6 * 60 * 60;
+
+/// HTTP client and event state shared by webhook deliveries.
+pub struct Webhooks {
+    client: reqwest::Client,
+    /// Set while the treasury can't cover unsent schedules, so `treasury_low` is sent once per drop.
+    treasury_low: AtomicBool,
+}
+
+impl Webhooks {
+    pub fn new() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .context("Failed to create webhook HTTP client")?;
+        Ok(Webhooks {
+            client,
+            treasury_low: AtomicBool::new(false),
+        })
+    }
+}
+
+/// Queues `event` for every active webhook subscribed to it. Errors are only logged,
+/// a webhook problem must never stop a distribution.
+pub async fn notify(app_state: &AppState, event: WebhookEvent, data: serde_json::Value) {
+    let webhooks = match app_state.db.get_all_webhooks().await {
+        Ok(webhooks) => webhooks,
+        Err(e) => {
+            log::error!("Failed to load webhooks for `{}`: {:#}", event.as_str(), e);
+            return;
+        }
+    };
+
+    let payload = data.to_string();
+    let now = Utc::now().naive_utc();
+    for webhook in webhooks.iter().filter(|w| w.active && w.subscribed(event)) {
+        if let Err(e) = app_state
+            .db
+            .save_webhook_delivery(webhook.id, event.as_str(), &payload, now)
+            .await
+        {
+            log::error!(
+                "Failed to queue `{}` for webhook {}: {:#}",
+                event.as_str(),
+                webhook.id,
+                e
+            );
+        }
+    }
+}
+
+/// Sends `treasury_low` when the treasury balance drops below the sum of unsent schedules.
+pub async fn check_treasury(app_state: &AppState) {
+    let spl_token = &app_state.spl_token;
+    let balance = match SplToken::get_token_account_balance(
+        &spl_token.client,
+        &spl_token.token_account,
+    )
+    .await
+    {
+        Ok(balance) => balance,
+        Err(e) => {
+            log::error!("Failed to get treasury balance: {:#}", e);
+            app_state.metrics.rpc_error("get_token_balance");
+            return;
+        }
+    };
+    let unsent = match app_state.db.get_unsent_schedules_amount().await {
+        Ok(unsent) => unsent,
+        Err(e) => {
+            log::error!("Failed to get unsent schedules amount: {:#}", e);
+            return;
+        }
+    };
+
+    let low = balance < unsent;
+    let was_low = app_state.webhooks.treasury_low.swap(low, Ordering::Relaxed);
+    if low && !was_low {
+        log::warn!(
+            "Treasury balance {} is lower than unsent schedules {}",
+            balance,
+            unsent
+        );
+        notify(
+            app_state,
+            WebhookEvent::TreasuryLow,
+            json!({
+                "balance_lamports": balance,
+                "unsent_lamports": unsent,
+                "shortfall_lamports": unsent - balance,
+            }),
+        )
+        .await;
+    }
+}
+
+/// Sends due deliveries forever.
+pub async fn start_webhook_worker(app_state: web::Data<AppState>) {
+    loop {
+        let now = Utc::now().naive_utc();
+        match app_state
+            .db
+            .get_due_webhook_deliveries(now, WORKER_BATCH)
+            .await
+        {
+            Ok(deliveries) => {
+                for delivery in deliveries {
+                    deliver(&app_state, delivery).await;
+                }
+            }
+            Err(e) => log::error!("Failed to get due webhook deliveries: {:#}", e),
+        }
+        sleep(WORKER_INTERVAL).await;
+    }
+}
+
+async fn deliver(app_state: &AppState, mut delivery: WebhookDelivery) {
+    let webhook = match app_state.db.get_webhook(delivery.webhook_id).await {
+        Ok(Some(webhook)) => webhook,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("Failed to get webhook {}: {:#}", delivery.webhook_id, e);
+            return;
+        }
+    };
+
+    let data: serde_json::Value = serde_json::from_str(&delivery.payload).unwrap_or_default();
+    let body = json!({
+        "id": delivery.id,
+        "event": delivery.event_type,
+        "created_at": delivery.created_at,
+        "data": data,
+    })
+    .to_string();
+    let timestamp = Utc::now().timestamp().to_string();
+
+    let result = app_state
+        .webhooks
+        .client
+        .post(&webhook.url)
+        .header("Content-Type", "application/json")
+        .header("X-Webhook-Event", &delivery.event_type)
+        .header("X-Webhook-Delivery", delivery.id.to_string())
+        .header("X-Webhook-Timestamp", &timestamp)
+        .header(
+            "X-Webhook-Signature",
+            format!("sha256={}", sign(&webhook.secret, &timestamp, &body)),
+        )
+        .body(body)
+        .send()
+        .await;
+
+    delivery.attempts += 1;
+    let now = Utc::now().naive_utc();
+    match result {
+        Ok(response) if response.status().is_success() => {
+            delivery.status = "success".to_string();
+            delivery.last_status_code = Some(response.status().as_u16() as i32);
+            delivery.last_error = None;
+            delivery.delivered_at = Some(now);
+        }
+        failed => {
+            let (code, error) = match failed {
+                Ok(response) => (
+                    Some(response.status().as_u16() as i32),
+                    format!("Webhook responded with {}", response.status()),
+                ),
+                Err(e) => (None, e.to_string()),
+            };
+            log::warn!(
+                "Webhook delivery {} to {} failed (attempt {}): {}",
+                delivery.id,
+                webhook.url,
+                delivery.attempts,
+                error
+            );
+            delivery.last_status_code = code;
+            delivery.last_error = Some(error);
+            if delivery.attempts >= MAX_ATTEMPTS {
+                delivery.status = "failed".to_string();
+            } else {
+                delivery.next_attempt_at =
+                    now + chrono::Duration::seconds(retry_delay(delivery.attempts));
+            }
+        }
+    }
+
+    if let Err(e) = app_state.db.update_webhook_delivery(&delivery).await {
+        log::error!("Failed to save webhook delivery {}: {:#}", delivery.id, e);
+    }
+}
+
+fn retry_delay(attempts: i32) -> i64 {
+    let exponent = (attempts - 1).clamp(0, 20) as u32;
+    (FIRST_RETRY_SECONDS * 2_i64.pow(exponent)).min(MAX_RETRY_SECONDS)
+}
+
+/// HMAC-SHA256 of `{timestamp}.{body}`, hex encoded.
+fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}